@@ -0,0 +1,487 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{BlockRef, Function};
+
+///
+/// Successors and predecessors of every block of a function, as given by the targets of its terminators
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph{
+    entry: BlockRef,
+    blocks: Vec<BlockRef>,
+    successors: HashMap<BlockRef, Vec<BlockRef>>,
+    predecessors: HashMap<BlockRef, Vec<BlockRef>>,
+}
+
+impl ControlFlowGraph{
+    pub fn new(function: &Function) -> Self{
+        let mut successors = HashMap::with_capacity(function.blocks.len());
+        let mut predecessors: HashMap<BlockRef, Vec<BlockRef>> = function.blocks.iter().map(|x| (x.label, Vec::new())).collect();
+
+        for block in &function.blocks{
+            let mut targets = Vec::new();
+            for instruction in &block.instructions{
+                for call in instruction.operation().targets(){
+                    if !targets.contains(&call.block){
+                        targets.push(call.block);
+                    }
+                }
+            }
+
+            for target in &targets{
+                let predecessors = predecessors.entry(*target).or_default();
+                if !predecessors.contains(&block.label){
+                    predecessors.push(block.label);
+                }
+            }
+            successors.insert(block.label, targets);
+        }
+
+        Self{
+            entry: function.entry,
+            blocks: function.blocks.iter().map(|x| x.label).collect(),
+            successors,
+            predecessors
+        }
+    }
+
+    pub fn entry(&self) -> BlockRef{
+        self.entry
+    }
+
+    ///
+    /// Every block of the function in layout order, including unreachable ones
+    pub fn blocks(&self) -> &[BlockRef]{
+        &self.blocks
+    }
+
+    pub fn successors(&self, block: BlockRef) -> &[BlockRef]{
+        self.successors.get(&block).map(|x| &x[..]).unwrap_or_default()
+    }
+
+    pub fn predecessors(&self, block: BlockRef) -> &[BlockRef]{
+        self.predecessors.get(&block).map(|x| &x[..]).unwrap_or_default()
+    }
+
+    ///
+    /// Blocks reachable from the entry, each block comes before its successors except along back edges
+    pub fn reverse_postorder(&self) -> Vec<BlockRef>{
+        let mut postorder = Vec::with_capacity(self.blocks.len());
+        let mut visited = BTreeSet::new();
+
+        // Iterative depth first search, the index is the next successor to visit
+        let mut stack = vec![(self.entry, 0)];
+        visited.insert(self.entry);
+        while let Some((block, index)) = stack.last_mut(){
+            let block = *block;
+            match self.successors(block).get(*index) {
+                Some(successor) => {
+                    *index += 1;
+                    if visited.insert(*successor){
+                        stack.push((*successor, 0));
+                    }
+                },
+                None => {
+                    postorder.push(block);
+                    stack.pop();
+                }
+            }
+        }
+
+        postorder.reverse();
+        postorder
+    }
+}
+
+///
+/// Immediate dominators of the reachable blocks, computed with the Cooper, Harvey and Kennedy algorithm
+#[derive(Clone, Debug)]
+pub struct DominatorTree{
+    entry: BlockRef,
+    reverse_postorder: Vec<BlockRef>,
+    order: HashMap<BlockRef, usize>,
+    idom: HashMap<BlockRef, BlockRef>,
+    children: HashMap<BlockRef, Vec<BlockRef>>,
+}
+
+impl DominatorTree{
+    pub fn new(cfg: &ControlFlowGraph) -> Self{
+        let reverse_postorder = cfg.reverse_postorder();
+        let order = reverse_postorder.iter().enumerate().map(|(i, x)| (*x, i)).collect::<HashMap<_, _>>();
+
+        let entry = cfg.entry();
+        let mut idom = HashMap::with_capacity(reverse_postorder.len());
+        idom.insert(entry, entry);
+
+        let intersect = |idom: &HashMap<BlockRef, BlockRef>, mut a: BlockRef, mut b: BlockRef| {
+            while a != b{
+                while order[&a] > order[&b]{
+                    a = idom[&a];
+                }
+                while order[&b] > order[&a]{
+                    b = idom[&b];
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed{
+            changed = false;
+            for block in reverse_postorder.iter().skip(1){
+                // Only predecessors that are reachable and already processed take part
+                let mut new_idom = None;
+                for predecessor in cfg.predecessors(*block){
+                    if !idom.contains_key(predecessor){
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        Some(x) => intersect(&idom, *predecessor, x),
+                        None => *predecessor,
+                    });
+                }
+
+                let new_idom = new_idom.expect("reachable blocks have a processed predecessor");
+                if idom.get(block) != Some(&new_idom){
+                    idom.insert(*block, new_idom);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children: HashMap<BlockRef, Vec<BlockRef>> = HashMap::new();
+        for block in reverse_postorder.iter().skip(1){
+            children.entry(idom[block]).or_default().push(*block);
+        }
+
+        Self{
+            entry,
+            reverse_postorder,
+            order,
+            idom,
+            children
+        }
+    }
+
+    ///
+    /// The closest strict dominator of a block, `None` for the entry and unreachable blocks
+    pub fn immediate_dominator(&self, block: BlockRef) -> Option<BlockRef>{
+        if block == self.entry{
+            return None;
+        }
+        self.idom.get(&block).copied()
+    }
+
+    ///
+    /// Whether every path from the entry to `b` passes through `a`, a block dominates itself
+    pub fn dominates(&self, a: BlockRef, b: BlockRef) -> bool{
+        if !self.is_reachable(a) || !self.is_reachable(b){
+            return false;
+        }
+
+        let mut current = b;
+        loop {
+            if current == a{
+                return true;
+            }
+            // Dominators come earlier in reverse postorder, so walking up can stop once we pass `a`
+            if current == self.entry || self.order[&current] < self.order[&a]{
+                return false;
+            }
+            current = self.idom[&current];
+        }
+    }
+
+    pub fn strictly_dominates(&self, a: BlockRef, b: BlockRef) -> bool{
+        a != b && self.dominates(a, b)
+    }
+
+    ///
+    /// Blocks immediately dominated by `block`
+    pub fn children(&self, block: BlockRef) -> &[BlockRef]{
+        self.children.get(&block).map(|x| &x[..]).unwrap_or_default()
+    }
+
+    pub fn is_reachable(&self, block: BlockRef) -> bool{
+        self.order.contains_key(&block)
+    }
+
+    pub fn reverse_postorder(&self) -> &[BlockRef]{
+        &self.reverse_postorder
+    }
+}
+
+///
+/// Blocks where the dominance of each block ends, the places a definition in the block needs a merge
+pub fn dominance_frontiers(cfg: &ControlFlowGraph, domtree: &DominatorTree) -> HashMap<BlockRef, BTreeSet<BlockRef>>{
+    let mut frontiers: HashMap<BlockRef, BTreeSet<BlockRef>> = domtree.reverse_postorder().iter().map(|x| (*x, BTreeSet::new())).collect();
+
+    for block in domtree.reverse_postorder(){
+        let predecessors = cfg.predecessors(*block).iter().filter(|x| domtree.is_reachable(**x)).collect::<Vec<_>>();
+        if predecessors.len() < 2{
+            continue;
+        }
+
+        let idom = domtree.immediate_dominator(*block);
+        for predecessor in predecessors{
+            let mut runner = Some(*predecessor);
+            while let Some(current) = runner.filter(|x| Some(*x) != idom){
+                frontiers.entry(current).or_default().insert(*block);
+                runner = domtree.immediate_dominator(current);
+            }
+        }
+    }
+
+    frontiers
+}
+
+///
+/// A natural loop, all loops sharing a header are merged into one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loop{
+    pub header: BlockRef,
+
+    ///
+    /// Blocks branching back to the header
+    pub latches: Vec<BlockRef>,
+
+    ///
+    /// Every block of the loop, including the header and the blocks of nested loops
+    pub blocks: BTreeSet<BlockRef>,
+
+    ///
+    /// Index of the innermost loop containing this one
+    pub parent: Option<usize>,
+}
+
+///
+/// Natural loops of a function, found through back edges to a dominating header.
+/// Irreducible cycles have no such header and are not reported
+#[derive(Clone, Debug)]
+pub struct LoopInfo{
+    loops: Vec<Loop>,
+}
+
+impl LoopInfo{
+    pub fn new(cfg: &ControlFlowGraph, domtree: &DominatorTree) -> Self{
+        let mut loops: Vec<Loop> = Vec::new();
+
+        for header in domtree.reverse_postorder(){
+            let latches = cfg.predecessors(*header).iter()
+                .filter(|x| domtree.dominates(*header, **x))
+                .copied()
+                .collect::<Vec<_>>();
+            if latches.is_empty(){
+                continue;
+            }
+
+            let mut blocks = BTreeSet::from([*header]);
+            let mut stack = latches.clone();
+            while let Some(block) = stack.pop(){
+                if blocks.insert(block){
+                    stack.extend(cfg.predecessors(block).iter().filter(|x| domtree.is_reachable(**x)));
+                }
+            }
+
+            loops.push(Loop{
+                header: *header,
+                latches,
+                blocks,
+                parent: None
+            });
+        }
+
+        // The parent is the smallest other loop containing the header
+        for i in 0..loops.len(){
+            loops[i].parent = (0..loops.len())
+                .filter(|j| *j != i && loops[*j].blocks.contains(&loops[i].header) && loops[*j].blocks.len() > loops[i].blocks.len())
+                .min_by_key(|j| loops[*j].blocks.len());
+        }
+
+        Self{
+            loops
+        }
+    }
+
+    ///
+    /// Loops ordered by their header in reverse postorder, so outer loops come before the loops they contain
+    pub fn loops(&self) -> &[Loop]{
+        &self.loops
+    }
+
+    ///
+    /// Index of the innermost loop containing the block
+    pub fn innermost_loop(&self, block: BlockRef) -> Option<usize>{
+        (0..self.loops.len())
+            .filter(|x| self.loops[*x].blocks.contains(&block))
+            .min_by_key(|x| self.loops[*x].blocks.len())
+    }
+
+    ///
+    /// Number of loops containing the block, 0 outside of any loop
+    pub fn depth(&self, block: BlockRef) -> usize{
+        self.loops.iter().filter(|x| x.blocks.contains(&block)).count()
+    }
+
+    pub fn is_header(&self, block: BlockRef) -> bool{
+        self.loops.iter().any(|x| x.header == block)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::parse_module;
+
+    fn function(source: &str) -> Function{
+        parse_module(source).unwrap().functions.remove(0)
+    }
+
+    fn blocks<const N: usize>(labels: [u32; N]) -> Vec<BlockRef>{
+        labels.into_iter().map(BlockRef).collect()
+    }
+
+    fn frontier<const N: usize>(labels: [u32; N]) -> BTreeSet<BlockRef>{
+        labels.into_iter().map(BlockRef).collect()
+    }
+
+    const DIAMOND: &str = "
+module 0
+function fn0(i32) -> (i32) {
+block0(%1: i32):
+    br_eq %1, block1, block2
+block1:
+    %2 = const.i32 1
+    br block3(%2)
+block2:
+    %3 = const.i32 2
+    br block3(%3)
+block3(%4: i32):
+    return %4
+}
+";
+
+    // An outer loop with header block1 and latch block4, containing an inner loop with header block2 and latch block3.
+    // block6 is unreachable and branches into the outer loop
+    const NESTED_LOOPS: &str = "
+module 0
+function fn0(i32) {
+block0(%1: i32):
+    br block1
+block1:
+    br_eq %1, block2, block5
+block2:
+    br_eq %1, block3, block4
+block3:
+    br block2
+block4:
+    br block1
+block5:
+    return
+block6:
+    br block1
+}
+";
+
+    #[test]
+    fn diamond(){
+        let cfg = ControlFlowGraph::new(&function(DIAMOND));
+        assert_eq!(cfg.successors(BlockRef(0)), blocks([1, 2]));
+        assert_eq!(cfg.predecessors(BlockRef(3)), blocks([1, 2]));
+        assert_eq!(cfg.reverse_postorder(), blocks([0, 2, 1, 3]));
+
+        let domtree = DominatorTree::new(&cfg);
+        assert_eq!(domtree.immediate_dominator(BlockRef(0)), None);
+        assert_eq!(domtree.immediate_dominator(BlockRef(3)), Some(BlockRef(0)));
+        assert!(domtree.dominates(BlockRef(0), BlockRef(3)));
+        assert!(!domtree.dominates(BlockRef(1), BlockRef(3)));
+        assert!(domtree.dominates(BlockRef(3), BlockRef(3)));
+        assert!(!domtree.strictly_dominates(BlockRef(3), BlockRef(3)));
+        assert_eq!(domtree.children(BlockRef(0)), blocks([2, 1, 3]));
+
+        let frontiers = dominance_frontiers(&cfg, &domtree);
+        assert_eq!(frontiers[&BlockRef(0)], frontier([]));
+        assert_eq!(frontiers[&BlockRef(1)], frontier([3]));
+        assert_eq!(frontiers[&BlockRef(2)], frontier([3]));
+        assert_eq!(frontiers[&BlockRef(3)], frontier([]));
+
+        assert!(LoopInfo::new(&cfg, &domtree).loops().is_empty());
+    }
+
+    #[test]
+    fn nested_loops(){
+        let cfg = ControlFlowGraph::new(&function(NESTED_LOOPS));
+        assert_eq!(cfg.blocks(), blocks([0, 1, 2, 3, 4, 5, 6]));
+        assert_eq!(cfg.predecessors(BlockRef(1)), blocks([0, 4, 6]));
+        assert_eq!(cfg.reverse_postorder(), blocks([0, 1, 5, 2, 4, 3]));
+
+        let domtree = DominatorTree::new(&cfg);
+        assert!(!domtree.is_reachable(BlockRef(6)));
+        assert!(!domtree.dominates(BlockRef(6), BlockRef(1)));
+        assert_eq!(domtree.immediate_dominator(BlockRef(1)), Some(BlockRef(0)));
+        assert_eq!(domtree.immediate_dominator(BlockRef(4)), Some(BlockRef(2)));
+        assert_eq!(domtree.immediate_dominator(BlockRef(5)), Some(BlockRef(1)));
+        assert_eq!(domtree.immediate_dominator(BlockRef(6)), None);
+        assert_eq!(domtree.children(BlockRef(1)), blocks([5, 2]));
+
+        // Loop headers are in their own frontier, through the back edge
+        let frontiers = dominance_frontiers(&cfg, &domtree);
+        assert_eq!(frontiers[&BlockRef(0)], frontier([]));
+        assert_eq!(frontiers[&BlockRef(1)], frontier([1]));
+        assert_eq!(frontiers[&BlockRef(2)], frontier([1, 2]));
+        assert_eq!(frontiers[&BlockRef(3)], frontier([2]));
+        assert_eq!(frontiers[&BlockRef(4)], frontier([1]));
+        assert_eq!(frontiers[&BlockRef(5)], frontier([]));
+        assert!(!frontiers.contains_key(&BlockRef(6)));
+
+        let loops = LoopInfo::new(&cfg, &domtree);
+        assert_eq!(loops.loops(), [
+            Loop{
+                header: BlockRef(1),
+                latches: blocks([4]),
+                blocks: frontier([1, 2, 3, 4]),
+                parent: None
+            },
+            Loop{
+                header: BlockRef(2),
+                latches: blocks([3]),
+                blocks: frontier([2, 3]),
+                parent: Some(0)
+            },
+        ]);
+        assert!(loops.is_header(BlockRef(2)));
+        assert!(!loops.is_header(BlockRef(3)));
+        assert_eq!(loops.innermost_loop(BlockRef(3)), Some(1));
+        assert_eq!(loops.innermost_loop(BlockRef(4)), Some(0));
+        assert_eq!(loops.innermost_loop(BlockRef(5)), None);
+        assert_eq!([0, 1, 2, 3, 4, 5].map(|x| loops.depth(BlockRef(x))), [0, 1, 2, 2, 1, 0]);
+    }
+
+    #[test]
+    fn self_loop(){
+        let cfg = ControlFlowGraph::new(&function("
+module 0
+function fn0(i32) {
+block0(%1: i32):
+    br block1(%1)
+block1(%2: i32):
+    br_gt %2, block1(%2), block2
+block2:
+    return
+}
+"));
+        assert_eq!(cfg.successors(BlockRef(1)), blocks([1, 2]));
+        assert_eq!(cfg.predecessors(BlockRef(1)), blocks([0, 1]));
+
+        let domtree = DominatorTree::new(&cfg);
+        assert_eq!(domtree.immediate_dominator(BlockRef(1)), Some(BlockRef(0)));
+        assert_eq!(dominance_frontiers(&cfg, &domtree)[&BlockRef(1)], frontier([1]));
+
+        let loops = LoopInfo::new(&cfg, &domtree);
+        assert_eq!(loops.loops(), [Loop{
+            header: BlockRef(1),
+            latches: blocks([1]),
+            blocks: frontier([1]),
+            parent: None
+        }]);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt::Display;
+
+use crate::{FunctionRef, Module, ModuleRef, Scalar, Type};
+
+///
+/// Consumer of corrosion-base modules, e.g. a JIT, an interpreter or an ahead of time compiler
+pub trait Backend{
+    ///
+    /// Load a module, after which its functions can be resolved and invoked
+    fn load_module(&mut self, module: Module) -> Result<(), BackendError>;
+
+    ///
+    /// Find a function of a loaded module by name
+    fn resolve_function(&self, module: ModuleRef, name: &str) -> Result<FunctionRef, BackendError>;
+
+    fn signature(&self, module: ModuleRef, function: FunctionRef) -> Result<Signature, BackendError>;
+
+    ///
+    /// Call a function of a loaded module, the arguments must match its inputs
+    fn invoke(&mut self, module: ModuleRef, function: FunctionRef, args: &[Scalar]) -> Result<Vec<Scalar>, BackendError>;
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Signature{
+    pub inputs: Vec<Type>,
+    pub outputs: Vec<Type>,
+}
+
+impl Signature{
+    ///
+    /// Check that the arguments match the inputs of the signature
+    pub fn check_args(&self, args: &[Scalar]) -> Result<(), BackendError>{
+        if args.len() != self.inputs.len() || self.inputs.iter().zip(args).any(|(a, b)| *a != b.type_()){
+            return Err(BackendError::InvalidArguments {
+                expected: self.inputs.clone(),
+                found: args.iter().map(|x| x.type_()).collect()
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum BackendError{
+    UndefinedModule(ModuleRef),
+    UndefinedFunction(ModuleRef, FunctionRef),
+    UnknownFunction(ModuleRef, String),
+    DuplicateDefinition(ModuleRef, FunctionRef),
+
+    ///
+    /// Another function of the module already has the name
+    DuplicateName(ModuleRef, String),
+    InvalidArguments{
+        expected: Vec<Type>,
+        found: Vec<Type>
+    },
+
+    ///
+    /// The module could not be compiled or loaded by the backend
+    Compile(Box<dyn std::error::Error + Send + Sync>),
+
+    ///
+    /// Execution of a function failed
+    Trap(Box<dyn std::error::Error + Send + Sync>),
+    Unsupported(String),
+}
+
+impl Display for BackendError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::UndefinedModule(module) => write!(f, "undefined module {:?}", module),
+            BackendError::UndefinedFunction(module, function) => write!(f, "undefined function {:?} in {:?}", function, module),
+            BackendError::UnknownFunction(module, name) => write!(f, "no function named '{}' in {:?}", name, module),
+            BackendError::DuplicateDefinition(module, function) => write!(f, "duplicate definition of {:?} in {:?}", function, module),
+            BackendError::DuplicateName(module, name) => write!(f, "duplicate function name '{}' in {:?}", name, module),
+            BackendError::InvalidArguments { expected, found } => write!(f, "expected arguments {:?}, found {:?}", expected, found),
+            BackendError::Compile(err) => write!(f, "compilation failed: {}", err),
+            BackendError::Trap(err) => write!(f, "trap: {}", err),
+            BackendError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for BackendError{}
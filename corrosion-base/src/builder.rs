@@ -1,9 +1,15 @@
-use crate::{FunctionRef, Type, Function, Module, ModuleRef, Block, BlockRef, ImmediateRef, Instruction, Operation, VariableRef};
+use crate::{Inline, FunctionRef, Type, Function, Module, ModuleRef, Block, BlockRef, BlockCall, ImmediateRef, Instruction, Operation, VariableRef};
 
 pub struct ModuleBuilder{
     functions: Vec<Function>,
 }
 
+impl Default for ModuleBuilder{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ModuleBuilder{
 
     pub fn new() -> Self{
@@ -20,7 +26,7 @@ impl ModuleBuilder{
         id
     }
 
-    pub fn funtion_builder<'a>(&'a mut self, function: FunctionRef) -> FunctionBuilder<'_>{
+    pub fn funtion_builder(&mut self, function: FunctionRef) -> FunctionBuilder<'_>{
         FunctionBuilder{
             function,
             module: self,
@@ -75,13 +81,17 @@ impl<'a> FunctionBuilder<'a>{
         id
     }
 
+    pub fn set_inline(&mut self, inline: Inline){
+        self.function_mut().inline = inline;
+    }
+
     pub fn create_block(&mut self) -> BlockRef{
         let id = BlockRef((self.function().blocks.len()+1) as u32);
         self.function_mut().blocks.push(Block::new(id));
         id
     }
 
-    pub fn block_builder(&'a mut self, block: BlockRef) -> BlockBuilder<'_>{
+    pub fn block_builder(&mut self, block: BlockRef) -> BlockBuilder<'_, 'a>{
         BlockBuilder{
             fb: self,
             cur_block: block
@@ -90,12 +100,12 @@ impl<'a> FunctionBuilder<'a>{
 }
 
 
-pub struct BlockBuilder<'a>{
-    fb: &'a mut FunctionBuilder<'a>,
+pub struct BlockBuilder<'a, 'b>{
+    fb: &'a mut FunctionBuilder<'b>,
     cur_block: BlockRef
 }
 
-impl<'a> BlockBuilder<'a>{
+impl<'a, 'b> BlockBuilder<'a, 'b>{
 
     fn new_immediate(&mut self) -> ImmediateRef{
         self.fb.immediate_counter += 1;
@@ -174,7 +184,7 @@ impl<'a> BlockBuilder<'a>{
     }
 
     pub fn add_values(&mut self, a: Value, b: Value) -> Value{
-        assert!(a.1 == b.1 && !a.1.is_pointer(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
+        assert!(a.1 == b.1 && a.1.is_numeric(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
         let output = self.new_immediate();
         self.block_mut().instructions.push(Instruction{
             operation: Operation::Add(a.0, b.0, a.1),
@@ -185,7 +195,7 @@ impl<'a> BlockBuilder<'a>{
     }
 
     pub fn sub_values(&mut self, a: Value, b: Value) -> Value{
-        assert!(a.1 == b.1 && !a.1.is_pointer(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
+        assert!(a.1 == b.1 && a.1.is_numeric(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
         let output = self.new_immediate();
         self.block_mut().instructions.push(Instruction{
             operation: Operation::Sub(a.0, b.0, a.1),
@@ -196,7 +206,7 @@ impl<'a> BlockBuilder<'a>{
     }
 
     pub fn mul_values(&mut self, a: Value, b: Value) -> Value{
-        assert!(a.1 == b.1 && !a.1.is_pointer(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
+        assert!(a.1 == b.1 && a.1.is_numeric(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
         let output = self.new_immediate();
         self.block_mut().instructions.push(Instruction{
             operation: Operation::Mul(a.0, b.0, a.1),
@@ -207,7 +217,7 @@ impl<'a> BlockBuilder<'a>{
     }
 
     pub fn div_values(&mut self, a: Value, b: Value) -> Value{
-        assert!(a.1 == b.1 && !a.1.is_pointer(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
+        assert!(a.1 == b.1 && a.1.is_numeric(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
         let output = self.new_immediate();
         self.block_mut().instructions.push(Instruction{
             operation: Operation::Div(a.0, b.0, a.1),
@@ -218,7 +228,7 @@ impl<'a> BlockBuilder<'a>{
     }
 
     pub fn modulus_values(&mut self, a: Value, b: Value) -> Value{
-        assert!(a.1 == b.1 && !a.1.is_pointer(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
+        assert!(a.1 == b.1 && a.1.is_numeric(), "expects {:?} and {:?} to be the same numeric type", a.1, b.1);
         let output = self.new_immediate();
         self.block_mut().instructions.push(Instruction{
             operation: Operation::Mod(a.0, b.0, a.1),
@@ -228,6 +238,46 @@ impl<'a> BlockBuilder<'a>{
         Value(output, a.1)
     }
 
+    ///
+    /// Offset a pointer by `offset` elements of size `alignment`, which must be 1, 2, 4 or 8
+    pub fn offset_ptr(&mut self, ptr: Value, offset: Value, alignment: u8) -> Value{
+        assert!(ptr.1.is_pointer(), "expects {:?} to be a pointer", ptr.1);
+        assert!(offset.1.is_integer(), "expects {:?} to be an integer", offset.1);
+        let operation = match alignment {
+            1 => Operation::OffsetPtr1(ptr.0, offset.0),
+            2 => Operation::OffsetPtr2(ptr.0, offset.0),
+            4 => Operation::OffsetPtr4(ptr.0, offset.0),
+            8 => Operation::OffsetPtr8(ptr.0, offset.0),
+            _ => panic!("Invalid pointer alignment {}", alignment)
+        };
+        let output = self.new_immediate();
+        self.block_mut().instructions.push(Instruction{
+            operation,
+            output: vec![output]
+        });
+
+        Value(output, Type::Ptr)
+    }
+
+    pub fn read(&mut self, ptr: Value, type_: Type, aligned: bool) -> Value{
+        assert!(ptr.1.is_pointer(), "expects {:?} to be a pointer", ptr.1);
+        let output = self.new_immediate();
+        self.block_mut().instructions.push(Instruction{
+            operation: Operation::Read(ptr.0, type_, aligned),
+            output: vec![output]
+        });
+
+        Value(output, type_)
+    }
+
+    pub fn write(&mut self, ptr: Value, value: Value, aligned: bool){
+        assert!(ptr.1.is_pointer(), "expects {:?} to be a pointer", ptr.1);
+        self.block_mut().instructions.push(Instruction{
+            operation: Operation::Write(ptr.0, value.0, aligned),
+            output: Vec::with_capacity(0)
+        });
+    }
+
     pub fn get_local(&mut self, var: VariableRef) -> Value{
         let output = self.new_immediate();
 
@@ -250,6 +300,59 @@ impl<'a> BlockBuilder<'a>{
         });
     }
 
+    fn block_call(&self, block: BlockRef, args: &[Value]) -> BlockCall{
+        let target = &self.fb.function().blocks[(block.0 - 1) as usize];
+        assert!(target.inputs.len() == args.len(), "{:?} expects {} arguments, not {}", block, target.inputs.len(), args.len());
+        assert!(target.inputs.iter().zip(args.iter()).all(|(a, b)| a.1 == b.1), "arguments {:?} does not match the inputs of {:?}", args, block);
+
+        BlockCall::new(block, args.iter().map(|x| x.0).collect())
+    }
+
+    pub fn branch(&mut self, block: BlockRef, args: &[Value]){
+        let target = self.block_call(block, args);
+        self.block_mut().instructions.push(Instruction{
+            operation: Operation::Branch(target),
+            output: Vec::with_capacity(0)
+        });
+    }
+
+    ///
+    /// Branch to `then` if `value` compared to zero satisfies `condition`; otherwise `else_`
+    pub fn branch_if(&mut self, condition: Condition, value: Value, then: (BlockRef, &[Value]), else_: (BlockRef, &[Value])){
+        let then = self.block_call(then.0, then.1);
+        let else_ = self.block_call(else_.0, else_.1);
+        let operation = match condition {
+            Condition::Eq => Operation::BranchIfEq(value.0, then, else_),
+            Condition::Ne => Operation::BranchIfNe(value.0, then, else_),
+            Condition::Lt => Operation::BranchIfLt(value.0, then, else_),
+            Condition::Le => Operation::BranchIfLe(value.0, then, else_),
+            Condition::Gt => Operation::BranchIfGt(value.0, then, else_),
+            Condition::Ge => Operation::BranchIfGe(value.0, then, else_),
+        };
+        self.block_mut().instructions.push(Instruction{
+            operation,
+            output: Vec::with_capacity(0)
+        });
+    }
+
+    ///
+    /// Call a function in the same module, the function's inputs and outputs must already be defined
+    pub fn invoke(&mut self, function: FunctionRef, args: &[Value]) -> Vec<Value>{
+        let callee = &self.fb.module.functions[(function.0 - 1) as usize];
+        assert!(callee.inputs.len() == args.len() && callee.inputs.iter().zip(args.iter()).all(|(a, b)| *a == b.1),
+            "arguments {:?} does not match the inputs {:?} of {:?}", args, callee.inputs, function);
+
+        let outputs = callee.outputs.clone();
+        let values = outputs.into_iter().map(|type_| Value(self.new_immediate(), type_)).collect::<Vec<_>>();
+
+        self.block_mut().instructions.push(Instruction{
+            operation: Operation::Invoke(function, args.iter().map(|x| x.0).collect()),
+            output: values.iter().map(|x| x.0).collect()
+        });
+
+        values
+    }
+
     pub fn return_(&mut self, values: &[Value]){
 
         assert!(values.len() == self.fb.function().outputs.len());
@@ -264,8 +367,21 @@ impl<'a> BlockBuilder<'a>{
 }
 
 
+///
+/// Comparison against zero used by conditional branches
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum Condition{
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
-pub struct Value(ImmediateRef, Type);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Value(pub(crate) ImmediateRef, pub(crate) Type);
 
 impl Value{
     pub fn immediate(self) -> ImmediateRef{
@@ -0,0 +1,640 @@
+use std::{fmt::Display, io::{Read, Write}};
+
+use crate::{verify_module, Block, BlockCall, BlockRef, ExportRef, Function, FunctionRef, ImmediateRef, Inline, Instruction, Module, ModuleRef, Operation, Type, Value, VariableRef, VerifyError};
+
+const MAGIC: [u8; 4] = *b"CORM";
+
+///
+/// Version of the binary encoding, bumped whenever the layout changes
+pub const FORMAT_VERSION: u16 = 2;
+
+impl Module{
+    ///
+    /// Write the module in the binary encoding, integers are leb128 encoded and floats are written as raw bits
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()>{
+        let mut w = Writer{ inner: writer };
+        w.bytes(&MAGIC)?;
+        w.bytes(&FORMAT_VERSION.to_le_bytes())?;
+
+        w.uint(self.id.0 as u64)?;
+        w.uint(self.exports.len() as u64)?;
+        for export in &self.exports{
+            w.uint(export.0 as u64)?;
+        }
+
+        w.uint(self.functions.len() as u64)?;
+        for function in &self.functions{
+            w.function(function)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Read a module written by `write_to`, the module is verified before it is returned
+    pub fn read_from(reader: &mut impl Read) -> Result<Module, DecodeError>{
+        let mut r = Reader{ inner: reader };
+
+        let mut magic = [0; 4];
+        r.bytes(&mut magic)?;
+        if magic != MAGIC{
+            return Err(DecodeError::BadMagic(magic));
+        }
+        let mut version = [0; 2];
+        r.bytes(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != FORMAT_VERSION{
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let mut module = Module::new(ModuleRef(r.u32()?));
+        for _ in 0..r.uint()?{
+            module.exports.push(ExportRef(r.u32()?));
+        }
+        for _ in 0..r.uint()?{
+            module.functions.push(r.function()?);
+        }
+
+        verify_module(&module).map_err(DecodeError::Invalid)?;
+        Ok(module)
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError{
+    Io(std::io::Error),
+
+    ///
+    /// The input ended in the middle of the module
+    Truncated,
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u16),
+    InvalidType(u8),
+    InvalidOpcode(u8),
+    InvalidInline(u8),
+
+    ///
+    /// An integer does not fit the field it encodes
+    Overflow,
+    InvalidUtf8,
+
+    ///
+    /// The module decoded but does not pass verification
+    Invalid(Vec<VerifyError>),
+}
+
+impl Display for DecodeError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Io(err) => write!(f, "failed to read module: {}", err),
+            DecodeError::Truncated => write!(f, "unexpected end of module"),
+            DecodeError::BadMagic(magic) => write!(f, "not a corrosion module, found magic {:x?}", magic),
+            DecodeError::UnsupportedVersion(version) => write!(f, "unsupported format version {}, expected {}", version, FORMAT_VERSION),
+            DecodeError::InvalidType(x) => write!(f, "invalid type tag {}", x),
+            DecodeError::InvalidOpcode(x) => write!(f, "invalid opcode {}", x),
+            DecodeError::InvalidInline(x) => write!(f, "invalid inline attribute {}", x),
+            DecodeError::Overflow => write!(f, "integer out of range"),
+            DecodeError::InvalidUtf8 => write!(f, "function name is not valid utf-8"),
+            DecodeError::Invalid(errors) => {
+                write!(f, "invalid module")?;
+                for err in errors{
+                    write!(f, "\n  {}", err)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for DecodeError{}
+
+impl From<std::io::Error> for DecodeError{
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            std::io::ErrorKind::UnexpectedEof => DecodeError::Truncated,
+            _ => DecodeError::Io(value)
+        }
+    }
+}
+
+fn type_tag(type_: Type) -> u8{
+    match type_ {
+        Type::I8 => 0,
+        Type::I16 => 1,
+        Type::I32 => 2,
+        Type::I64 => 3,
+        Type::F32 => 4,
+        Type::F64 => 5,
+        Type::Ptr => 6,
+        Type::Ref => 7,
+    }
+}
+
+fn tag_type(tag: u8) -> Result<Type, DecodeError>{
+    Ok(match tag {
+        0 => Type::I8,
+        1 => Type::I16,
+        2 => Type::I32,
+        3 => Type::I64,
+        4 => Type::F32,
+        5 => Type::F64,
+        6 => Type::Ptr,
+        7 => Type::Ref,
+        x => return Err(DecodeError::InvalidType(x))
+    })
+}
+
+mod opcode{
+    pub const CONST_I32: u8 = 0;
+    pub const CONST_I64: u8 = 1;
+    pub const CONST_F32: u8 = 2;
+    pub const CONST_F64: u8 = 3;
+    pub const OFFSET_PTR1: u8 = 4;
+    pub const OFFSET_PTR2: u8 = 5;
+    pub const OFFSET_PTR4: u8 = 6;
+    pub const OFFSET_PTR8: u8 = 7;
+    pub const ADD: u8 = 8;
+    pub const SUB: u8 = 9;
+    pub const MUL: u8 = 10;
+    pub const DIV: u8 = 11;
+    pub const MOD: u8 = 12;
+    pub const LOAD_LOCAL: u8 = 13;
+    pub const STORE_LOCAL: u8 = 14;
+    pub const READ: u8 = 15;
+    pub const WRITE: u8 = 16;
+    pub const BRANCH_IF_EQ: u8 = 17;
+    pub const BRANCH_IF_NE: u8 = 18;
+    pub const BRANCH_IF_LT: u8 = 19;
+    pub const BRANCH_IF_LE: u8 = 20;
+    pub const BRANCH_IF_GT: u8 = 21;
+    pub const BRANCH_IF_GE: u8 = 22;
+    pub const BRANCH: u8 = 23;
+    pub const RETURN: u8 = 24;
+    pub const INVOKE: u8 = 25;
+}
+
+struct Writer<'a, W: Write>{
+    inner: &'a mut W,
+}
+
+impl<'a, W: Write> Writer<'a, W>{
+    fn bytes(&mut self, bytes: &[u8]) -> std::io::Result<()>{
+        self.inner.write_all(bytes)
+    }
+
+    fn byte(&mut self, byte: u8) -> std::io::Result<()>{
+        self.bytes(&[byte])
+    }
+
+    fn uint(&mut self, mut value: u64) -> std::io::Result<()>{
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0{
+                return self.byte(byte);
+            }
+            self.byte(byte | 0x80)?;
+        }
+    }
+
+    fn type_(&mut self, type_: Type) -> std::io::Result<()>{
+        self.byte(type_tag(type_))
+    }
+
+    fn types(&mut self, types: &[Type]) -> std::io::Result<()>{
+        self.uint(types.len() as u64)?;
+        for type_ in types{
+            self.type_(*type_)?;
+        }
+        Ok(())
+    }
+
+    fn immediate(&mut self, immediate: ImmediateRef) -> std::io::Result<()>{
+        self.uint(immediate.0 as u64)
+    }
+
+    fn immediates(&mut self, immediates: &[ImmediateRef]) -> std::io::Result<()>{
+        self.uint(immediates.len() as u64)?;
+        for immediate in immediates{
+            self.immediate(*immediate)?;
+        }
+        Ok(())
+    }
+
+    fn block_call(&mut self, call: &BlockCall) -> std::io::Result<()>{
+        self.uint(call.block.0 as u64)?;
+        self.immediates(&call.args)
+    }
+
+    fn function(&mut self, function: &Function) -> std::io::Result<()>{
+        self.uint(function.id.0 as u64)?;
+        self.uint(function.name.len() as u64)?;
+        self.bytes(function.name.as_bytes())?;
+        self.byte(match function.inline {
+            Inline::Auto => 0,
+            Inline::Always => 1,
+            Inline::Never => 2,
+        })?;
+        self.types(&function.inputs)?;
+        self.types(&function.outputs)?;
+
+        self.uint(function.locals.len() as u64)?;
+        for (var, type_) in &function.locals{
+            self.uint(var.0 as u64)?;
+            self.type_(*type_)?;
+        }
+
+        self.uint(function.entry.0 as u64)?;
+        self.uint(function.blocks.len() as u64)?;
+        for block in &function.blocks{
+            self.uint(block.label.0 as u64)?;
+            self.uint(block.inputs.len() as u64)?;
+            for input in &block.inputs{
+                self.immediate(input.0)?;
+                self.type_(input.1)?;
+            }
+
+            self.uint(block.instructions.len() as u64)?;
+            for instruction in &block.instructions{
+                self.operation(&instruction.operation)?;
+                self.immediates(&instruction.output)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn operation(&mut self, operation: &Operation) -> std::io::Result<()>{
+        match operation {
+            Operation::ConstI32(x) => {
+                self.byte(opcode::CONST_I32)?;
+                self.uint(*x as u64)
+            },
+            Operation::ConstI64(x) => {
+                self.byte(opcode::CONST_I64)?;
+                // Zigzag encoded so small negative constants stay small
+                let x = *x as i64;
+                self.uint(((x << 1) ^ (x >> 63)) as u64)
+            },
+            Operation::ConstF32(x) => {
+                self.byte(opcode::CONST_F32)?;
+                self.bytes(&x.to_bits().to_le_bytes())
+            },
+            Operation::ConstF64(x) => {
+                self.byte(opcode::CONST_F64)?;
+                self.bytes(&x.to_bits().to_le_bytes())
+            },
+            Operation::OffsetPtr1(a, b) |
+            Operation::OffsetPtr2(a, b) |
+            Operation::OffsetPtr4(a, b) |
+            Operation::OffsetPtr8(a, b) => {
+                self.byte(match operation {
+                    Operation::OffsetPtr1(..) => opcode::OFFSET_PTR1,
+                    Operation::OffsetPtr2(..) => opcode::OFFSET_PTR2,
+                    Operation::OffsetPtr4(..) => opcode::OFFSET_PTR4,
+                    _ => opcode::OFFSET_PTR8,
+                })?;
+                self.immediate(*a)?;
+                self.immediate(*b)
+            },
+            Operation::Add(a, b, type_) |
+            Operation::Sub(a, b, type_) |
+            Operation::Mul(a, b, type_) |
+            Operation::Div(a, b, type_) |
+            Operation::Mod(a, b, type_) => {
+                self.byte(match operation {
+                    Operation::Add(..) => opcode::ADD,
+                    Operation::Sub(..) => opcode::SUB,
+                    Operation::Mul(..) => opcode::MUL,
+                    Operation::Div(..) => opcode::DIV,
+                    _ => opcode::MOD,
+                })?;
+                self.immediate(*a)?;
+                self.immediate(*b)?;
+                self.type_(*type_)
+            },
+            Operation::LoadLocal(var) => {
+                self.byte(opcode::LOAD_LOCAL)?;
+                self.uint(var.0 as u64)
+            },
+            Operation::StoreLocal(var, value) => {
+                self.byte(opcode::STORE_LOCAL)?;
+                self.uint(var.0 as u64)?;
+                self.immediate(*value)
+            },
+            Operation::Read(ptr, type_, aligned) => {
+                self.byte(opcode::READ)?;
+                self.immediate(*ptr)?;
+                self.type_(*type_)?;
+                self.byte(*aligned as u8)
+            },
+            Operation::Write(ptr, value, aligned) => {
+                self.byte(opcode::WRITE)?;
+                self.immediate(*ptr)?;
+                self.immediate(*value)?;
+                self.byte(*aligned as u8)
+            },
+            Operation::BranchIfEq(value, then, else_) |
+            Operation::BranchIfNe(value, then, else_) |
+            Operation::BranchIfLt(value, then, else_) |
+            Operation::BranchIfLe(value, then, else_) |
+            Operation::BranchIfGt(value, then, else_) |
+            Operation::BranchIfGe(value, then, else_) => {
+                self.byte(match operation {
+                    Operation::BranchIfEq(..) => opcode::BRANCH_IF_EQ,
+                    Operation::BranchIfNe(..) => opcode::BRANCH_IF_NE,
+                    Operation::BranchIfLt(..) => opcode::BRANCH_IF_LT,
+                    Operation::BranchIfLe(..) => opcode::BRANCH_IF_LE,
+                    Operation::BranchIfGt(..) => opcode::BRANCH_IF_GT,
+                    _ => opcode::BRANCH_IF_GE,
+                })?;
+                self.immediate(*value)?;
+                self.block_call(then)?;
+                self.block_call(else_)
+            },
+            Operation::Branch(call) => {
+                self.byte(opcode::BRANCH)?;
+                self.block_call(call)
+            },
+            Operation::Return(values) => {
+                self.byte(opcode::RETURN)?;
+                self.immediates(values)
+            },
+            Operation::Invoke(function, args) => {
+                self.byte(opcode::INVOKE)?;
+                self.uint(function.0 as u64)?;
+                self.immediates(args)
+            },
+        }
+    }
+}
+
+struct Reader<'a, R: Read>{
+    inner: &'a mut R,
+}
+
+impl<'a, R: Read> Reader<'a, R>{
+    fn bytes(&mut self, bytes: &mut [u8]) -> Result<(), DecodeError>{
+        Ok(self.inner.read_exact(bytes)?)
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError>{
+        let mut byte = [0];
+        self.bytes(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, DecodeError>{
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::Overflow)
+        }
+    }
+
+    fn uint(&mut self) -> Result<u64, DecodeError>{
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7){
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1{
+                return Err(DecodeError::Overflow);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0{
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Overflow)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError>{
+        self.uint()?.try_into().map_err(|_| DecodeError::Overflow)
+    }
+
+    fn type_(&mut self) -> Result<Type, DecodeError>{
+        tag_type(self.byte()?)
+    }
+
+    fn types(&mut self) -> Result<Vec<Type>, DecodeError>{
+        // Counts are not trusted for preallocation, a corrupt count fails on the truncated input instead
+        let mut types = Vec::new();
+        for _ in 0..self.uint()?{
+            types.push(self.type_()?);
+        }
+        Ok(types)
+    }
+
+    fn immediate(&mut self) -> Result<ImmediateRef, DecodeError>{
+        self.u32().map(ImmediateRef)
+    }
+
+    fn immediates(&mut self) -> Result<Vec<ImmediateRef>, DecodeError>{
+        let mut immediates = Vec::new();
+        for _ in 0..self.uint()?{
+            immediates.push(self.immediate()?);
+        }
+        Ok(immediates)
+    }
+
+    fn block_call(&mut self) -> Result<BlockCall, DecodeError>{
+        let block = BlockRef(self.u32()?);
+        Ok(BlockCall::new(block, self.immediates()?))
+    }
+
+    fn function(&mut self) -> Result<Function, DecodeError>{
+        let mut function = Function::new(FunctionRef(self.u32()?));
+
+        let mut name = Vec::new();
+        for _ in 0..self.uint()?{
+            name.push(self.byte()?);
+        }
+        function.name = String::from_utf8(name).map_err(|_| DecodeError::InvalidUtf8)?;
+        function.inline = match self.byte()? {
+            0 => Inline::Auto,
+            1 => Inline::Always,
+            2 => Inline::Never,
+            x => return Err(DecodeError::InvalidInline(x)),
+        };
+        function.inputs = self.types()?;
+        function.outputs = self.types()?;
+
+        for _ in 0..self.uint()?{
+            let var = VariableRef(self.u32()?);
+            function.locals.push((var, self.type_()?));
+        }
+
+        function.entry = BlockRef(self.u32()?);
+        for _ in 0..self.uint()?{
+            let mut block = Block::new(BlockRef(self.u32()?));
+            for _ in 0..self.uint()?{
+                let immediate = self.immediate()?;
+                block.inputs.push(Value(immediate, self.type_()?));
+            }
+            for _ in 0..self.uint()?{
+                let operation = self.operation()?;
+                block.instructions.push(Instruction::new(operation, self.immediates()?));
+            }
+            function.blocks.push(block);
+        }
+
+        Ok(function)
+    }
+
+    fn operation(&mut self) -> Result<Operation, DecodeError>{
+        let opcode = self.byte()?;
+        Ok(match opcode {
+            opcode::CONST_I32 => Operation::ConstI32(self.u32()?),
+            opcode::CONST_I64 => {
+                let x = self.uint()?;
+                Operation::ConstI64(((x >> 1) as i64 ^ -((x & 1) as i64)) as u64)
+            },
+            opcode::CONST_F32 => {
+                let mut bits = [0; 4];
+                self.bytes(&mut bits)?;
+                Operation::ConstF32(f32::from_bits(u32::from_le_bytes(bits)))
+            },
+            opcode::CONST_F64 => {
+                let mut bits = [0; 8];
+                self.bytes(&mut bits)?;
+                Operation::ConstF64(f64::from_bits(u64::from_le_bytes(bits)))
+            },
+            opcode::OFFSET_PTR1 => Operation::OffsetPtr1(self.immediate()?, self.immediate()?),
+            opcode::OFFSET_PTR2 => Operation::OffsetPtr2(self.immediate()?, self.immediate()?),
+            opcode::OFFSET_PTR4 => Operation::OffsetPtr4(self.immediate()?, self.immediate()?),
+            opcode::OFFSET_PTR8 => Operation::OffsetPtr8(self.immediate()?, self.immediate()?),
+            opcode::ADD => Operation::Add(self.immediate()?, self.immediate()?, self.type_()?),
+            opcode::SUB => Operation::Sub(self.immediate()?, self.immediate()?, self.type_()?),
+            opcode::MUL => Operation::Mul(self.immediate()?, self.immediate()?, self.type_()?),
+            opcode::DIV => Operation::Div(self.immediate()?, self.immediate()?, self.type_()?),
+            opcode::MOD => Operation::Mod(self.immediate()?, self.immediate()?, self.type_()?),
+            opcode::LOAD_LOCAL => Operation::LoadLocal(VariableRef(self.u32()?)),
+            opcode::STORE_LOCAL => Operation::StoreLocal(VariableRef(self.u32()?), self.immediate()?),
+            opcode::READ => Operation::Read(self.immediate()?, self.type_()?, self.bool()?),
+            opcode::WRITE => Operation::Write(self.immediate()?, self.immediate()?, self.bool()?),
+            opcode::BRANCH_IF_EQ => Operation::BranchIfEq(self.immediate()?, self.block_call()?, self.block_call()?),
+            opcode::BRANCH_IF_NE => Operation::BranchIfNe(self.immediate()?, self.block_call()?, self.block_call()?),
+            opcode::BRANCH_IF_LT => Operation::BranchIfLt(self.immediate()?, self.block_call()?, self.block_call()?),
+            opcode::BRANCH_IF_LE => Operation::BranchIfLe(self.immediate()?, self.block_call()?, self.block_call()?),
+            opcode::BRANCH_IF_GT => Operation::BranchIfGt(self.immediate()?, self.block_call()?, self.block_call()?),
+            opcode::BRANCH_IF_GE => Operation::BranchIfGe(self.immediate()?, self.block_call()?, self.block_call()?),
+            opcode::BRANCH => Operation::Branch(self.block_call()?),
+            opcode::RETURN => Operation::Return(self.immediates()?),
+            opcode::INVOKE => Operation::Invoke(FunctionRef(self.u32()?), self.immediates()?),
+            x => return Err(DecodeError::InvalidOpcode(x))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::parse_module;
+
+    const SOURCE: &str = r#"
+module 3
+export fn1
+
+function fn1 "max"(i64, i64) -> (i64) inline {
+block0(%1: i64, %2: i64):
+    %3 = sub.i64 %1, %2
+    br_lt %3, block1(%2), block1(%1)
+block1(%4: i64):
+    return %4
+}
+
+function fn2 "constants ✓"() -> (i64) noinline {
+    local $1: f64
+block0:
+    %1 = const.i64 0
+    %2 = const.i64 -1
+    %3 = const.i64 63
+    %4 = const.i64 -64
+    %5 = const.i64 64
+    %6 = const.i64 -9223372036854775808
+    %7 = const.i64 9223372036854775807
+    %8 = const.i32 -2147483648
+    %9 = const.f32 -0.0
+    %10 = const.f64 1e300
+    store_local $1, %10
+    %11 = invoke fn1(%6, %7)
+    return %11
+}
+"#;
+
+    fn encode(module: &Module) -> Vec<u8>{
+        let mut bytes = Vec::new();
+        module.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip(){
+        let module = parse_module(SOURCE).unwrap();
+        let bytes = encode(&module);
+        let decoded = Module::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded.to_string(), module.to_string());
+        assert_eq!(encode(&decoded), bytes);
+    }
+
+    #[test]
+    fn zigzag_constants(){
+        for (x, len) in [(0i64, 1), (-1, 1), (63, 1), (-64, 1), (64, 2), (-65, 2), (i64::MIN, 10), (i64::MAX, 10)]{
+            let module = parse_module(&format!("module 0\nfunction fn1() -> (i64) {{\nblock0:\n    %1 = const.i64 {}\n    return %1\n}}", x)).unwrap();
+            let bytes = encode(&module);
+            let zero = encode(&parse_module("module 0\nfunction fn1() -> (i64) {\nblock0:\n    %1 = const.i64 0\n    return %1\n}").unwrap());
+            assert_eq!(bytes.len(), zero.len() - 1 + len, "{}", x);
+
+            let decoded = Module::read_from(&mut &bytes[..]).unwrap();
+            assert_eq!(decoded.functions[0].blocks[0].instructions[0].operation.to_string(), format!("const.i64 {}", x));
+        }
+    }
+
+    #[test]
+    fn header(){
+        let mut bytes = encode(&parse_module(SOURCE).unwrap());
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(Module::read_from(&mut &bytes[..]), Err(DecodeError::UnsupportedVersion(x)) if x == FORMAT_VERSION + 1));
+
+        bytes[0] = b'X';
+        assert!(matches!(Module::read_from(&mut &bytes[..]), Err(DecodeError::BadMagic(x)) if &x == b"XORM"));
+    }
+
+    #[test]
+    fn inline_attribute(){
+        let auto = encode(&parse_module("function fn1() {\nblock0:\n    return\n}").unwrap());
+        let always = encode(&parse_module("function fn1() inline {\nblock0:\n    return\n}").unwrap());
+        let (index, _) = auto.iter().zip(&always).enumerate().find(|(_, (a, b))| a != b).unwrap();
+        assert_eq!((auto[index], always[index]), (0, 1));
+
+        let mut bytes = always;
+        bytes[index] = 3;
+        assert!(matches!(Module::read_from(&mut &bytes[..]), Err(DecodeError::InvalidInline(3))));
+    }
+
+    #[test]
+    fn truncated(){
+        let bytes = encode(&parse_module(SOURCE).unwrap());
+        for len in 0..bytes.len(){
+            assert!(matches!(Module::read_from(&mut &bytes[..len]), Err(DecodeError::Truncated)), "{}", len);
+        }
+    }
+
+    #[test]
+    fn overflow(){
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        bytes.extend([0x80, 0x80, 0x80, 0x80, 0x10]);
+        assert!(matches!(Module::read_from(&mut &bytes[..]), Err(DecodeError::Overflow)));
+
+        bytes.truncate(6);
+        bytes.extend([0xff; 9]);
+        bytes.push(0x02);
+        assert!(matches!(Module::read_from(&mut &bytes[..]), Err(DecodeError::Overflow)));
+    }
+
+    #[test]
+    fn verified(){
+        let mut module = parse_module(SOURCE).unwrap();
+        module.functions[0].blocks.pop();
+        assert!(matches!(Module::read_from(&mut &encode(&module)[..]), Err(DecodeError::Invalid(_))));
+    }
+}
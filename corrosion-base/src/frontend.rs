@@ -0,0 +1,100 @@
+use std::{fmt::Display, path::Path};
+
+use crate::Module;
+
+///
+/// Producer of corrosion-base modules from some input language, e.g. java class files
+pub trait Frontend{
+    fn name(&self) -> &str;
+
+    ///
+    /// File extensions handled by this frontend, without the leading dot
+    fn extensions(&self) -> &[&str];
+
+    ///
+    /// Whether the contents look like an input of this frontend, e.g. by checking magic bytes
+    fn detect(&self, bytes: &[u8]) -> bool;
+
+    fn translate(&self, path: &Path, bytes: &[u8]) -> Translation;
+}
+
+///
+/// Select the frontend for an input, frontends recognizing the contents take precedence over the file extension
+pub fn select_frontend<'a>(frontends: &'a [Box<dyn Frontend>], path: &Path, bytes: &[u8]) -> Option<&'a dyn Frontend>{
+    let extension = path.extension().and_then(|x| x.to_str()).unwrap_or_default();
+
+    frontends.iter()
+        .find(|x| x.detect(bytes))
+        .or_else(|| frontends.iter().find(|x| x.extensions().contains(&extension)))
+        .map(|x| x.as_ref())
+}
+
+#[derive(Debug, Default)]
+pub struct Translation{
+    pub modules: Vec<Module>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Translation{
+    pub fn has_errors(&self) -> bool{
+        self.diagnostics.iter().any(|x| x.severity == Severity::Error)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Severity{
+    Note,
+    Warning,
+    Error,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic{
+    pub severity: Severity,
+    pub message: String,
+
+    ///
+    /// Where in the input the diagnostic applies, e.g. a file name or `class#method`
+    pub location: Option<String>,
+}
+
+impl Diagnostic{
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self{
+        Self{
+            severity,
+            message: message.into(),
+            location: None
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self{
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self{
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn note(message: impl Into<String>) -> Self{
+        Self::new(Severity::Note, message)
+    }
+
+    pub fn at(mut self, location: impl Into<String>) -> Self{
+        self.location = Some(location.into());
+        self
+    }
+}
+
+impl Display for Diagnostic{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match &self.location {
+            Some(location) => write!(f, "{}: {}: {}", severity, location, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
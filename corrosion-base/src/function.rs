@@ -1,18 +1,23 @@
 use crate::Value;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunctionRef(pub(crate) u32);
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VariableRef(pub(crate) u32);
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImmediateRef(pub(crate) u32);
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockRef(pub(crate) u32);
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type{
     I8,
     I16,
@@ -20,32 +25,40 @@ pub enum Type{
     I64,
     F32,
     F64,
+
+    ///
+    /// Raw pointer with the width of the target's native pointer
     Ptr,
+
+    ///
+    /// Managed reference to a garbage collected object
+    Ref,
 }
 
 impl Type{
     pub fn is_integer(self) -> bool{
-        match self {
-            Self::I8 | Self::I16 | Self::I32 | Self::I64 => true,
-            _ => false
-        }
+        matches!(self, Self::I8 | Self::I16 | Self::I32 | Self::I64)
     }
 
     pub fn is_pointer(self) -> bool{
-        if let Type::Ptr = self{
-            true
-        }
-        else{
-            false
-        }
+        matches!(self, Self::Ptr)
+    }
+
+    pub fn is_reference(self) -> bool{
+        matches!(self, Self::Ref)
     }
 
     pub fn is_float(self) -> bool{
-        !(self.is_integer() || self.is_float())
+        matches!(self, Self::F32 | Self::F64)
+    }
+
+    pub fn is_numeric(self) -> bool{
+        self.is_integer() || self.is_float()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Function{
     pub id: FunctionRef,
     pub name: String,
@@ -53,7 +66,21 @@ pub struct Function{
     pub locals: Vec<(VariableRef, Type)>,
     pub outputs: Vec<Type>,
     pub entry: BlockRef,
-    pub blocks: Vec<Block>
+    pub blocks: Vec<Block>,
+    pub inline: Inline,
+}
+
+///
+/// Whether calls to a function should be replaced by its body
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Inline{
+    ///
+    /// Leave it to the inliner's size heuristic
+    #[default]
+    Auto,
+    Always,
+    Never,
 }
 
 impl Function{
@@ -65,12 +92,14 @@ impl Function{
             id,
             inputs: Vec::new(),
             locals: Vec::new(),
-            outputs: Vec::new()
+            outputs: Vec::new(),
+            inline: Inline::Auto
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block{
     pub label: BlockRef,
     pub inputs: Vec<Value>,
@@ -87,7 +116,8 @@ impl Block{
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operation{
     
     ///
@@ -160,46 +190,179 @@ pub enum Operation{
 
     ///
     /// Branch to the first block if the immediate is == 0; otherwise the second block
-    BranchIfEq(ImmediateRef, BlockRef, BlockRef),
+    BranchIfEq(ImmediateRef, BlockCall, BlockCall),
 
     ///
     /// Branch to the first block if the immediate is != 0; otherwise the second block
-    BranchIfNe(ImmediateRef, BlockRef, BlockRef),
+    BranchIfNe(ImmediateRef, BlockCall, BlockCall),
 
     ///
     /// Branch to the first block if the immediate is < 0; otherwise the second block
-    BranchIfLt(ImmediateRef, BlockRef, BlockRef),
+    BranchIfLt(ImmediateRef, BlockCall, BlockCall),
 
     ///
     /// Branch to the first block if the immediate is <= 0; otherwise the second block
-    BranchIfLe(ImmediateRef, BlockRef, BlockRef),
+    BranchIfLe(ImmediateRef, BlockCall, BlockCall),
 
     ///
     /// Branch to the first block if the immediate is > 0; otherwise the second block
-    BranchIfGt(ImmediateRef, BlockRef, BlockRef),
+    BranchIfGt(ImmediateRef, BlockCall, BlockCall),
     
     ///
     /// Branch to the first block if the immediate is >= 0; otherwise the second block
-    BranchIfGe(ImmediateRef, BlockRef, BlockRef),
+    BranchIfGe(ImmediateRef, BlockCall, BlockCall),
 
     ///
     /// Branch to the block
-    Branch(BlockRef),
+    Branch(BlockCall),
 
     ///
     /// Return with the provided amount of immediates, must match the functions return values
     Return(Vec<ImmediateRef>),
 
+    ///
+    /// Call a function in the same module, the outputs are the function's return values
     Invoke(FunctionRef, Vec<ImmediateRef>)
 }
 
-#[derive(Debug)]
+impl Operation{
+    ///
+    /// Blocks this operation may transfer control to
+    pub fn targets(&self) -> Vec<&BlockCall>{
+        match self {
+            Operation::BranchIfEq(_, a, b) |
+            Operation::BranchIfNe(_, a, b) |
+            Operation::BranchIfLt(_, a, b) |
+            Operation::BranchIfLe(_, a, b) |
+            Operation::BranchIfGt(_, a, b) |
+            Operation::BranchIfGe(_, a, b) => vec![a, b],
+            Operation::Branch(a) => vec![a],
+            _ => Vec::with_capacity(0)
+        }
+    }
+
+    pub fn targets_mut(&mut self) -> Vec<&mut BlockCall>{
+        match self {
+            Operation::BranchIfEq(_, a, b) |
+            Operation::BranchIfNe(_, a, b) |
+            Operation::BranchIfLt(_, a, b) |
+            Operation::BranchIfLe(_, a, b) |
+            Operation::BranchIfGt(_, a, b) |
+            Operation::BranchIfGe(_, a, b) => vec![a, b],
+            Operation::Branch(a) => vec![a],
+            _ => Vec::with_capacity(0)
+        }
+    }
+
+    ///
+    /// Immediates read by this operation, including the arguments passed to branch targets
+    pub fn uses(&self) -> Vec<ImmediateRef>{
+        let mut uses = Vec::new();
+        // Visits a copy so the traversal is shared with replace_uses
+        self.clone().replace_uses(|x| {
+            uses.push(x);
+            x
+        });
+        uses
+    }
+
+    ///
+    /// Replace every immediate read by this operation
+    pub fn replace_uses(&mut self, mut f: impl FnMut(ImmediateRef) -> ImmediateRef){
+        match self {
+            Operation::ConstI32(_) |
+            Operation::ConstI64(_) |
+            Operation::ConstF32(_) |
+            Operation::ConstF64(_) |
+            Operation::LoadLocal(_) => {},
+            Operation::OffsetPtr1(a, b) |
+            Operation::OffsetPtr2(a, b) |
+            Operation::OffsetPtr4(a, b) |
+            Operation::OffsetPtr8(a, b) |
+            Operation::Add(a, b, _) |
+            Operation::Sub(a, b, _) |
+            Operation::Mul(a, b, _) |
+            Operation::Div(a, b, _) |
+            Operation::Mod(a, b, _) |
+            Operation::Write(a, b, _) => {
+                *a = f(*a);
+                *b = f(*b);
+            },
+            Operation::StoreLocal(_, a) |
+            Operation::Read(a, _, _) => *a = f(*a),
+            Operation::BranchIfEq(value, then, else_) |
+            Operation::BranchIfNe(value, then, else_) |
+            Operation::BranchIfLt(value, then, else_) |
+            Operation::BranchIfLe(value, then, else_) |
+            Operation::BranchIfGt(value, then, else_) |
+            Operation::BranchIfGe(value, then, else_) => {
+                *value = f(*value);
+                for x in then.args.iter_mut().chain(else_.args.iter_mut()){
+                    *x = f(*x);
+                }
+            },
+            Operation::Branch(call) => {
+                for x in call.args.iter_mut(){
+                    *x = f(*x);
+                }
+            },
+            Operation::Return(values) |
+            Operation::Invoke(_, values) => {
+                for x in values.iter_mut(){
+                    *x = f(*x);
+                }
+            },
+        }
+    }
+
+    ///
+    /// Whether this operation ends a block
+    pub fn is_terminator(&self) -> bool{
+        matches!(self, 
+            Operation::BranchIfEq(..) | 
+            Operation::BranchIfNe(..) | 
+            Operation::BranchIfLt(..) | 
+            Operation::BranchIfLe(..) | 
+            Operation::BranchIfGt(..) | 
+            Operation::BranchIfGe(..) | 
+            Operation::Branch(..) | 
+            Operation::Return(..))
+    }
+}
+
+///
+/// A branch target together with the immediates passed as the block's inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockCall{
+    pub block: BlockRef,
+    pub args: Vec<ImmediateRef>,
+}
+
+impl BlockCall{
+    pub fn new(block: BlockRef, args: Vec<ImmediateRef>) -> Self{
+        Self{
+            block,
+            args
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instruction{
     pub(crate) operation: Operation,
     pub(crate) output: Vec<ImmediateRef>,
 }
 
 impl Instruction{
+    pub fn new(operation: Operation, output: Vec<ImmediateRef>) -> Self{
+        Self{
+            operation,
+            output
+        }
+    }
+
     pub fn operation(&self) -> &Operation{
         &self.operation
     }
@@ -219,6 +382,6 @@ impl Instruction{
     }
 
     pub fn assert_no_immediates(&self){
-        assert!(self.immediates().len() == 0, "Expected no immediates, not {}", self.immediates().len());
+        assert!(self.immediates().is_empty(), "Expected no immediates, not {}", self.immediates().len());
     }
 }
\ No newline at end of file
@@ -1,7 +1,22 @@
+pub mod analysis;
+pub mod passes;
+
 mod function;
 mod module;
 mod builder;
+mod scalar;
+mod backend;
+mod frontend;
+mod verify;
+mod text;
+mod encoding;
 
 pub use function::*;
 pub use module::*;
-pub use builder::*;
\ No newline at end of file
+pub use builder::*;
+pub use scalar::*;
+pub use backend::*;
+pub use frontend::*;
+pub use verify::*;
+pub use text::*;
+pub use encoding::*;
\ No newline at end of file
@@ -0,0 +1,246 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::{analysis::ControlFlowGraph, Function, ImmediateRef, Module, Operation, Scalar};
+
+use super::{run_on_functions, Pass};
+
+///
+/// Evaluates arithmetic on constants and branches on constant conditions at compile time.
+/// Operations that would trap, and float results that are NaN, are left for the backend
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding{
+    fn name(&self) -> &str {
+        "const-fold"
+    }
+
+    fn run(&self, module: &mut Module) -> bool {
+        run_on_functions(module, fold_function)
+    }
+}
+
+fn fold_function(function: &mut Function) -> bool{
+    let mut changed = false;
+    let mut constants = HashMap::new();
+
+    // Definitions dominate their uses, so visiting in reverse postorder sees every constant before it is used
+    for label in ControlFlowGraph::new(function).reverse_postorder(){
+        let Some(block) = function.blocks.iter_mut().find(|x| x.label == label) else { continue };
+
+        for instruction in &mut block.instructions{
+            if let Some(folded) = fold(&instruction.operation, &constants){
+                instruction.operation = folded;
+                changed = true;
+            }
+
+            if let (Some(value), [output]) = (constant(&instruction.operation), &instruction.output[..]){
+                constants.insert(*output, value);
+            }
+        }
+    }
+
+    changed
+}
+
+fn constant(operation: &Operation) -> Option<Scalar>{
+    match operation {
+        Operation::ConstI32(x) => Some(Scalar::I32(*x as i32)),
+        Operation::ConstI64(x) => Some(Scalar::I64(*x as i64)),
+        Operation::ConstF32(x) => Some(Scalar::F32(*x)),
+        Operation::ConstF64(x) => Some(Scalar::F64(*x)),
+        _ => None
+    }
+}
+
+///
+/// Operation loading a constant, only 32 and 64 bit types have constant operations
+pub(crate) fn from_constant(value: Scalar) -> Option<Operation>{
+    match value {
+        Scalar::I32(x) => Some(Operation::ConstI32(x as u32)),
+        Scalar::I64(x) => Some(Operation::ConstI64(x as u64)),
+        Scalar::F32(x) => Some(Operation::ConstF32(x)),
+        Scalar::F64(x) => Some(Operation::ConstF64(x)),
+        _ => None
+    }
+}
+
+macro_rules! integer_arithmetic {
+    ($operation:expr, $a:expr, $b:expr, $min:expr) => {
+        match $operation {
+            Operation::Add(..) => $a.wrapping_add($b),
+            Operation::Sub(..) => $a.wrapping_sub($b),
+            Operation::Mul(..) => $a.wrapping_mul($b),
+            Operation::Div(..) if $b == 0 || ($a == $min && $b == -1) => return None,
+            Operation::Div(..) => $a / $b,
+            Operation::Mod(..) if $b == 0 => return None,
+            Operation::Mod(..) => $a.wrapping_rem($b),
+            _ => return None
+        }
+    };
+}
+
+macro_rules! float_arithmetic {
+    ($operation:expr, $a:expr, $b:expr) => {{
+        let value = match $operation {
+            Operation::Add(..) => $a + $b,
+            Operation::Sub(..) => $a - $b,
+            Operation::Mul(..) => $a * $b,
+            Operation::Div(..) => $a / $b,
+            Operation::Mod(..) => $a % $b,
+            _ => return None
+        };
+        // The payload of a NaN depends on the hardware computing it
+        if value.is_nan(){
+            return None;
+        }
+        value
+    }};
+}
+
+fn fold(operation: &Operation, constants: &HashMap<ImmediateRef, Scalar>) -> Option<Operation>{
+    match operation {
+        Operation::Add(a, b, _) |
+        Operation::Sub(a, b, _) |
+        Operation::Mul(a, b, _) |
+        Operation::Div(a, b, _) |
+        Operation::Mod(a, b, _) => {
+            let value = match (constants.get(a)?, constants.get(b)?) {
+                (Scalar::I32(a), Scalar::I32(b)) => Scalar::I32(integer_arithmetic!(operation, *a, *b, i32::MIN)),
+                (Scalar::I64(a), Scalar::I64(b)) => Scalar::I64(integer_arithmetic!(operation, *a, *b, i64::MIN)),
+                (Scalar::F32(a), Scalar::F32(b)) => Scalar::F32(float_arithmetic!(operation, *a, *b)),
+                (Scalar::F64(a), Scalar::F64(b)) => Scalar::F64(float_arithmetic!(operation, *a, *b)),
+                _ => return None
+            };
+            from_constant(value)
+        },
+        Operation::BranchIfEq(value, then, else_) |
+        Operation::BranchIfNe(value, then, else_) |
+        Operation::BranchIfLt(value, then, else_) |
+        Operation::BranchIfLe(value, then, else_) |
+        Operation::BranchIfGt(value, then, else_) |
+        Operation::BranchIfGe(value, then, else_) => {
+            // Compare the condition against zero, a NaN condition is unordered and only `ne` holds
+            let ordering = match constants.get(value)? {
+                Scalar::F32(x) => x.partial_cmp(&0.0),
+                Scalar::F64(x) => x.partial_cmp(&0.0),
+                x => Some(x.as_i64()?.cmp(&0)),
+            };
+            let taken = match operation {
+                Operation::BranchIfEq(..) => ordering == Some(Ordering::Equal),
+                Operation::BranchIfNe(..) => ordering != Some(Ordering::Equal),
+                Operation::BranchIfLt(..) => ordering == Some(Ordering::Less),
+                Operation::BranchIfLe(..) => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                Operation::BranchIfGt(..) => ordering == Some(Ordering::Greater),
+                _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            };
+            Some(Operation::Branch(if taken { then.clone() } else { else_.clone() }))
+        },
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::super::tests::check_pass;
+    use super::ConstantFolding;
+
+    #[test]
+    fn arithmetic_and_branches(){
+        check_pass(&ConstantFolding, "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 6
+    %3 = const.i32 7
+    %4 = mul.i32 %2, %3
+    br_gt %4, block1, block2
+block1:
+    %5 = add.i32 %4, %1
+    %6 = const.i32 2147483647
+    %7 = add.i32 %6, %5
+    return %7
+block2:
+    %8 = const.i32 0
+    %9 = div.i32 %4, %8
+    return %9
+}
+", "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 6
+    %3 = const.i32 7
+    %4 = const.i32 42
+    br block1
+block1:
+    %5 = add.i32 %4, %1
+    %6 = const.i32 2147483647
+    %7 = add.i32 %6, %5
+    return %7
+block2:
+    %8 = const.i32 0
+    %9 = div.i32 %4, %8
+    return %9
+}
+");
+    }
+
+    #[test]
+    fn wrapping_and_floats(){
+        check_pass(&ConstantFolding, "
+module 0
+function fn0 \"f\"() -> (i32, i64, f64) {
+block0:
+    %1 = const.i32 2147483647
+    %2 = const.i32 1
+    %3 = add.i32 %1, %2
+    %4 = const.i64 -9223372036854775808
+    %5 = const.i64 -1
+    %6 = mod.i64 %4, %5
+    %7 = const.f64 0.0
+    %8 = div.f64 %7, %7
+    %9 = const.f64 7.5
+    %10 = const.f64 2.0
+    %11 = mod.f64 %9, %10
+    return %3, %6, %11
+}
+", "
+module 0
+function fn0 \"f\"() -> (i32, i64, f64) {
+block0:
+    %1 = const.i32 2147483647
+    %2 = const.i32 1
+    %3 = const.i32 -2147483648
+    %4 = const.i64 -9223372036854775808
+    %5 = const.i64 -1
+    %6 = const.i64 0
+    %7 = const.f64 0.0
+    %8 = div.f64 %7, %7
+    %9 = const.f64 7.5
+    %10 = const.f64 2.0
+    %11 = const.f64 1.5
+    return %3, %6, %11
+}
+");
+    }
+
+    #[test]
+    fn loop_inputs_are_not_constant(){
+        let source = "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 0
+    br block1(%2)
+block1(%3: i32):
+    %4 = const.i32 1
+    %5 = add.i32 %3, %4
+    %6 = sub.i32 %5, %1
+    br_lt %6, block1(%5), block2
+block2:
+    return %5
+}
+";
+        check_pass(&ConstantFolding, source, source);
+    }
+}
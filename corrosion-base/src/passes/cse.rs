@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use crate::{analysis::{ControlFlowGraph, DominatorTree}, Function, ImmediateRef, Module, Operation, Type};
+
+use super::{run_on_functions, Pass};
+
+///
+/// Replaces pure operations with an identical operation in a dominating position
+pub struct CommonSubexpressionElimination;
+
+impl Pass for CommonSubexpressionElimination{
+    fn name(&self) -> &str {
+        "cse"
+    }
+
+    fn run(&self, module: &mut Module) -> bool {
+        run_on_functions(module, eliminate_common_subexpressions)
+    }
+}
+
+///
+/// Identity of a pure operation, floats are compared by their bits
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Key{
+    ConstI32(u32),
+    ConstI64(u64),
+    ConstF32(u32),
+    ConstF64(u64),
+    OffsetPtr(u8, ImmediateRef, ImmediateRef),
+    Arithmetic(u8, ImmediateRef, ImmediateRef, Type),
+}
+
+fn key(operation: &Operation) -> Option<Key>{
+    Some(match operation {
+        Operation::ConstI32(x) => Key::ConstI32(*x),
+        Operation::ConstI64(x) => Key::ConstI64(*x),
+        Operation::ConstF32(x) => Key::ConstF32(x.to_bits()),
+        Operation::ConstF64(x) => Key::ConstF64(x.to_bits()),
+        Operation::OffsetPtr1(a, b) => Key::OffsetPtr(1, *a, *b),
+        Operation::OffsetPtr2(a, b) => Key::OffsetPtr(2, *a, *b),
+        Operation::OffsetPtr4(a, b) => Key::OffsetPtr(4, *a, *b),
+        Operation::OffsetPtr8(a, b) => Key::OffsetPtr(8, *a, *b),
+
+        // Integer addition and multiplication commute, so their operands are ordered
+        Operation::Add(a, b, t) if t.is_integer() => Key::Arithmetic(0, *a.min(b), *a.max(b), *t),
+        Operation::Mul(a, b, t) if t.is_integer() => Key::Arithmetic(2, *a.min(b), *a.max(b), *t),
+        Operation::Add(a, b, t) => Key::Arithmetic(0, *a, *b, *t),
+        Operation::Sub(a, b, t) => Key::Arithmetic(1, *a, *b, *t),
+        Operation::Mul(a, b, t) => Key::Arithmetic(2, *a, *b, *t),
+        Operation::Div(a, b, t) => Key::Arithmetic(3, *a, *b, *t),
+        Operation::Mod(a, b, t) => Key::Arithmetic(4, *a, *b, *t),
+        _ => return None
+    })
+}
+
+fn eliminate_common_subexpressions(function: &mut Function) -> bool{
+    let cfg = ControlFlowGraph::new(function);
+    let domtree = DominatorTree::new(&cfg);
+    let index = function.blocks.iter().enumerate().map(|(i, x)| (x.label, i)).collect::<HashMap<_, _>>();
+
+    let mut replacements = HashMap::new();
+    let mut available: HashMap<Key, ImmediateRef> = HashMap::new();
+
+    // Walk the dominator tree, the operations available in a block are those of its dominators
+    let mut scopes: Vec<Vec<Key>> = Vec::new();
+    let mut visit = vec![Some(cfg.entry())];
+
+    while let Some(next) = visit.pop(){
+        let Some(label) = next else {
+            // Leaving a block, forget what it made available
+            for key in scopes.pop().unwrap_or_default(){
+                available.remove(&key);
+            }
+            continue;
+        };
+        let Some(block) = index.get(&label).map(|x| &mut function.blocks[*x]) else { continue };
+
+        let mut scope = Vec::new();
+        block.instructions.retain_mut(|instruction| {
+            instruction.operation.replace_uses(|x| *replacements.get(&x).unwrap_or(&x));
+
+            let (Some(key), [output]) = (key(&instruction.operation), &instruction.output[..]) else {
+                return true;
+            };
+            match available.get(&key) {
+                Some(existing) => {
+                    replacements.insert(*output, *existing);
+                    false
+                },
+                None => {
+                    available.insert(key, *output);
+                    scope.push(key);
+                    true
+                }
+            }
+        });
+        scopes.push(scope);
+
+        visit.push(None);
+        visit.extend(domtree.children(label).iter().rev().map(|x| Some(*x)));
+    }
+
+    if replacements.is_empty(){
+        return false;
+    }
+
+    // Uses in blocks that are not dominated by the definition, e.g. unreachable ones, are renamed too
+    for instruction in function.blocks.iter_mut().flat_map(|x| x.instructions.iter_mut()){
+        instruction.operation.replace_uses(|x| *replacements.get(&x).unwrap_or(&x));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests{
+    use super::super::tests::check_pass;
+    use super::CommonSubexpressionElimination;
+
+    #[test]
+    fn dominating_expressions(){
+        // Integer addition commutes and subtraction doesn't, the constants of the two branches don't dominate each other
+        check_pass(&CommonSubexpressionElimination, "
+module 0
+function fn0 \"f\"(i32, i32) -> (i32) {
+block0(%1: i32, %2: i32):
+    %3 = add.i32 %1, %2
+    %4 = add.i32 %2, %1
+    %5 = sub.i32 %1, %2
+    %6 = sub.i32 %2, %1
+    br_eq %1, block1, block2
+block1:
+    %7 = const.i32 9
+    %8 = sub.i32 %1, %2
+    %9 = mul.i32 %7, %8
+    br block3(%9)
+block2:
+    %10 = const.i32 9
+    %11 = mul.i32 %10, %6
+    br block3(%11)
+block3(%12: i32):
+    %13 = add.i32 %3, %4
+    %14 = add.i32 %12, %13
+    return %14
+}
+", "
+module 0
+function fn0 \"f\"(i32, i32) -> (i32) {
+block0(%1: i32, %2: i32):
+    %3 = add.i32 %1, %2
+    %5 = sub.i32 %1, %2
+    %6 = sub.i32 %2, %1
+    br_eq %1, block1, block2
+block1:
+    %7 = const.i32 9
+    %9 = mul.i32 %7, %5
+    br block3(%9)
+block2:
+    %10 = const.i32 9
+    %11 = mul.i32 %10, %6
+    br block3(%11)
+block3(%12: i32):
+    %13 = add.i32 %3, %3
+    %14 = add.i32 %12, %13
+    return %14
+}
+");
+    }
+
+    #[test]
+    fn floats(){
+        // Float addition is not reordered, and -0.0 is a different constant than 0.0
+        let source = "
+module 0
+function fn0 \"f\"(f64, f64) -> (f64) {
+block0(%1: f64, %2: f64):
+    %3 = add.f64 %1, %2
+    %4 = add.f64 %2, %1
+    %5 = const.f64 0.0
+    %6 = const.f64 -0.0
+    %7 = mul.f64 %3, %5
+    %8 = mul.f64 %4, %6
+    %9 = sub.f64 %7, %8
+    return %9
+}
+";
+        check_pass(&CommonSubexpressionElimination, source, source);
+    }
+
+    #[test]
+    fn loop_body(){
+        // The header dominates the loop body, so its expressions are reused there
+        check_pass(&CommonSubexpressionElimination, "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 0
+    br block1(%2)
+block1(%3: i32):
+    %4 = const.i32 1
+    %5 = add.i32 %3, %4
+    %6 = sub.i32 %5, %1
+    br_lt %6, block2, block3
+block2:
+    %7 = const.i32 1
+    %8 = add.i32 %4, %3
+    br block1(%8)
+block3:
+    return %5
+}
+", "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 0
+    br block1(%2)
+block1(%3: i32):
+    %4 = const.i32 1
+    %5 = add.i32 %3, %4
+    %6 = sub.i32 %5, %1
+    br_lt %6, block2, block3
+block2:
+    br block1(%5)
+block3:
+    return %5
+}
+");
+    }
+}
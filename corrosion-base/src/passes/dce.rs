@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{BlockRef, Function, ImmediateRef, Module, Operation};
+
+use super::{run_on_functions, Pass};
+
+///
+/// Removes operations whose outputs are never used and that have no effects, as well as
+/// block inputs that are only passed along to other unused block inputs
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination{
+    fn name(&self) -> &str {
+        "dce"
+    }
+
+    fn run(&self, module: &mut Module) -> bool {
+        run_on_functions(module, eliminate_dead_code)
+    }
+}
+
+///
+/// Whether removing the operation could change the behavior of the function, ignoring its outputs
+pub(crate) fn has_side_effects(operation: &Operation, constants: &HashMap<ImmediateRef, i64>) -> bool{
+    match operation {
+        Operation::ConstI32(_) |
+        Operation::ConstI64(_) |
+        Operation::ConstF32(_) |
+        Operation::ConstF64(_) |
+        Operation::OffsetPtr1(..) |
+        Operation::OffsetPtr2(..) |
+        Operation::OffsetPtr4(..) |
+        Operation::OffsetPtr8(..) |
+        Operation::Add(..) |
+        Operation::Sub(..) |
+        Operation::Mul(..) |
+        Operation::LoadLocal(_) => false,
+
+        // Integer division traps on zero, and on overflow when dividing the minimum by -1
+        Operation::Div(_, b, type_) |
+        Operation::Mod(_, b, type_) => type_.is_integer() && !matches!(constants.get(b), Some(x) if *x != 0 && *x != -1),
+
+        // Reads may trap on invalid addresses
+        Operation::Read(..) |
+        Operation::StoreLocal(..) |
+        Operation::Write(..) |
+        Operation::Invoke(..) => true,
+        _ => operation.is_terminator()
+    }
+}
+
+enum Definition{
+    Instruction(usize, usize),
+    Input(BlockRef, usize),
+}
+
+fn eliminate_dead_code(function: &mut Function) -> bool{
+    let mut constants = HashMap::new();
+    let mut definitions = HashMap::new();
+
+    // Arguments passed to each input of a block, by every branch to it
+    let mut incoming: HashMap<(BlockRef, usize), Vec<ImmediateRef>> = HashMap::new();
+
+    for (b, block) in function.blocks.iter().enumerate(){
+        for (i, input) in block.inputs.iter().enumerate(){
+            definitions.insert(input.immediate(), Definition::Input(block.label, i));
+        }
+        for (i, instruction) in block.instructions.iter().enumerate(){
+            for output in &instruction.output{
+                definitions.insert(*output, Definition::Instruction(b, i));
+            }
+            if let (Operation::ConstI32(x), [output]) = (&instruction.operation, &instruction.output[..]){
+                constants.insert(*output, *x as i32 as i64);
+            }
+            if let (Operation::ConstI64(x), [output]) = (&instruction.operation, &instruction.output[..]){
+                constants.insert(*output, *x as i64);
+            }
+            for call in instruction.operation.targets(){
+                for (index, arg) in call.args.iter().enumerate(){
+                    incoming.entry((call.block, index)).or_default().push(*arg);
+                }
+            }
+        }
+    }
+
+    // Mark everything that effects depend on, block arguments only become live when the input they are passed to is
+    let mut live = HashSet::new();
+    let mut worklist = Vec::new();
+    for block in &function.blocks{
+        for instruction in &block.instructions{
+            if !has_side_effects(&instruction.operation, &constants){
+                continue;
+            }
+            let mut operation = instruction.operation.clone();
+            for call in operation.targets_mut(){
+                call.args.clear();
+            }
+            worklist.extend(operation.uses());
+        }
+    }
+
+    while let Some(immediate) = worklist.pop(){
+        if !live.insert(immediate){
+            continue;
+        }
+        match definitions.get(&immediate) {
+            Some(Definition::Instruction(b, i)) => {
+                let operation = &function.blocks[*b].instructions[*i].operation;
+                if !has_side_effects(operation, &constants){
+                    worklist.extend(operation.uses());
+                }
+            },
+            Some(Definition::Input(block, index)) => worklist.extend(incoming.get(&(*block, *index)).into_iter().flatten()),
+            None => {},
+        }
+    }
+
+    let mut changed = false;
+
+    // Entry inputs are the function's parameters and stay
+    let mut removed_inputs: HashMap<BlockRef, Vec<usize>> = HashMap::new();
+    for block in function.blocks.iter_mut(){
+        let before = block.instructions.len();
+        block.instructions.retain(|x| has_side_effects(&x.operation, &constants) || x.output.iter().any(|x| live.contains(x)));
+        changed |= block.instructions.len() != before;
+
+        if block.label == function.entry{
+            continue;
+        }
+        let dead = block.inputs.iter().enumerate().filter(|(_, x)| !live.contains(&x.immediate())).map(|(i, _)| i).collect::<Vec<_>>();
+        if !dead.is_empty(){
+            let mut index = 0;
+            block.inputs.retain(|_| {
+                index += 1;
+                !dead.contains(&(index - 1))
+            });
+            removed_inputs.insert(block.label, dead);
+            changed = true;
+        }
+    }
+
+    if !removed_inputs.is_empty(){
+        for instruction in function.blocks.iter_mut().flat_map(|x| x.instructions.iter_mut()){
+            for call in instruction.operation.targets_mut(){
+                let Some(dead) = removed_inputs.get(&call.block) else { continue };
+                let mut index = 0;
+                call.args.retain(|_| {
+                    index += 1;
+                    !dead.contains(&(index - 1))
+                });
+            }
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests{
+    use super::super::tests::check_pass;
+    use super::DeadCodeElimination;
+
+    #[test]
+    fn unused_operations_and_inputs(){
+        // %9 is only passed back to itself around the loop, the division by %2 may trap and stays
+        check_pass(&DeadCodeElimination, "
+module 0
+function fn0 \"f\"(i32, i32) -> (i32) {
+block0(%1: i32, %2: i32):
+    %3 = mul.i32 %1, %2
+    %4 = div.i32 %1, %2
+    %5 = const.i32 2
+    %6 = div.i32 %1, %5
+    %7 = const.i32 0
+    br block1(%7, %3)
+block1(%8: i32, %9: i32):
+    %10 = add.i32 %9, %9
+    %11 = const.i32 1
+    %12 = add.i32 %8, %11
+    %13 = sub.i32 %12, %1
+    br_lt %13, block1(%12, %10), block2
+block2:
+    return %12
+}
+", "
+module 0
+function fn0 \"f\"(i32, i32) -> (i32) {
+block0(%1: i32, %2: i32):
+    %4 = div.i32 %1, %2
+    %7 = const.i32 0
+    br block1(%7)
+block1(%8: i32):
+    %11 = const.i32 1
+    %12 = add.i32 %8, %11
+    %13 = sub.i32 %12, %1
+    br_lt %13, block1(%12), block2
+block2:
+    return %12
+}
+");
+    }
+
+    #[test]
+    fn effects_stay(){
+        let source = "
+module 0
+function fn0 \"f\"(i64) -> (i64) {
+    local $1: i64
+block0(%1: i64):
+    %2 = const.i64 -1
+    %3 = mod.i64 %1, %2
+    store_local $1, %1
+    %4 = invoke fn1(%1)
+    %5 = load_local $1
+    return %5
+}
+function fn1 \"g\"(i64) -> (i64) {
+block0(%1: i64):
+    return %1
+}
+";
+        check_pass(&DeadCodeElimination, source, source);
+    }
+}
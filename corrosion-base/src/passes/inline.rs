@@ -0,0 +1,383 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{Block, BlockCall, BlockRef, Function, FunctionRef, ImmediateRef, Inline, Instruction, Module, Operation, Scalar, Value, VariableRef};
+
+use super::{const_fold::from_constant, last_block, last_immediate, Pass};
+
+///
+/// Replaces calls with a copy of the called function's body. Functions marked `Inline::Always` are always
+/// inlined, `Inline::Never` never is, and the rest only when they have at most `threshold` instructions.
+/// Recursive functions and calls that could recurse back into the caller are left alone
+pub struct Inliner{
+    threshold: usize,
+}
+
+impl Default for Inliner{
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl Inliner{
+    pub fn new(threshold: usize) -> Self{
+        Self{
+            threshold
+        }
+    }
+}
+
+impl Pass for Inliner{
+    fn name(&self) -> &str {
+        "inline"
+    }
+
+    fn run(&self, module: &mut Module) -> bool {
+        // Inlining only adds calls the callee already made, so the reachability of the original module holds throughout
+        let callees = module.functions.iter().map(|x| (x.id, x.clone())).collect::<HashMap<_, _>>();
+        let calls = module.functions.iter().map(|x| (x.id, called_functions(x))).collect::<HashMap<_, _>>();
+
+        let mut changed = false;
+        for function in &mut module.functions{
+            let caller = function.id;
+            let eligible = |callee: &Function| match callee.inline {
+                Inline::Never => false,
+                Inline::Always => true,
+                Inline::Auto => size(callee) <= self.threshold,
+            } && !reaches(&calls, callee.id, caller) && !is_recursive(&calls, callee.id) && callee.locals.iter().all(|(_, x)| from_constant(Scalar::zero(*x)).is_some());
+
+            while let Some((block, index, callee)) = find_call_site(function, |x| callees.get(&x).filter(|x| eligible(x))){
+                inline_call(function, block, index, callee);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn size(function: &Function) -> usize{
+    function.blocks.iter().map(|x| x.instructions.len()).sum()
+}
+
+fn called_functions(function: &Function) -> HashSet<FunctionRef>{
+    function.blocks.iter()
+        .flat_map(|x| &x.instructions)
+        .filter_map(|x| match x.operation {
+            Operation::Invoke(callee, _) => Some(callee),
+            _ => None
+        })
+        .collect()
+}
+
+///
+/// Whether `from` can end up calling `to`, directly or through other functions
+fn reaches(calls: &HashMap<FunctionRef, HashSet<FunctionRef>>, from: FunctionRef, to: FunctionRef) -> bool{
+    let mut visited = HashSet::new();
+    let mut stack = vec![from];
+    while let Some(function) = stack.pop(){
+        if function == to{
+            return true;
+        }
+        if visited.insert(function){
+            stack.extend(calls.get(&function).into_iter().flatten());
+        }
+    }
+    false
+}
+
+fn is_recursive(calls: &HashMap<FunctionRef, HashSet<FunctionRef>>, function: FunctionRef) -> bool{
+    calls.get(&function).into_iter().flatten().any(|x| reaches(calls, *x, function))
+}
+
+fn find_call_site<'a>(function: &Function, callee: impl Fn(FunctionRef) -> Option<&'a Function>) -> Option<(usize, usize, &'a Function)>{
+    function.blocks.iter().enumerate().find_map(|(b, block)| {
+        block.instructions.iter().enumerate().find_map(|(i, instruction)| match instruction.operation {
+            Operation::Invoke(id, _) => callee(id).map(|x| (b, i, x)),
+            _ => None
+        })
+    })
+}
+
+///
+/// Fresh immediates for the copied body, numbered after the caller's own
+struct Renamer{
+    next: u32,
+    names: HashMap<ImmediateRef, ImmediateRef>,
+}
+
+impl Renamer{
+    fn fresh(&mut self) -> ImmediateRef{
+        self.next += 1;
+        ImmediateRef(self.next)
+    }
+
+    fn rename(&mut self, immediate: ImmediateRef) -> ImmediateRef{
+        match self.names.get(&immediate) {
+            Some(x) => *x,
+            None => {
+                let x = self.fresh();
+                self.names.insert(immediate, x);
+                x
+            }
+        }
+    }
+}
+
+///
+/// Replace the call at `index` in `block` with a branch into a renamed copy of the callee.
+/// The instructions after the call move to a continuation block receiving the callee's return values
+fn inline_call(function: &mut Function, block: usize, index: usize, callee: &Function){
+    let mut immediates = Renamer{
+        next: last_immediate(function).0,
+        names: HashMap::new()
+    };
+
+    let mut next_block = last_block(function).0;
+    let blocks = callee.blocks.iter().map(|x| {
+        next_block += 1;
+        (x.label, BlockRef(next_block))
+    }).collect::<HashMap<_, _>>();
+    let continuation = BlockRef(next_block + 1);
+
+    let next_variable = function.locals.iter().map(|x| x.0.0).max().unwrap_or(0);
+    let variables = callee.locals.iter().enumerate().map(|(i, (var, _))| (*var, VariableRef(next_variable + 1 + i as u32))).collect::<HashMap<_, _>>();
+    function.locals.extend(callee.locals.iter().map(|(var, type_)| (variables[var], *type_)));
+
+    let caller = &mut function.blocks[block];
+    let mut after = Block::new(continuation);
+    after.instructions = caller.instructions.split_off(index + 1);
+    let call = caller.instructions.pop().unwrap();
+    let Operation::Invoke(_, args) = call.operation else {
+        unreachable!("call sites are invoke instructions")
+    };
+    after.inputs = call.output.iter().zip(&callee.outputs).map(|(x, type_)| Value(*x, *type_)).collect();
+
+    // Locals start out as zero on every call, so the copies are reset before entering the body
+    for (var, type_) in &callee.locals{
+        let zero = immediates.fresh();
+        caller.instructions.push(Instruction::new(from_constant(Scalar::zero(*type_)).unwrap(), vec![zero]));
+        caller.instructions.push(Instruction::new(Operation::StoreLocal(variables[var], zero), vec![]));
+    }
+    caller.instructions.push(Instruction::new(Operation::Branch(BlockCall::new(blocks[&callee.entry], args)), vec![]));
+
+    let mut body = Vec::with_capacity(callee.blocks.len() + 1);
+    for original in &callee.blocks{
+        let mut copy = Block::new(blocks[&original.label]);
+        copy.inputs = original.inputs.iter().map(|x| Value(immediates.rename(x.immediate()), x.1)).collect();
+
+        for instruction in &original.instructions{
+            let mut operation = instruction.operation.clone();
+            operation.replace_uses(|x| immediates.rename(x));
+            for call in operation.targets_mut(){
+                call.block = blocks[&call.block];
+            }
+            let operation = match operation {
+                Operation::Return(values) => Operation::Branch(BlockCall::new(continuation, values)),
+                Operation::LoadLocal(var) => Operation::LoadLocal(variables[&var]),
+                Operation::StoreLocal(var, value) => Operation::StoreLocal(variables[&var], value),
+                operation => operation
+            };
+            let output = instruction.output.iter().map(|x| immediates.rename(*x)).collect();
+            copy.instructions.push(Instruction::new(operation, output));
+        }
+        body.push(copy);
+    }
+    body.push(after);
+
+    function.blocks.splice(block + 1..block + 1, body);
+}
+
+#[cfg(test)]
+mod tests{
+    use super::super::{tests::check_pass, Pass};
+    use super::Inliner;
+
+    const SOURCE: &str = "
+module 0
+function fn0 \"main\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = invoke fn1(%1)
+    %3 = invoke fn2(%2)
+    %4 = invoke fn3(%3)
+    %5 = invoke fn4(%4)
+    return %5
+}
+function fn1 \"twice\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = add.i32 %1, %1
+    return %2
+}
+function fn2 \"next\"(i32) -> (i32) noinline {
+block0(%1: i32):
+    %2 = const.i32 1
+    %3 = add.i32 %1, %2
+    return %3
+}
+function fn3 \"accumulate\"(i32) -> (i32) inline {
+    local $1: i32
+block0(%1: i32):
+    %2 = load_local $1
+    %3 = add.i32 %2, %1
+    store_local $1, %3
+    return %3
+}
+function fn4 \"down\"(i32) -> (i32) {
+block0(%1: i32):
+    br_gt %1, block1, block2
+block1:
+    %2 = const.i32 3
+    %3 = sub.i32 %1, %2
+    %4 = invoke fn4(%3)
+    return %4
+block2:
+    return %1
+}
+";
+
+    #[test]
+    fn inline_calls(){
+        // Callee blocks, immediates and locals are renamed after the caller's, and the copied local is reset before the body
+        check_pass(&Inliner::default(), SOURCE, "
+module 0
+function fn0 \"main\"(i32) -> (i32) {
+    local $1: i32
+block0(%1: i32):
+    br block1(%1)
+block1(%6: i32):
+    %7 = add.i32 %6, %6
+    br block2(%7)
+block2(%2: i32):
+    %3 = invoke fn2(%2)
+    %8 = const.i32 0
+    store_local $1, %8
+    br block3(%3)
+block3(%9: i32):
+    %10 = load_local $1
+    %11 = add.i32 %10, %9
+    store_local $1, %11
+    br block4(%11)
+block4(%4: i32):
+    %5 = invoke fn4(%4)
+    return %5
+}
+function fn1 \"twice\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = add.i32 %1, %1
+    return %2
+}
+function fn2 \"next\"(i32) -> (i32) noinline {
+block0(%1: i32):
+    %2 = const.i32 1
+    %3 = add.i32 %1, %2
+    return %3
+}
+function fn3 \"accumulate\"(i32) -> (i32) inline {
+    local $1: i32
+block0(%1: i32):
+    %2 = load_local $1
+    %3 = add.i32 %2, %1
+    store_local $1, %3
+    return %3
+}
+function fn4 \"down\"(i32) -> (i32) {
+block0(%1: i32):
+    br_gt %1, block1, block2
+block1:
+    %2 = const.i32 3
+    %3 = sub.i32 %1, %2
+    %4 = invoke fn4(%3)
+    return %4
+block2:
+    return %1
+}
+");
+    }
+
+    #[test]
+    fn threshold(){
+        // Only the function marked inline is small enough for a threshold of 0
+        let module = check_pass(&Inliner::new(0), "
+module 0
+function fn0 \"main\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = invoke fn1(%1)
+    %3 = invoke fn2(%2)
+    %4 = invoke fn2(%3)
+    return %4
+}
+function fn1 \"twice\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = add.i32 %1, %1
+    return %2
+}
+function fn2 \"negate\"(i32) -> (i32) inline {
+block0(%1: i32):
+    %2 = const.i32 0
+    %3 = sub.i32 %2, %1
+    return %3
+}
+", "
+module 0
+function fn0 \"main\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = invoke fn1(%1)
+    br block1(%2)
+block1(%5: i32):
+    %6 = const.i32 0
+    %7 = sub.i32 %6, %5
+    br block2(%7)
+block2(%3: i32):
+    br block3(%3)
+block3(%8: i32):
+    %9 = const.i32 0
+    %10 = sub.i32 %9, %8
+    br block4(%10)
+block4(%4: i32):
+    return %4
+}
+function fn1 \"twice\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = add.i32 %1, %1
+    return %2
+}
+function fn2 \"negate\"(i32) -> (i32) inline {
+block0(%1: i32):
+    %2 = const.i32 0
+    %3 = sub.i32 %2, %1
+    return %3
+}
+");
+        assert!(!Inliner::new(0).run(&mut module.clone()));
+    }
+
+    #[test]
+    fn mutual_recursion(){
+        let source = "
+module 0
+function fn0 \"even\"(i32) -> (i32) inline {
+block0(%1: i32):
+    br_eq %1, block1, block2
+block1:
+    %2 = const.i32 1
+    return %2
+block2:
+    %3 = const.i32 1
+    %4 = sub.i32 %1, %3
+    %5 = invoke fn1(%4)
+    return %5
+}
+function fn1 \"odd\"(i32) -> (i32) inline {
+block0(%1: i32):
+    br_eq %1, block1, block2
+block1:
+    %2 = const.i32 0
+    return %2
+block2:
+    %3 = const.i32 1
+    %4 = sub.i32 %1, %3
+    %5 = invoke fn0(%4)
+    return %5
+}
+";
+        check_pass(&Inliner::default(), source, source);
+    }
+}
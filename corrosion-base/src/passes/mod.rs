@@ -0,0 +1,324 @@
+use std::fmt::Display;
+
+use crate::{verify_module, BlockRef, Function, ImmediateRef, Module, VerifyError};
+
+mod const_fold;
+mod dce;
+mod cse;
+mod simplify_cfg;
+mod inline;
+mod ssa;
+
+pub use const_fold::*;
+pub use dce::*;
+pub use cse::*;
+pub use simplify_cfg::*;
+pub use inline::*;
+pub use ssa::*;
+
+///
+/// A transformation of a module that preserves its behavior
+pub trait Pass{
+    ///
+    /// Name used to toggle the pass in a `PassManager`
+    fn name(&self) -> &str;
+
+    ///
+    /// Returns whether the module changed
+    fn run(&self, module: &mut Module) -> bool;
+}
+
+///
+/// Run a function level transformation on every function of a module
+pub(crate) fn run_on_functions(module: &mut Module, mut f: impl FnMut(&mut Function) -> bool) -> bool{
+    module.functions.iter_mut().fold(false, |changed, x| f(x) | changed)
+}
+
+///
+/// Highest immediate defined in a function, new immediates are numbered after it
+pub(crate) fn last_immediate(function: &Function) -> ImmediateRef{
+    function.blocks.iter()
+        .flat_map(|x| x.inputs.iter().map(|x| x.immediate()).chain(x.instructions.iter().flat_map(|x| x.output.iter().copied())))
+        .max()
+        .unwrap_or(ImmediateRef(0))
+}
+
+///
+/// Highest block label of a function, new blocks are numbered after it
+pub(crate) fn last_block(function: &Function) -> BlockRef{
+    function.blocks.iter().map(|x| x.label).max().unwrap_or(BlockRef(0))
+}
+
+///
+/// Ordered list of passes, rerun until none of the enabled passes change the module
+pub struct PassManager{
+    passes: Vec<(Box<dyn Pass>, bool)>,
+    verify: bool,
+    max_iterations: usize,
+}
+
+impl Default for PassManager{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassManager{
+    pub fn new() -> Self{
+        Self{
+            passes: Vec::new(),
+            verify: false,
+            max_iterations: 8
+        }
+    }
+
+    ///
+    /// Inlining, promotion of locals, constant folding, common subexpression elimination, dead code elimination and cfg cleanup
+    pub fn with_default_passes() -> Self{
+        let mut manager = Self::new();
+        manager.add(Inliner::default())
+            .add(PromoteLocals)
+            .add(ConstantFolding)
+            .add(CommonSubexpressionElimination)
+            .add(DeadCodeElimination)
+            .add(UnreachableBlockElimination)
+            .add(BlockMerging);
+        manager
+    }
+
+    pub fn add(&mut self, pass: impl Pass + 'static) -> &mut Self{
+        self.passes.push((Box::new(pass), true));
+        self
+    }
+
+    ///
+    /// Enable or disable a pass by name, returns false if there is no such pass
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool{
+        let mut found = false;
+        for (_, x) in self.passes.iter_mut().filter(|x| x.0.name() == name){
+            *x = enabled;
+            found = true;
+        }
+        found
+    }
+
+    pub fn passes(&self) -> impl Iterator<Item = (&str, bool)>{
+        self.passes.iter().map(|(pass, enabled)| (pass.name(), *enabled))
+    }
+
+    ///
+    /// Verify the module after every pass that changed it, to find the pass breaking a module
+    pub fn set_verify(&mut self, verify: bool){
+        self.verify = verify;
+    }
+
+    pub fn set_max_iterations(&mut self, max_iterations: usize){
+        self.max_iterations = max_iterations;
+    }
+
+    ///
+    /// Returns whether the module changed
+    pub fn run(&self, module: &mut Module) -> Result<bool, PassError>{
+        let mut changed = false;
+        for _ in 0..self.max_iterations{
+            let mut iteration_changed = false;
+            for (pass, _) in self.passes.iter().filter(|x| x.1){
+                if !pass.run(module){
+                    continue;
+                }
+                iteration_changed = true;
+
+                if self.verify{
+                    verify_module(module).map_err(|errors| PassError{
+                        pass: pass.name().to_string(),
+                        errors
+                    })?;
+                }
+            }
+
+            changed |= iteration_changed;
+            if !iteration_changed{
+                break;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+///
+/// A pass produced a module that does not verify
+#[derive(Clone, Debug)]
+pub struct PassError{
+    pub pass: String,
+    pub errors: Vec<VerifyError>,
+}
+
+impl Display for PassError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "module is invalid after {}", self.pass)?;
+        for err in &self.errors{
+            write!(f, "\n  {}", err)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PassError{}
+
+#[cfg(test)]
+pub(crate) mod tests{
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{parse_module, verify_module, Module};
+
+    use super::{Pass, PassManager};
+
+    ///
+    /// Run a pass on `source` and check that it produces `expected` and reports whether it changed anything
+    pub(crate) fn check_pass(pass: &dyn Pass, source: &str, expected: &str) -> Module{
+        let original = parse_module(source).unwrap();
+        verify_module(&original).unwrap();
+        let expected = parse_module(expected).unwrap().to_string();
+
+        let mut module = original.clone();
+        let changed = pass.run(&mut module);
+        assert_eq!(module.to_string(), expected, "{}", pass.name());
+        assert_eq!(changed, original.to_string() != expected, "{}", pass.name());
+        verify_module(&module).unwrap();
+        module
+    }
+
+    const SOURCE: &str = "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 6
+    %3 = const.i32 7
+    %4 = mul.i32 %2, %3
+    br_gt %4, block1, block2
+block1:
+    %5 = add.i32 %4, %1
+    br block3(%5)
+block2:
+    %6 = sub.i32 %4, %1
+    br block3(%6)
+block3(%7: i32):
+    return %7
+}
+";
+
+    ///
+    /// Changes the module the first `limit` times it runs
+    struct Countdown{
+        runs: Rc<Cell<usize>>,
+        limit: usize,
+    }
+
+    impl Pass for Countdown{
+        fn name(&self) -> &str {
+            "countdown"
+        }
+
+        fn run(&self, _module: &mut Module) -> bool {
+            self.runs.set(self.runs.get() + 1);
+            self.runs.get() <= self.limit
+        }
+    }
+
+    ///
+    /// Drops the terminator of every entry block
+    struct Truncate;
+
+    impl Pass for Truncate{
+        fn name(&self) -> &str {
+            "truncate"
+        }
+
+        fn run(&self, module: &mut Module) -> bool {
+            for function in &mut module.functions{
+                let entry = function.entry;
+                function.blocks.iter_mut().find(|x| x.label == entry).unwrap().instructions.pop();
+            }
+            true
+        }
+    }
+
+    #[test]
+    fn default_passes_reach_a_fixpoint(){
+        let mut module = parse_module(SOURCE).unwrap();
+        assert!(PassManager::with_default_passes().run(&mut module).unwrap());
+        assert_eq!(module.to_string(), parse_module("
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %4 = const.i32 42
+    %5 = add.i32 %4, %1
+    return %5
+}
+").unwrap().to_string());
+
+        assert!(!PassManager::with_default_passes().run(&mut module).unwrap());
+    }
+
+    #[test]
+    fn disabled_passes_do_not_run(){
+        let mut manager = PassManager::with_default_passes();
+        assert!(manager.set_enabled("const-fold", false));
+        assert!(!manager.set_enabled("no-such-pass", false));
+        assert_eq!(manager.passes().collect::<Vec<_>>(), [
+            ("inline", true),
+            ("mem2reg", true),
+            ("const-fold", false),
+            ("cse", true),
+            ("dce", true),
+            ("unreachable-blocks", true),
+            ("merge-blocks", true),
+        ]);
+
+        // Without folding the branch stays conditional, and every block stays reachable
+        let mut module = parse_module(SOURCE).unwrap();
+        assert!(!manager.run(&mut module).unwrap());
+        assert_eq!(module.to_string(), parse_module(SOURCE).unwrap().to_string());
+
+        assert!(manager.set_enabled("const-fold", true));
+        assert!(manager.run(&mut module).unwrap());
+    }
+
+    #[test]
+    fn iterations_are_bounded(){
+        let runs = Rc::new(Cell::new(0));
+        let mut manager = PassManager::new();
+        manager.add(Countdown{ runs: runs.clone(), limit: 5 });
+
+        // Five runs changing the module and one more to find the fixpoint
+        assert!(manager.run(&mut parse_module(SOURCE).unwrap()).unwrap());
+        assert_eq!(runs.get(), 6);
+
+        runs.set(0);
+        manager.set_max_iterations(3);
+        assert!(manager.run(&mut parse_module(SOURCE).unwrap()).unwrap());
+        assert_eq!(runs.get(), 3);
+
+        runs.set(0);
+        manager.set_max_iterations(0);
+        assert!(!manager.run(&mut parse_module(SOURCE).unwrap()).unwrap());
+        assert_eq!(runs.get(), 0);
+    }
+
+    #[test]
+    fn verification_reports_the_breaking_pass(){
+        let mut manager = PassManager::with_default_passes();
+        manager.add(Truncate);
+
+        let mut module = parse_module(SOURCE).unwrap();
+        assert!(manager.run(&mut module).unwrap());
+        assert!(verify_module(&module).is_err());
+
+        manager.set_verify(true);
+        let error = manager.run(&mut parse_module(SOURCE).unwrap()).unwrap_err();
+        assert_eq!(error.pass, "truncate");
+        assert_eq!(error.errors.len(), 1);
+        assert_eq!(error.errors[0].message, "block does not end with a branch or return");
+        assert!(error.to_string().starts_with("module is invalid after truncate\n  fn0 block0: "));
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{analysis::ControlFlowGraph, Function, Module, Operation};
+
+use super::{run_on_functions, Pass};
+
+///
+/// Removes blocks that can't be reached from the entry of their function
+pub struct UnreachableBlockElimination;
+
+impl Pass for UnreachableBlockElimination{
+    fn name(&self) -> &str {
+        "unreachable-blocks"
+    }
+
+    fn run(&self, module: &mut Module) -> bool {
+        run_on_functions(module, remove_unreachable_blocks)
+    }
+}
+
+fn remove_unreachable_blocks(function: &mut Function) -> bool{
+    let reachable = ControlFlowGraph::new(function).reverse_postorder().into_iter().collect::<HashSet<_>>();
+    let before = function.blocks.len();
+    function.blocks.retain(|x| reachable.contains(&x.label));
+    function.blocks.len() != before
+}
+
+///
+/// Appends blocks to their only predecessor when it ends with an unconditional branch to them,
+/// and turns conditional branches with identical targets into unconditional ones
+pub struct BlockMerging;
+
+impl Pass for BlockMerging{
+    fn name(&self) -> &str {
+        "merge-blocks"
+    }
+
+    fn run(&self, module: &mut Module) -> bool {
+        run_on_functions(module, merge_blocks)
+    }
+}
+
+fn merge_blocks(function: &mut Function) -> bool{
+    let mut changed = false;
+
+    for instruction in function.blocks.iter_mut().filter_map(|x| x.instructions.last_mut()){
+        if let [a, b] = &instruction.operation.targets()[..]{
+            if a == b{
+                instruction.operation = Operation::Branch((*a).clone());
+                changed = true;
+            }
+        }
+    }
+
+    // Merging changes the predecessors, so the graph is rebuilt after every merge
+    loop {
+        let cfg = ControlFlowGraph::new(function);
+        let candidate = function.blocks.iter().find_map(|block| {
+            let Some(Operation::Branch(call)) = block.instructions.last().map(|x| &x.operation) else {
+                return None;
+            };
+            let exists = function.blocks.iter().any(|x| x.label == call.block);
+            let mergeable = exists && call.block != block.label && call.block != function.entry && cfg.predecessors(call.block) == [block.label];
+            mergeable.then_some((block.label, call.block))
+        });
+        let Some((predecessor, successor)) = candidate else { break };
+
+        let successor = function.blocks.iter().position(|x| x.label == successor).unwrap();
+        let successor = function.blocks.remove(successor);
+        let predecessor = function.blocks.iter_mut().find(|x| x.label == predecessor).unwrap();
+
+        let Some(Operation::Branch(call)) = predecessor.instructions.pop().map(|x| x.operation) else {
+            unreachable!("the predecessor ends with a branch")
+        };
+        predecessor.instructions.extend(successor.instructions);
+
+        // The inputs of the successor are replaced by the arguments it was branched to with
+        let replacements = successor.inputs.iter().map(|x| x.immediate()).zip(call.args).collect::<HashMap<_, _>>();
+        for instruction in function.blocks.iter_mut().flat_map(|x| x.instructions.iter_mut()){
+            instruction.operation.replace_uses(|x| *replacements.get(&x).unwrap_or(&x));
+        }
+
+        changed = true;
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests{
+    use super::super::tests::check_pass;
+    use super::{BlockMerging, UnreachableBlockElimination};
+
+    #[test]
+    fn unreachable_blocks(){
+        check_pass(&UnreachableBlockElimination, "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    br block2(%1)
+block1:
+    %2 = const.i32 1
+    br block3(%2)
+block2(%3: i32):
+    return %3
+block3(%4: i32):
+    br block1
+}
+", "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    br block2(%1)
+block2(%3: i32):
+    return %3
+}
+");
+    }
+
+    #[test]
+    fn merge_blocks(){
+        // block1 loops to itself and has two predecessors, block5 is its own only predecessor
+        check_pass(&BlockMerging, "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 1
+    br block1(%1)
+block1(%3: i32):
+    %4 = sub.i32 %3, %2
+    br_gt %4, block1(%4), block2
+block2:
+    br_eq %4, block3(%4), block3(%4)
+block3(%5: i32):
+    %6 = add.i32 %5, %2
+    br block4
+block4:
+    return %6
+block5:
+    br block5
+}
+", "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 1
+    br block1(%1)
+block1(%3: i32):
+    %4 = sub.i32 %3, %2
+    br_gt %4, block1(%4), block2
+block2:
+    %6 = add.i32 %4, %2
+    return %6
+block5:
+    br block5
+}
+");
+    }
+
+    #[test]
+    fn entry_is_not_merged(){
+        // block1 is the only predecessor of the entry, which still has to stay the first block
+        let source = "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 1
+    %3 = sub.i32 %1, %2
+    br_gt %3, block1, block2
+block1:
+    br block0(%3)
+block2:
+    return %3
+}
+";
+        check_pass(&BlockMerging, source, source);
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{analysis::{dominance_frontiers, ControlFlowGraph, DominatorTree}, Block, BlockCall, BlockRef, Function, ImmediateRef, Instruction, Module, Operation, Scalar, Value, VariableRef};
+
+use super::{const_fold::from_constant, last_block, last_immediate, run_on_functions, Pass};
+
+///
+/// Replaces locals with immediates, passing their values between blocks as block inputs.
+/// Only locals with a type that has a constant operation are promoted, as they start out as zero.
+/// Unreachable blocks are removed, since loads in them have no value to be replaced with
+pub struct PromoteLocals;
+
+impl Pass for PromoteLocals{
+    fn name(&self) -> &str {
+        "mem2reg"
+    }
+
+    fn run(&self, module: &mut Module) -> bool {
+        run_on_functions(module, promote_locals)
+    }
+}
+
+///
+/// Replaces the inputs of every block but the entry with locals, stored to before branching to the block
+/// and loaded at its start. This is the inverse of `PromoteLocals`
+pub struct DemoteBlockInputs;
+
+impl Pass for DemoteBlockInputs{
+    fn name(&self) -> &str {
+        "reg2mem"
+    }
+
+    fn run(&self, module: &mut Module) -> bool {
+        run_on_functions(module, demote_block_inputs)
+    }
+}
+
+fn promote_locals(function: &mut Function) -> bool{
+    let promoted = function.locals.iter()
+        .filter(|(_, type_)| from_constant(Scalar::zero(*type_)).is_some())
+        .copied()
+        .collect::<Vec<_>>();
+    if promoted.is_empty(){
+        return false;
+    }
+    let types = promoted.iter().copied().collect::<HashMap<_, _>>();
+
+    let reachable = ControlFlowGraph::new(function).reverse_postorder().into_iter().collect::<HashSet<_>>();
+    function.blocks.retain(|x| reachable.contains(&x.label));
+
+    let mut next_immediate = last_immediate(function).0;
+    let mut fresh = || {
+        next_immediate += 1;
+        ImmediateRef(next_immediate)
+    };
+
+    // The entry's inputs are the function's parameters, so when it is a branch target it
+    // is moved behind a new entry block before it can receive the locals as inputs
+    let cfg = ControlFlowGraph::new(function);
+    if !cfg.predecessors(function.entry).is_empty(){
+        let mut entry = Block::new(BlockRef(last_block(function).0 + 1));
+        entry.inputs = function.inputs.iter().map(|x| Value(fresh(), *x)).collect();
+        let args = entry.inputs.iter().map(|x| x.immediate()).collect();
+        entry.instructions.push(Instruction::new(Operation::Branch(BlockCall::new(function.entry, args)), vec![]));
+        function.entry = entry.label;
+        function.blocks.insert(0, entry);
+    }
+
+    let cfg = ControlFlowGraph::new(function);
+    let domtree = DominatorTree::new(&cfg);
+    let frontiers = dominance_frontiers(&cfg, &domtree);
+
+    // A local needs an input in the iterated dominance frontier of the blocks storing to it
+    let mut inputs: HashMap<BlockRef, Vec<VariableRef>> = HashMap::new();
+    for (var, _) in &promoted{
+        let mut worklist = function.blocks.iter()
+            .filter(|x| x.instructions.iter().any(|x| matches!(x.operation, Operation::StoreLocal(v, _) if v == *var)))
+            .map(|x| x.label)
+            .collect::<Vec<_>>();
+        let mut placed = HashSet::new();
+        while let Some(block) = worklist.pop(){
+            for frontier in frontiers.get(&block).into_iter().flatten(){
+                if placed.insert(*frontier){
+                    inputs.entry(*frontier).or_default().push(*var);
+                    worklist.push(*frontier);
+                }
+            }
+        }
+    }
+
+    let index = function.blocks.iter().enumerate().map(|(i, x)| (x.label, i)).collect::<HashMap<_, _>>();
+    let mut replacements = HashMap::new();
+
+    let mut entry_values = HashMap::new();
+    let entry = &mut function.blocks[index[&function.entry]];
+    let zeros = promoted.iter().map(|(var, type_)| {
+        let zero = fresh();
+        entry_values.insert(*var, zero);
+        Instruction::new(from_constant(Scalar::zero(*type_)).unwrap(), vec![zero])
+    }).collect::<Vec<_>>();
+    entry.instructions.splice(0..0, zeros);
+
+    // Walk the dominator tree, the value of a local in a block is the one it had at the end of its immediate dominator
+    let mut visit = vec![(function.entry, entry_values)];
+    while let Some((label, mut values)) = visit.pop(){
+        let block = &mut function.blocks[index[&label]];
+        for var in inputs.get(&label).into_iter().flatten(){
+            let input = fresh();
+            block.inputs.push(Value(input, types[var]));
+            values.insert(*var, input);
+        }
+
+        block.instructions.retain_mut(|instruction| {
+            match instruction.operation {
+                Operation::LoadLocal(var) if types.contains_key(&var) => {
+                    replacements.insert(instruction.output[0], values[&var]);
+                    return false;
+                },
+                Operation::StoreLocal(var, value) if types.contains_key(&var) => {
+                    values.insert(var, *replacements.get(&value).unwrap_or(&value));
+                    return false;
+                },
+                _ => {}
+            }
+            for call in instruction.operation.targets_mut(){
+                call.args.extend(inputs.get(&call.block).into_iter().flatten().map(|x| values[x]));
+            }
+            true
+        });
+
+        visit.extend(domtree.children(label).iter().map(|x| (*x, values.clone())));
+    }
+
+    for instruction in function.blocks.iter_mut().flat_map(|x| x.instructions.iter_mut()){
+        instruction.operation.replace_uses(|x| *replacements.get(&x).unwrap_or(&x));
+    }
+    function.locals.retain(|(var, _)| !types.contains_key(var));
+    true
+}
+
+fn demote_block_inputs(function: &mut Function) -> bool{
+    let mut next_variable = function.locals.iter().map(|x| x.0.0).max().unwrap_or(0);
+    let mut locals = HashMap::new();
+    for block in function.blocks.iter().filter(|x| x.label != function.entry){
+        let vars = block.inputs.iter().map(|input| {
+            next_variable += 1;
+            function.locals.push((VariableRef(next_variable), input.1));
+            VariableRef(next_variable)
+        }).collect::<Vec<_>>();
+        if !vars.is_empty(){
+            locals.insert(block.label, vars);
+        }
+    }
+    if locals.is_empty(){
+        return false;
+    }
+
+    // A conditional branch passing different arguments to the same block can't store both,
+    // so one of the edges gets a block of its own
+    let mut next_block = last_block(function).0;
+    let mut split = Vec::new();
+    for block in &mut function.blocks{
+        let Some(terminator) = block.instructions.last_mut() else { continue };
+        if let [a, b] = &mut terminator.operation.targets_mut()[..]{
+            if a.block == b.block && a.args != b.args && locals.contains_key(&a.block){
+                next_block += 1;
+                let mut edge = Block::new(BlockRef(next_block));
+                let call = std::mem::replace(*b, BlockCall::new(edge.label, vec![]));
+                edge.instructions.push(Instruction::new(Operation::Branch(call), vec![]));
+                split.push(edge);
+            }
+        }
+    }
+    function.blocks.extend(split);
+
+    for block in &mut function.blocks{
+        if let Some(vars) = locals.get(&block.label){
+            let loads = block.inputs.drain(..).zip(vars).map(|(input, var)| Instruction::new(Operation::LoadLocal(*var), vec![input.immediate()]));
+            block.instructions.splice(0..0, loads);
+        }
+
+        let Some(mut terminator) = block.instructions.pop() else { continue };
+        for call in terminator.operation.targets_mut(){
+            let Some(vars) = locals.get(&call.block) else { continue };
+            for (var, arg) in vars.iter().zip(call.args.drain(..)){
+                block.instructions.push(Instruction::new(Operation::StoreLocal(*var, arg), vec![]));
+            }
+        }
+        block.instructions.push(terminator);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::{parse_module, verify_module};
+
+    use super::super::{tests::check_pass, Pass};
+    use super::{DemoteBlockInputs, PromoteLocals};
+
+    #[test]
+    fn promote_loop(){
+        // The header gets an input for each local stored in the loop, passed along the back edge.
+        // The i16 local has no constant operation to start from and stays
+        check_pass(&PromoteLocals, "
+module 0
+function fn0 \"sum\"(i32) -> (i32) {
+    local $1: i32
+    local $2: i32
+    local $3: i16
+block0(%1: i32):
+    br block1
+block1:
+    %2 = load_local $2
+    %3 = sub.i32 %2, %1
+    br_ge %3, block3, block2
+block2:
+    %4 = load_local $1
+    %5 = add.i32 %4, %2
+    store_local $1, %5
+    %6 = const.i32 1
+    %7 = add.i32 %2, %6
+    store_local $2, %7
+    br block1
+block3:
+    %8 = load_local $1
+    return %8
+}
+", "
+module 0
+function fn0 \"sum\"(i32) -> (i32) {
+    local $3: i16
+block0(%1: i32):
+    %9 = const.i32 0
+    %10 = const.i32 0
+    br block1(%9, %10)
+block1(%11: i32, %12: i32):
+    %3 = sub.i32 %12, %1
+    br_ge %3, block3, block2
+block2:
+    %5 = add.i32 %11, %12
+    %6 = const.i32 1
+    %7 = add.i32 %12, %6
+    br block1(%5, %7)
+block3:
+    return %11
+}
+");
+    }
+
+    #[test]
+    fn promote_entry_loop(){
+        // The entry is a branch target, so a new entry passes the parameters to it
+        check_pass(&PromoteLocals, "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+    local $1: i32
+block0(%1: i32):
+    %2 = load_local $1
+    %3 = add.i32 %2, %1
+    store_local $1, %3
+    %4 = const.i32 10
+    %5 = sub.i32 %3, %4
+    br_lt %5, block0(%1), block1
+block1:
+    return %3
+}
+", "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block2(%6: i32):
+    %7 = const.i32 0
+    br block0(%6, %7)
+block0(%1: i32, %8: i32):
+    %3 = add.i32 %8, %1
+    %4 = const.i32 10
+    %5 = sub.i32 %3, %4
+    br_lt %5, block0(%1, %3), block1
+block1:
+    return %3
+}
+");
+    }
+
+    const INPUTS: &str = "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+block0(%1: i32):
+    %2 = const.i32 0
+    br block1(%2)
+block1(%3: i32):
+    %4 = const.i32 1
+    %5 = add.i32 %3, %4
+    %6 = sub.i32 %5, %1
+    br_lt %6, block1(%5), block2(%3, %5)
+block2(%7: i32, %8: i32):
+    %9 = add.i32 %7, %8
+    br_eq %1, block3(%9), block3(%1)
+block3(%10: i32):
+    return %10
+}
+";
+
+    #[test]
+    fn demote_inputs(){
+        // The two edges to block3 pass different values, so one of them is split
+        check_pass(&DemoteBlockInputs, INPUTS, "
+module 0
+function fn0 \"f\"(i32) -> (i32) {
+    local $1: i32
+    local $2: i32
+    local $3: i32
+    local $4: i32
+block0(%1: i32):
+    %2 = const.i32 0
+    store_local $1, %2
+    br block1
+block1:
+    %3 = load_local $1
+    %4 = const.i32 1
+    %5 = add.i32 %3, %4
+    %6 = sub.i32 %5, %1
+    store_local $1, %5
+    store_local $2, %3
+    store_local $3, %5
+    br_lt %6, block1, block2
+block2:
+    %7 = load_local $2
+    %8 = load_local $3
+    %9 = add.i32 %7, %8
+    store_local $4, %9
+    br_eq %1, block3, block4
+block3:
+    %10 = load_local $4
+    return %10
+block4:
+    store_local $4, %1
+    br block3
+}
+");
+    }
+
+    #[test]
+    fn round_trip(){
+        let mut module = parse_module(INPUTS).unwrap();
+        assert!(DemoteBlockInputs.run(&mut module));
+        assert!(PromoteLocals.run(&mut module));
+        assert!(module.functions[0].locals.is_empty());
+        assert!(verify_module(&module).is_ok());
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt::Display;
+
+use crate::Type;
+
+///
+/// A runtime value of one of the base types, used to pass arguments to and results from a backend
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Scalar{
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+
+    ///
+    /// Address into the interpreter's heap
+    Ptr(u64),
+
+    ///
+    /// Managed reference, opaque to the interpreted code
+    Ref(u64),
+}
+
+impl Scalar{
+    pub fn type_(self) -> Type{
+        match self {
+            Scalar::I8(_) => Type::I8,
+            Scalar::I16(_) => Type::I16,
+            Scalar::I32(_) => Type::I32,
+            Scalar::I64(_) => Type::I64,
+            Scalar::F32(_) => Type::F32,
+            Scalar::F64(_) => Type::F64,
+            Scalar::Ptr(_) => Type::Ptr,
+            Scalar::Ref(_) => Type::Ref,
+        }
+    }
+
+    ///
+    /// The zero value of a type, used for locals that are read before they are written
+    pub fn zero(type_: Type) -> Self{
+        match type_ {
+            Type::I8 => Scalar::I8(0),
+            Type::I16 => Scalar::I16(0),
+            Type::I32 => Scalar::I32(0),
+            Type::I64 => Scalar::I64(0),
+            Type::F32 => Scalar::F32(0.0),
+            Type::F64 => Scalar::F64(0.0),
+            Type::Ptr => Scalar::Ptr(0),
+            Type::Ref => Scalar::Ref(0),
+        }
+    }
+
+    ///
+    /// Integer value sign extended to 64 bits, pointers and references are returned as is
+    pub fn as_i64(self) -> Option<i64>{
+        match self {
+            Scalar::I8(x) => Some(x as i64),
+            Scalar::I16(x) => Some(x as i64),
+            Scalar::I32(x) => Some(x as i64),
+            Scalar::I64(x) => Some(x),
+            Scalar::Ptr(x) | Scalar::Ref(x) => Some(x as i64),
+            Scalar::F32(_) | Scalar::F64(_) => None,
+        }
+    }
+
+    ///
+    /// Raw bits of the value, zero extended to 64 bits
+    pub fn to_bits(self) -> u64{
+        match self {
+            Scalar::I8(x) => x as u8 as u64,
+            Scalar::I16(x) => x as u16 as u64,
+            Scalar::I32(x) => x as u32 as u64,
+            Scalar::I64(x) => x as u64,
+            Scalar::F32(x) => x.to_bits() as u64,
+            Scalar::F64(x) => x.to_bits(),
+            Scalar::Ptr(x) | Scalar::Ref(x) => x,
+        }
+    }
+
+    ///
+    /// Value of a type from the low bits of `bits`
+    pub fn from_bits(type_: Type, bits: u64) -> Self{
+        match type_ {
+            Type::I8 => Scalar::I8(bits as i8),
+            Type::I16 => Scalar::I16(bits as i16),
+            Type::I32 => Scalar::I32(bits as i32),
+            Type::I64 => Scalar::I64(bits as i64),
+            Type::F32 => Scalar::F32(f32::from_bits(bits as u32)),
+            Type::F64 => Scalar::F64(f64::from_bits(bits)),
+            Type::Ptr => Scalar::Ptr(bits),
+            Type::Ref => Scalar::Ref(bits),
+        }
+    }
+
+    ///
+    /// Parse a value of a type, integers may be decimal or `0x` prefixed hex and use the unsigned range
+    pub fn parse(type_: Type, text: &str) -> Result<Self, String>{
+        let invalid = || format!("invalid {:?} '{}'", type_, text);
+        match type_ {
+            Type::F32 => text.parse().map(Scalar::F32).map_err(|_| invalid()),
+            Type::F64 => text.parse().map(Scalar::F64).map_err(|_| invalid()),
+            _ => {
+                let (negative, digits) = match text.strip_prefix('-') {
+                    Some(x) => (true, x),
+                    None => (false, text),
+                };
+                let value = match digits.strip_prefix("0x") {
+                    Some(hex) => i128::from_str_radix(hex, 16),
+                    None => digits.parse::<i128>(),
+                }.map_err(|_| invalid())?;
+                let value = if negative { -value } else { value };
+
+                let bits = match type_ {
+                    Type::I8 => 8,
+                    Type::I16 => 16,
+                    Type::I32 => 32,
+                    _ => 64,
+                };
+                let signed = !matches!(type_, Type::Ptr | Type::Ref);
+                let min = if signed { -(1i128 << (bits - 1)) } else { 0 };
+                if value < min || value >= 1i128 << bits{
+                    return Err(format!("{} is out of range for {:?}", text, type_));
+                }
+                Ok(Scalar::from_bits(type_, value as u64))
+            }
+        }
+    }
+
+    ///
+    /// Compares the bit patterns, so that NaNs with the same payload are equal
+    pub fn bitwise_eq(self, other: Scalar) -> bool{
+        match (self, other) {
+            (Scalar::F32(a), Scalar::F32(b)) => a.to_bits() == b.to_bits(),
+            (Scalar::F64(a), Scalar::F64(b)) => a.to_bits() == b.to_bits(),
+            (a, b) => a == b
+        }
+    }
+}
+
+impl Display for Scalar{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scalar::I8(x) => write!(f, "{}", x),
+            Scalar::I16(x) => write!(f, "{}", x),
+            Scalar::I32(x) => write!(f, "{}", x),
+            Scalar::I64(x) => write!(f, "{}", x),
+            Scalar::F32(x) => write!(f, "{}", x),
+            Scalar::F64(x) => write!(f, "{}", x),
+            Scalar::Ptr(x) => write!(f, "{:#x}", x),
+            Scalar::Ref(x) => write!(f, "ref {:#x}", x),
+        }
+    }
+}
@@ -0,0 +1,917 @@
+use std::{fmt::Display, path::Path};
+
+use crate::{Block, BlockCall, BlockRef, Diagnostic, ExportRef, Frontend, Function, FunctionRef, ImmediateRef, Inline, Instruction, Module, ModuleRef, Operation, Translation, Type, Value, VariableRef};
+
+///
+/// Textual form of the ir, written by the `Display` impls and read by `parse_module`
+///
+/// ```text
+/// module 0
+/// export fn1
+///
+/// function fn1 "add"(i32, i32) -> (i32) {
+///     local $1: i32
+/// block1(%1: i32, %2: i32):
+///     %3 = add.i32 %1, %2
+///     return %3
+/// }
+/// ```
+///
+/// `inline` or `noinline` after the outputs sets the function's inline attribute
+pub fn parse_module(source: &str) -> Result<Module, ParseError>{
+    let mut parser = Parser{
+        tokens: lex(source)?,
+        position: 0
+    };
+    parser.module()
+}
+
+///
+/// Parse function definitions and exports into an existing module, replacing functions with the same id.
+/// Returns the functions that were defined
+pub fn parse_into(module: &mut Module, source: &str) -> Result<Vec<FunctionRef>, ParseError>{
+    let mut parser = Parser{
+        tokens: lex(source)?,
+        position: 0
+    };
+    let mut defined = Vec::new();
+    while !parser.at_end(){
+        match parser.item()? {
+            Item::Export(export) => {
+                if !module.exports.contains(&export){
+                    module.exports.push(export);
+                }
+            },
+            Item::Function(function) => {
+                defined.push(function.id);
+                match module.functions.iter_mut().find(|x| x.id == function.id) {
+                    Some(x) => *x = function,
+                    None => module.functions.push(function),
+                }
+            },
+        }
+    }
+    Ok(defined)
+}
+
+///
+/// Number of braces left open at the end of `source`, braces in strings and comments are not counted.
+/// Tells interactive input that continues on the next line apart from complete input
+pub fn open_braces(source: &str) -> Result<isize, ParseError>{
+    Ok(lex(source)?.iter().map(|x| match x.token {
+        Token::Punct('{') => 1,
+        Token::Punct('}') => -1,
+        _ => 0,
+    }).sum())
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError{
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ParseError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError{}
+
+///
+/// Frontend for `.cor` files containing the textual ir
+pub struct TextFrontend;
+
+impl Frontend for TextFrontend{
+    fn name(&self) -> &str {
+        "corrosion"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["cor"]
+    }
+
+    fn detect(&self, _bytes: &[u8]) -> bool {
+        false
+    }
+
+    fn translate(&self, path: &Path, bytes: &[u8]) -> Translation {
+        let mut translation = Translation::default();
+        let location = path.display().to_string();
+
+        let source = match std::str::from_utf8(bytes) {
+            Ok(x) => x,
+            Err(err) => {
+                translation.diagnostics.push(Diagnostic::error(format!("invalid utf-8: {}", err)).at(location));
+                return translation;
+            }
+        };
+
+        match parse_module(source) {
+            Ok(module) => translation.modules.push(module),
+            Err(err) => translation.diagnostics.push(Diagnostic::error(err.message).at(format!("{}:{}:{}", location, err.line, err.column))),
+        }
+
+        translation
+    }
+}
+
+impl Display for Type{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Type::I8 => "i8",
+            Type::I16 => "i16",
+            Type::I32 => "i32",
+            Type::I64 => "i64",
+            Type::F32 => "f32",
+            Type::F64 => "f64",
+            Type::Ptr => "ptr",
+            Type::Ref => "ref",
+        })
+    }
+}
+
+impl std::str::FromStr for Type{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "i8" => Ok(Type::I8),
+            "i16" => Ok(Type::I16),
+            "i32" => Ok(Type::I32),
+            "i64" => Ok(Type::I64),
+            "f32" => Ok(Type::F32),
+            "f64" => Ok(Type::F64),
+            "ptr" => Ok(Type::Ptr),
+            "ref" => Ok(Type::Ref),
+            _ => Err(format!("unknown type '{}'", s))
+        }
+    }
+}
+
+impl Display for ModuleRef{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "module{}", self.0)
+    }
+}
+
+impl Display for FunctionRef{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fn{}", self.0)
+    }
+}
+
+impl Display for BlockRef{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "block{}", self.0)
+    }
+}
+
+impl Display for ImmediateRef{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for VariableRef{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "${}", self.0)
+    }
+}
+
+impl Display for BlockCall{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.block)?;
+        if !self.args.is_empty(){
+            write!(f, "({})", List(&self.args))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Operation{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let aligned = |x: &bool| if *x { " aligned" } else { "" };
+        match self {
+            Operation::ConstI32(x) => write!(f, "const.i32 {}", *x as i32),
+            Operation::ConstI64(x) => write!(f, "const.i64 {}", *x as i64),
+            Operation::ConstF32(x) => write!(f, "const.f32 {:?}", x),
+            Operation::ConstF64(x) => write!(f, "const.f64 {:?}", x),
+            Operation::OffsetPtr1(a, b) => write!(f, "offset_ptr1 {}, {}", a, b),
+            Operation::OffsetPtr2(a, b) => write!(f, "offset_ptr2 {}, {}", a, b),
+            Operation::OffsetPtr4(a, b) => write!(f, "offset_ptr4 {}, {}", a, b),
+            Operation::OffsetPtr8(a, b) => write!(f, "offset_ptr8 {}, {}", a, b),
+            Operation::Add(a, b, t) => write!(f, "add.{} {}, {}", t, a, b),
+            Operation::Sub(a, b, t) => write!(f, "sub.{} {}, {}", t, a, b),
+            Operation::Mul(a, b, t) => write!(f, "mul.{} {}, {}", t, a, b),
+            Operation::Div(a, b, t) => write!(f, "div.{} {}, {}", t, a, b),
+            Operation::Mod(a, b, t) => write!(f, "mod.{} {}, {}", t, a, b),
+            Operation::LoadLocal(var) => write!(f, "load_local {}", var),
+            Operation::StoreLocal(var, value) => write!(f, "store_local {}, {}", var, value),
+            Operation::Read(ptr, t, x) => write!(f, "read.{} {}{}", t, ptr, aligned(x)),
+            Operation::Write(ptr, value, x) => write!(f, "write {}, {}{}", ptr, value, aligned(x)),
+            Operation::BranchIfEq(v, a, b) => write!(f, "br_eq {}, {}, {}", v, a, b),
+            Operation::BranchIfNe(v, a, b) => write!(f, "br_ne {}, {}, {}", v, a, b),
+            Operation::BranchIfLt(v, a, b) => write!(f, "br_lt {}, {}, {}", v, a, b),
+            Operation::BranchIfLe(v, a, b) => write!(f, "br_le {}, {}, {}", v, a, b),
+            Operation::BranchIfGt(v, a, b) => write!(f, "br_gt {}, {}, {}", v, a, b),
+            Operation::BranchIfGe(v, a, b) => write!(f, "br_ge {}, {}, {}", v, a, b),
+            Operation::Branch(a) => write!(f, "br {}", a),
+            Operation::Return(values) if values.is_empty() => write!(f, "return"),
+            Operation::Return(values) => write!(f, "return {}", List(values)),
+            Operation::Invoke(function, args) => write!(f, "invoke {}({})", function, List(args)),
+        }
+    }
+}
+
+impl Display for Instruction{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.output.is_empty(){
+            write!(f, "{} = ", List(&self.output))?;
+        }
+        write!(f, "{}", self.operation)
+    }
+}
+
+impl Display for Block{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)?;
+        if !self.inputs.is_empty(){
+            let inputs = self.inputs.iter().map(|x| format!("{}: {}", x.0, x.1)).collect::<Vec<_>>();
+            write!(f, "({})", inputs.join(", "))?;
+        }
+        writeln!(f, ":")?;
+        for instruction in &self.instructions{
+            writeln!(f, "    {}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Function{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "function {}", self.id)?;
+        if !self.name.is_empty(){
+            write!(f, " {:?}", self.name)?;
+        }
+        write!(f, "({})", List(&self.inputs))?;
+        if !self.outputs.is_empty(){
+            write!(f, " -> ({})", List(&self.outputs))?;
+        }
+        match self.inline {
+            Inline::Auto => {},
+            Inline::Always => write!(f, " inline")?,
+            Inline::Never => write!(f, " noinline")?,
+        }
+        writeln!(f, " {{")?;
+        for (var, type_) in &self.locals{
+            writeln!(f, "    local {}: {}", var, type_)?;
+        }
+        if self.blocks.first().map(|x| x.label) != Some(self.entry){
+            writeln!(f, "    entry {}", self.entry)?;
+        }
+        for block in &self.blocks{
+            write!(f, "{}", block)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Module{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "module {}", self.id.0)?;
+        for export in &self.exports{
+            writeln!(f, "export {}", FunctionRef(export.0))?;
+        }
+        for function in &self.functions{
+            writeln!(f)?;
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+struct List<'a, T>(&'a [T]);
+
+impl<'a, T: Display> Display for List<'a, T>{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, x) in self.0.iter().enumerate(){
+            if i > 0{
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", x)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token{
+    Ident(String),
+    Immediate(u32),
+    Local(u32),
+    Number(String),
+    Str(String),
+    Punct(char),
+    Arrow,
+}
+
+impl Display for Token{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(x) | Token::Number(x) => write!(f, "'{}'", x),
+            Token::Immediate(x) => write!(f, "'%{}'", x),
+            Token::Local(x) => write!(f, "'${}'", x),
+            Token::Str(x) => write!(f, "{:?}", x),
+            Token::Punct(x) => write!(f, "'{}'", x),
+            Token::Arrow => write!(f, "'->'"),
+        }
+    }
+}
+
+struct Spanned{
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+fn lex(source: &str) -> Result<Vec<Spanned>, ParseError>{
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let (mut line, mut column) = (1, 1);
+
+    macro_rules! bump {
+        () => {{
+            let c = chars.next();
+            if c == Some('\n'){
+                line += 1;
+                column = 1;
+            }
+            else{
+                column += 1;
+            }
+            c
+        }};
+    }
+
+    while let Some(&c) = chars.peek(){
+        let (start_line, start_column) = (line, column);
+        let error = |message: String| ParseError{ line: start_line, column: start_column, message };
+
+        let token = match c {
+            _ if c.is_whitespace() => {
+                bump!();
+                continue;
+            },
+            ';' => {
+                while chars.peek().is_some_and(|x| *x != '\n'){
+                    bump!();
+                }
+                continue;
+            },
+            '(' | ')' | '{' | '}' | ',' | ':' | '=' => {
+                bump!();
+                Token::Punct(c)
+            },
+            '%' | '$' => {
+                bump!();
+                let mut digits = String::new();
+                while let Some(x) = chars.peek().filter(|x| x.is_ascii_digit()){
+                    digits.push(*x);
+                    bump!();
+                }
+                let index = digits.parse::<u32>().map_err(|_| error(format!("expected an index after '{}'", c)))?;
+                if c == '%' { Token::Immediate(index) } else { Token::Local(index) }
+            },
+            '"' => {
+                bump!();
+                let mut string = String::new();
+                loop {
+                    match bump!() {
+                        Some('"') => break,
+                        Some('\\') => match bump!() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some('r') => string.push('\r'),
+                            Some('0') => string.push('\0'),
+                            Some(x @ ('\\' | '"' | '\'')) => string.push(x),
+                            Some('u') => {
+                                let mut digits = String::new();
+                                if bump!() != Some('{'){
+                                    return Err(error("expected '{' in unicode escape".to_string()));
+                                }
+                                loop {
+                                    match bump!() {
+                                        Some('}') => break,
+                                        Some(x) => digits.push(x),
+                                        None => return Err(error("unterminated unicode escape".to_string())),
+                                    }
+                                }
+                                let c = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32).ok_or(error(format!("invalid unicode escape '{}'", digits)))?;
+                                string.push(c);
+                            },
+                            x => return Err(error(format!("invalid escape {:?}", x))),
+                        },
+                        Some(x) => string.push(x),
+                        None => return Err(error("unterminated string".to_string())),
+                    }
+                }
+                Token::Str(string)
+            },
+            '-' | '+' | '0'..='9' => {
+                bump!();
+                if c == '-' && chars.peek() == Some(&'>'){
+                    bump!();
+                    Token::Arrow
+                }
+                else{
+                    let mut number = c.to_string();
+                    while let Some(&x) = chars.peek(){
+                        let exponent_sign = (x == '-' || x == '+') && number.ends_with(['e', 'E']) && !number.contains(['x', 'X']);
+                        if !(x.is_ascii_alphanumeric() || x == '_' || x == '.' || exponent_sign){
+                            break;
+                        }
+                        number.push(x);
+                        bump!();
+                    }
+                    Token::Number(number)
+                }
+            },
+            _ if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&x) = chars.peek().filter(|x| x.is_alphanumeric() || **x == '_' || **x == '.'){
+                    ident.push(x);
+                    bump!();
+                }
+                Token::Ident(ident)
+            },
+            _ => return Err(error(format!("unexpected character {:?}", c)))
+        };
+
+        tokens.push(Spanned{
+            token,
+            line: start_line,
+            column: start_column
+        });
+    }
+
+    Ok(tokens)
+}
+
+enum Item{
+    Export(ExportRef),
+    Function(Function),
+}
+
+struct Parser{
+    tokens: Vec<Spanned>,
+    position: usize,
+}
+
+impl Parser{
+    fn at_end(&self) -> bool{
+        self.position >= self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<&Token>{
+        self.tokens.get(self.position).map(|x| &x.token)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError>{
+        let (line, column) = match self.tokens.get(self.position).or(self.tokens.last()) {
+            Some(x) => (x.line, x.column),
+            None => (1, 1),
+        };
+        Err(ParseError{
+            line,
+            column,
+            message: message.into()
+        })
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError>{
+        match self.tokens.get(self.position) {
+            Some(x) => {
+                self.position += 1;
+                Ok(x.token.clone())
+            },
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool{
+        if self.peek() == Some(token){
+            self.position += 1;
+            true
+        }
+        else{
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParseError>{
+        match self.peek() {
+            Some(x) if *x == token => {
+                self.position += 1;
+                Ok(())
+            },
+            Some(x) => self.error(format!("expected {}, found {}", token, x)),
+            None => self.error(format!("expected {}, found end of input", token)),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool{
+        matches!(self.peek(), Some(Token::Ident(x)) if x == keyword)
+    }
+
+    fn ident(&mut self) -> Result<String, ParseError>{
+        match self.peek().cloned() {
+            Some(Token::Ident(x)) => {
+                self.position += 1;
+                Ok(x)
+            },
+            Some(x) => self.error(format!("expected an identifier, found {}", x)),
+            None => self.error("expected an identifier, found end of input"),
+        }
+    }
+
+    fn prefixed(&mut self, prefix: &str) -> Result<u32, ParseError>{
+        let ident = self.ident()?;
+        match ident.strip_prefix(prefix).and_then(|x| x.parse().ok()) {
+            Some(x) => Ok(x),
+            None => {
+                self.position -= 1;
+                self.error(format!("expected {}N, found '{}'", prefix, ident))
+            }
+        }
+    }
+
+    fn function_ref(&mut self) -> Result<FunctionRef, ParseError>{
+        self.prefixed("fn").map(FunctionRef)
+    }
+
+    fn block_ref(&mut self) -> Result<BlockRef, ParseError>{
+        self.prefixed("block").map(BlockRef)
+    }
+
+    fn is_block_ref(&self) -> bool{
+        matches!(self.peek(), Some(Token::Ident(x)) if x.strip_prefix("block").is_some_and(|x| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit())))
+    }
+
+    fn type_(&mut self) -> Result<Type, ParseError>{
+        let ident = self.ident()?;
+        ident.parse().or_else(|err: String| {
+            self.position -= 1;
+            self.error(err)
+        })
+    }
+
+    fn immediate(&mut self) -> Result<ImmediateRef, ParseError>{
+        match self.next()? {
+            Token::Immediate(x) => Ok(ImmediateRef(x)),
+            x => {
+                self.position -= 1;
+                self.error(format!("expected an immediate, found {}", x))
+            }
+        }
+    }
+
+    fn local(&mut self) -> Result<VariableRef, ParseError>{
+        match self.next()? {
+            Token::Local(x) => Ok(VariableRef(x)),
+            x => {
+                self.position -= 1;
+                self.error(format!("expected a local, found {}", x))
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<String, ParseError>{
+        match self.next()? {
+            Token::Number(x) | Token::Ident(x) => Ok(x),
+            x => {
+                self.position -= 1;
+                self.error(format!("expected a number, found {}", x))
+            }
+        }
+    }
+
+    fn integer(&mut self, min: i128, max: i128) -> Result<i128, ParseError>{
+        let text = self.number()?;
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(x) => (true, x),
+            None => (false, text.strip_prefix('+').unwrap_or(&text)),
+        };
+        let digits = digits.replace('_', "");
+        let value = match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+            Some(hex) => i128::from_str_radix(hex, 16),
+            None => digits.parse::<i128>(),
+        };
+        match value.map(|x| if negative { -x } else { x }) {
+            Ok(x) if x >= min && x <= max => Ok(x),
+            _ => {
+                self.position -= 1;
+                self.error(format!("invalid integer '{}'", text))
+            }
+        }
+    }
+
+    fn float<T: std::str::FromStr>(&mut self) -> Result<T, ParseError>{
+        let text = self.number()?;
+        text.parse().or_else(|_| {
+            self.position -= 1;
+            self.error(format!("invalid float '{}'", text))
+        })
+    }
+
+    fn list<T>(&mut self, mut element: impl FnMut(&mut Self) -> Result<T, ParseError>) -> Result<Vec<T>, ParseError>{
+        self.expect(Token::Punct('('))?;
+        let mut elements = Vec::new();
+        if !self.eat(&Token::Punct(')')){
+            loop {
+                elements.push(element(self)?);
+                if self.eat(&Token::Punct(')')){
+                    break;
+                }
+                self.expect(Token::Punct(','))?;
+            }
+        }
+        Ok(elements)
+    }
+
+    fn module(&mut self) -> Result<Module, ParseError>{
+        let id = if self.is_keyword("module"){
+            self.position += 1;
+            self.integer(0, u32::MAX as i128)? as u32
+        }
+        else{
+            0
+        };
+        let mut module = Module::new(ModuleRef(id));
+
+        while !self.at_end(){
+            match self.item()? {
+                Item::Export(x) => module.exports.push(x),
+                Item::Function(x) => {
+                    if module.functions.iter().any(|f| f.id == x.id){
+                        return self.error(format!("{} is defined more than once", x.id));
+                    }
+                    module.functions.push(x);
+                },
+            }
+        }
+
+        Ok(module)
+    }
+
+    fn item(&mut self) -> Result<Item, ParseError>{
+        let keyword = self.ident()?;
+        match keyword.as_str() {
+            "export" => Ok(Item::Export(self.function_ref()?.into())),
+            "function" => self.function().map(Item::Function),
+            _ => {
+                self.position -= 1;
+                self.error(format!("expected 'function' or 'export', found '{}'", keyword))
+            }
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, ParseError>{
+        let mut function = Function::new(self.function_ref()?);
+        if let Some(Token::Str(name)) = self.peek().cloned(){
+            self.position += 1;
+            function.name = name;
+        }
+        function.inputs = self.list(Self::type_)?;
+        if self.eat(&Token::Arrow){
+            function.outputs = self.list(Self::type_)?;
+        }
+        if self.is_keyword("inline"){
+            self.position += 1;
+            function.inline = Inline::Always;
+        }
+        else if self.is_keyword("noinline"){
+            self.position += 1;
+            function.inline = Inline::Never;
+        }
+        self.expect(Token::Punct('{'))?;
+
+        while self.is_keyword("local"){
+            self.position += 1;
+            let var = self.local()?;
+            self.expect(Token::Punct(':'))?;
+            function.locals.push((var, self.type_()?));
+        }
+
+        let entry = if self.is_keyword("entry"){
+            self.position += 1;
+            Some(self.block_ref()?)
+        }
+        else{
+            None
+        };
+
+        while !self.eat(&Token::Punct('}')){
+            function.blocks.push(self.block()?);
+        }
+
+        function.entry = match (entry, function.blocks.first()) {
+            (Some(x), _) => x,
+            (None, Some(x)) => x.label,
+            (None, None) => return self.error(format!("{} has no blocks", function.id)),
+        };
+
+        Ok(function)
+    }
+
+    fn block(&mut self) -> Result<Block, ParseError>{
+        let mut block = Block::new(self.block_ref()?);
+        if self.peek() == Some(&Token::Punct('(')){
+            block.inputs = self.list(|x| {
+                let immediate = x.immediate()?;
+                x.expect(Token::Punct(':'))?;
+                Ok(Value(immediate, x.type_()?))
+            })?;
+        }
+        self.expect(Token::Punct(':'))?;
+
+        while !self.is_block_ref() && self.peek() != Some(&Token::Punct('}')){
+            block.instructions.push(self.instruction()?);
+        }
+
+        Ok(block)
+    }
+
+    fn block_call(&mut self) -> Result<BlockCall, ParseError>{
+        let block = self.block_ref()?;
+        let args = if self.peek() == Some(&Token::Punct('(')){
+            self.list(Self::immediate)?
+        }
+        else{
+            Vec::new()
+        };
+        Ok(BlockCall::new(block, args))
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, ParseError>{
+        let mut output = Vec::new();
+        if let Some(Token::Immediate(_)) = self.peek(){
+            loop {
+                output.push(self.immediate()?);
+                if self.eat(&Token::Punct('=')){
+                    break;
+                }
+                self.expect(Token::Punct(','))?;
+            }
+        }
+
+        let name = self.ident()?;
+        let (opcode, type_) = match name.split_once('.') {
+            Some((opcode, type_)) => (opcode, Some(type_.parse::<Type>().or_else(|err| {
+                self.position -= 1;
+                self.error(err)
+            })?)),
+            None => (name.as_str(), None),
+        };
+
+        let binary = |x: &mut Self| -> Result<(ImmediateRef, ImmediateRef), ParseError>{
+            let a = x.immediate()?;
+            x.expect(Token::Punct(','))?;
+            Ok((a, x.immediate()?))
+        };
+        let branch = |x: &mut Self| -> Result<(ImmediateRef, BlockCall, BlockCall), ParseError>{
+            let value = x.immediate()?;
+            x.expect(Token::Punct(','))?;
+            let then = x.block_call()?;
+            x.expect(Token::Punct(','))?;
+            Ok((value, then, x.block_call()?))
+        };
+        let aligned = |x: &mut Self| if x.is_keyword("aligned") {
+            x.position += 1;
+            true
+        } else { false };
+
+        let operation = match (opcode, type_) {
+            ("const", Some(Type::I32)) => Operation::ConstI32(self.integer(i32::MIN as i128, u32::MAX as i128)? as u32),
+            ("const", Some(Type::I64)) => Operation::ConstI64(self.integer(i64::MIN as i128, u64::MAX as i128)? as u64),
+            ("const", Some(Type::F32)) => Operation::ConstF32(self.float()?),
+            ("const", Some(Type::F64)) => Operation::ConstF64(self.float()?),
+            ("offset_ptr1", None) => binary(self).map(|(a, b)| Operation::OffsetPtr1(a, b))?,
+            ("offset_ptr2", None) => binary(self).map(|(a, b)| Operation::OffsetPtr2(a, b))?,
+            ("offset_ptr4", None) => binary(self).map(|(a, b)| Operation::OffsetPtr4(a, b))?,
+            ("offset_ptr8", None) => binary(self).map(|(a, b)| Operation::OffsetPtr8(a, b))?,
+            ("add", Some(t)) => binary(self).map(|(a, b)| Operation::Add(a, b, t))?,
+            ("sub", Some(t)) => binary(self).map(|(a, b)| Operation::Sub(a, b, t))?,
+            ("mul", Some(t)) => binary(self).map(|(a, b)| Operation::Mul(a, b, t))?,
+            ("div", Some(t)) => binary(self).map(|(a, b)| Operation::Div(a, b, t))?,
+            ("mod", Some(t)) => binary(self).map(|(a, b)| Operation::Mod(a, b, t))?,
+            ("load_local", None) => Operation::LoadLocal(self.local()?),
+            ("store_local", None) => {
+                let var = self.local()?;
+                self.expect(Token::Punct(','))?;
+                Operation::StoreLocal(var, self.immediate()?)
+            },
+            ("read", Some(t)) => {
+                let ptr = self.immediate()?;
+                Operation::Read(ptr, t, aligned(self))
+            },
+            ("write", None) => {
+                let (ptr, value) = binary(self)?;
+                Operation::Write(ptr, value, aligned(self))
+            },
+            ("br_eq", None) => branch(self).map(|(v, a, b)| Operation::BranchIfEq(v, a, b))?,
+            ("br_ne", None) => branch(self).map(|(v, a, b)| Operation::BranchIfNe(v, a, b))?,
+            ("br_lt", None) => branch(self).map(|(v, a, b)| Operation::BranchIfLt(v, a, b))?,
+            ("br_le", None) => branch(self).map(|(v, a, b)| Operation::BranchIfLe(v, a, b))?,
+            ("br_gt", None) => branch(self).map(|(v, a, b)| Operation::BranchIfGt(v, a, b))?,
+            ("br_ge", None) => branch(self).map(|(v, a, b)| Operation::BranchIfGe(v, a, b))?,
+            ("br", None) => Operation::Branch(self.block_call()?),
+            ("return", None) => {
+                let mut values = Vec::new();
+                if let Some(Token::Immediate(_)) = self.peek(){
+                    values.push(self.immediate()?);
+                    while self.eat(&Token::Punct(',')){
+                        values.push(self.immediate()?);
+                    }
+                }
+                Operation::Return(values)
+            },
+            ("invoke", None) => {
+                let function = self.function_ref()?;
+                Operation::Invoke(function, self.list(Self::immediate)?)
+            },
+            _ => {
+                self.position -= 1;
+                return self.error(format!("unknown operation '{}'", name));
+            }
+        };
+
+        Ok(Instruction::new(operation, output))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const SOURCE: &str = "module 3
+export fn1
+
+function fn1 \"add\"(i32, i32) -> (i32) {
+    local $1: i32
+block1(%1: i32, %2: i32):
+    %3 = add.i32 %1, %2
+    store_local $1, %3
+    br_lt %3, block2(%3), block2(%1)
+block2(%4: i32):
+    return %4
+}
+";
+
+    fn error(source: &str) -> (usize, usize, String){
+        let err = parse_module(source).expect_err("expected a parse error");
+        (err.line, err.column, err.message)
+    }
+
+    #[test]
+    fn printed_module_parses_back(){
+        let module = parse_module(SOURCE).unwrap();
+        assert_eq!(module.to_string(), SOURCE);
+        assert_eq!(parse_module(&module.to_string()).unwrap().to_string(), SOURCE);
+    }
+
+    #[test]
+    fn lexer_errors_point_at_the_offending_character(){
+        assert_eq!(error("function fn1() {\nblock1:\n    @\n}"), (3, 5, "unexpected character '@'".to_string()));
+        assert_eq!(error("function fn1 \"name() {"), (1, 14, "unterminated string".to_string()));
+        assert_eq!(error("function fn1 \"\\q\"() {"), (1, 14, "invalid escape Some('q')".to_string()));
+        assert_eq!(error("function fn1() {\nblock1:\n    return %\n}"), (3, 12, "expected an index after '%'".to_string()));
+    }
+
+    #[test]
+    fn parser_errors_point_at_the_offending_token(){
+        assert_eq!(error("function fn1() {\nblock1:\n    frob\n}"), (3, 5, "unknown operation 'frob'".to_string()));
+        assert_eq!(error("function fn1() {\nblock1:\n    %1 = const.i32 4294967296\n}"), (3, 20, "invalid integer '4294967296'".to_string()));
+        assert_eq!(error("function fn1() {\nblock1:\n    %1 = const.u32 1\n}"), (3, 10, "unknown type 'u32'".to_string()));
+        assert_eq!(error("function fn1() {\nblock1\n    return\n}"), (3, 5, "expected ':', found 'return'".to_string()));
+        assert_eq!(error("fn1"), (1, 1, "expected 'function' or 'export', found 'fn1'".to_string()));
+        assert_eq!(error("export block1"), (1, 8, "expected fnN, found 'block1'".to_string()));
+    }
+
+    #[test]
+    fn structural_errors(){
+        assert_eq!(error("function fn1() {\n}"), (2, 1, "fn1 has no blocks".to_string()));
+        assert_eq!(error("function fn1() {\nblock1:\n    return\n}\nfunction fn1() {\nblock1:\n    return\n}"), (8, 1, "fn1 is defined more than once".to_string()));
+        assert_eq!(error("function fn1() {\nblock1:\n    return"), (3, 5, "expected an identifier, found end of input".to_string()));
+    }
+
+    #[test]
+    fn open_braces_skip_strings_and_comments(){
+        assert_eq!(open_braces("function fn1 \"{\"() -> () { ; }").unwrap(), 1);
+        assert_eq!(open_braces("}\n{ {").unwrap(), 1);
+        assert_eq!(open_braces("").unwrap(), 0);
+        assert!(open_braces("function fn1 \"{").is_err());
+    }
+}
@@ -0,0 +1,372 @@
+use std::{collections::{HashMap, HashSet}, fmt::Display};
+
+use crate::{analysis::{ControlFlowGraph, DominatorTree}, BlockCall, BlockRef, Function, FunctionRef, ImmediateRef, Module, Operation, Type};
+
+///
+/// A violation of the ir's typing or structure rules
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct VerifyError{
+    pub function: FunctionRef,
+    pub block: Option<BlockRef>,
+    pub instruction: Option<usize>,
+    pub message: String,
+}
+
+impl Display for VerifyError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.function)?;
+        if let Some(block) = self.block{
+            write!(f, " {}", block)?;
+        }
+        if let Some(instruction) = self.instruction{
+            write!(f, " instruction {}", instruction)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for VerifyError{}
+
+pub fn verify_module(module: &Module) -> Result<(), Vec<VerifyError>>{
+    let mut errors = Vec::new();
+
+    let mut ids = HashSet::new();
+    let mut names = HashSet::new();
+    for function in &module.functions{
+        if !ids.insert(function.id){
+            errors.push(VerifyError { function: function.id, block: None, instruction: None, message: "duplicate function id".to_string() });
+        }
+        if !function.name.is_empty() && !names.insert(&function.name){
+            errors.push(VerifyError { function: function.id, block: None, instruction: None, message: format!("duplicate function name '{}'", function.name) });
+        }
+        if let Err(x) = verify_function(module, function){
+            errors.extend(x);
+        }
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+///
+/// Types of every immediate defined in a function, by block inputs or instruction outputs
+pub fn immediate_types(module: &Module, function: &Function) -> HashMap<ImmediateRef, Type>{
+    let mut types = HashMap::new();
+    for block in &function.blocks{
+        for input in &block.inputs{
+            types.insert(input.immediate(), input.type_());
+        }
+        for instruction in &block.instructions{
+            for (immediate, type_) in instruction.immediates().iter().zip(output_types(module, function, instruction.operation())){
+                types.insert(*immediate, type_);
+            }
+        }
+    }
+    types
+}
+
+///
+/// Types of the values an operation produces
+pub fn output_types(module: &Module, function: &Function, operation: &Operation) -> Vec<Type>{
+    match operation {
+        Operation::ConstI32(_) => vec![Type::I32],
+        Operation::ConstI64(_) => vec![Type::I64],
+        Operation::ConstF32(_) => vec![Type::F32],
+        Operation::ConstF64(_) => vec![Type::F64],
+        Operation::OffsetPtr1(..) | Operation::OffsetPtr2(..) | Operation::OffsetPtr4(..) | Operation::OffsetPtr8(..) => vec![Type::Ptr],
+        Operation::Add(_, _, x) | Operation::Sub(_, _, x) | Operation::Mul(_, _, x) | Operation::Div(_, _, x) | Operation::Mod(_, _, x) => vec![*x],
+        Operation::LoadLocal(var) => function.locals.iter().filter(|x| x.0 == *var).map(|x| x.1).collect(),
+        Operation::Read(_, x, _) => vec![*x],
+        Operation::Invoke(callee, _) => module.functions.iter().filter(|x| x.id == *callee).flat_map(|x| x.outputs.clone()).collect(),
+        _ => Vec::with_capacity(0)
+    }
+}
+
+pub fn verify_function(module: &Module, function: &Function) -> Result<(), Vec<VerifyError>>{
+    let mut errors = Vec::new();
+    let mut error = |block: Option<BlockRef>, instruction: Option<usize>, message: String| errors.push(VerifyError{
+        function: function.id,
+        block,
+        instruction,
+        message
+    });
+
+    let types = immediate_types(module, function);
+
+    let mut labels = HashSet::new();
+    let mut defined = HashMap::new();
+    for block in &function.blocks{
+        if !labels.insert(block.label){
+            error(Some(block.label), None, "duplicate block label".to_string());
+        }
+        let outputs = block.inputs.iter().map(|x| x.immediate()).chain(block.instructions.iter().flat_map(|x| x.immediates().iter().copied()));
+        for immediate in outputs{
+            if defined.insert(immediate, block.label).is_some(){
+                error(Some(block.label), None, format!("{} is defined more than once", immediate));
+            }
+        }
+    }
+
+    let domtree = DominatorTree::new(&ControlFlowGraph::new(function));
+
+    match function.blocks.iter().find(|x| x.label == function.entry) {
+        Some(entry) => {
+            if !entry.inputs.iter().map(|x| x.type_()).eq(function.inputs.iter().copied()){
+                error(Some(entry.label), None, format!("entry inputs {:?} does not match the function inputs {:?}", entry.inputs.iter().map(|x| x.type_()).collect::<Vec<_>>(), function.inputs));
+            }
+        },
+        None => error(None, None, format!("entry {} is not a block of the function", function.entry)),
+    }
+
+    for block in &function.blocks{
+        match block.instructions.last() {
+            Some(x) if x.operation().is_terminator() => {},
+            _ => error(Some(block.label), None, "block does not end with a branch or return".to_string()),
+        }
+
+        let mut available = block.inputs.iter().map(|x| x.immediate()).collect::<HashSet<_>>();
+        for (index, instruction) in block.instructions.iter().enumerate(){
+            let mut check = |message: Option<String>| if let Some(message) = message { error(Some(block.label), Some(index), message) };
+            let operation = instruction.operation();
+
+            if operation.is_terminator() && index + 1 != block.instructions.len(){
+                check(Some("terminator in the middle of a block".to_string()));
+            }
+
+            // Immediates defined in this block must be defined before they are used, others must be defined in a dominating block.
+            // Unreachable blocks are never executed and are not checked for dominance
+            let type_of = |x: &ImmediateRef| -> Result<Type, String>{
+                let (Some(type_), Some(def)) = (types.get(x), defined.get(x)) else {
+                    return Err(format!("{} is not defined", x));
+                };
+                if *def == block.label && !available.contains(x){
+                    return Err(format!("{} is used before it is defined", x));
+                }
+                if *def != block.label && domtree.is_reachable(block.label) && !domtree.dominates(*def, block.label){
+                    return Err(format!("{} is defined in {}, which does not dominate its use", x, def));
+                }
+                Ok(*type_)
+            };
+
+            let expected_outputs = output_types(module, function, operation).len();
+            if instruction.immediates().len() != expected_outputs{
+                check(Some(format!("expected {} outputs, found {}", expected_outputs, instruction.immediates().len())));
+            }
+
+            check(verify_operation(module, function, operation, &type_of).err());
+
+            available.extend(instruction.immediates().iter().copied());
+        }
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+fn verify_operation(module: &Module, function: &Function, operation: &Operation, type_of: &dyn Fn(&ImmediateRef) -> Result<Type, String>) -> Result<(), String>{
+    let expect = |x: &ImmediateRef, expected: Type| -> Result<(), String>{
+        let found = type_of(x)?;
+        if found != expected{
+            return Err(format!("expected {} to be {}, found {}", x, expected, found));
+        }
+        Ok(())
+    };
+
+    let local = |var| function.locals.iter().find(|x| x.0 == var).map(|x| x.1).ok_or(format!("{} is not a local of the function", var));
+
+    let target = |call: &BlockCall| -> Result<(), String>{
+        let block = function.blocks.iter().find(|x| x.label == call.block).ok_or(format!("{} is not a block of the function", call.block))?;
+        if block.inputs.len() != call.args.len(){
+            return Err(format!("{} expects {} arguments, found {}", call.block, block.inputs.len(), call.args.len()));
+        }
+        for (input, arg) in block.inputs.iter().zip(&call.args){
+            expect(arg, input.type_())?;
+        }
+        Ok(())
+    };
+
+    match operation {
+        Operation::ConstI32(_) |
+        Operation::ConstI64(_) |
+        Operation::ConstF32(_) |
+        Operation::ConstF64(_) => Ok(()),
+        Operation::OffsetPtr1(ptr, offset) |
+        Operation::OffsetPtr2(ptr, offset) |
+        Operation::OffsetPtr4(ptr, offset) |
+        Operation::OffsetPtr8(ptr, offset) => {
+            expect(ptr, Type::Ptr)?;
+            let offset_type = type_of(offset)?;
+            if !offset_type.is_integer(){
+                return Err(format!("expected {} to be an integer, found {}", offset, offset_type));
+            }
+            Ok(())
+        },
+        Operation::Add(a, b, type_) |
+        Operation::Sub(a, b, type_) |
+        Operation::Mul(a, b, type_) |
+        Operation::Div(a, b, type_) |
+        Operation::Mod(a, b, type_) => {
+            if !type_.is_numeric(){
+                return Err(format!("arithmetic on non numeric type {}", type_));
+            }
+            expect(a, *type_)?;
+            expect(b, *type_)
+        },
+        Operation::LoadLocal(var) => local(*var).map(|_| ()),
+        Operation::StoreLocal(var, value) => expect(value, local(*var)?),
+        Operation::Read(ptr, _, _) => expect(ptr, Type::Ptr),
+        Operation::Write(ptr, value, _) => {
+            expect(ptr, Type::Ptr)?;
+            type_of(value).map(|_| ())
+        },
+        Operation::BranchIfEq(value, then, else_) |
+        Operation::BranchIfNe(value, then, else_) |
+        Operation::BranchIfLt(value, then, else_) |
+        Operation::BranchIfLe(value, then, else_) |
+        Operation::BranchIfGt(value, then, else_) |
+        Operation::BranchIfGe(value, then, else_) => {
+            let type_ = type_of(value)?;
+            if type_.is_reference(){
+                return Err(format!("cannot compare reference {} against zero", value));
+            }
+            target(then)?;
+            target(else_)
+        },
+        Operation::Branch(call) => target(call),
+        Operation::Return(values) => {
+            if values.len() != function.outputs.len(){
+                return Err(format!("expected {} return values, found {}", function.outputs.len(), values.len()));
+            }
+            for (value, type_) in values.iter().zip(&function.outputs){
+                expect(value, *type_)?;
+            }
+            Ok(())
+        },
+        Operation::Invoke(callee, args) => {
+            let callee_ = module.functions.iter().find(|x| x.id == *callee).ok_or(format!("{} is not a function of the module", callee))?;
+            if args.len() != callee_.inputs.len(){
+                return Err(format!("{} expects {} arguments, found {}", callee, callee_.inputs.len(), args.len()));
+            }
+            for (arg, type_) in args.iter().zip(&callee_.inputs){
+                expect(arg, *type_)?;
+            }
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::parse_module;
+
+    use super::*;
+
+    fn errors(source: &str) -> Vec<String>{
+        let module = parse_module(source).unwrap();
+        match verify_module(&module) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_module_has_no_errors(){
+        let source = "
+function fn1(i32) -> (i32) {
+    local $1: i32
+block1(%1: i32):
+    store_local $1, %1
+    br_gt %1, block2(%1), block3
+block2(%2: i32):
+    %3 = invoke fn1(%2)
+    return %3
+block3:
+    %4 = load_local $1
+    return %4
+}";
+        assert_eq!(errors(source), Vec::<String>::new());
+    }
+
+    #[test]
+    fn type_errors(){
+        let source = "
+function fn1(i32, i64, ref) -> (i32) {
+block1(%1: i32, %2: i64, %3: ref):
+    %4 = add.i32 %1, %2
+    %5 = add.ref %3, %3
+    br_eq %3, block2, block2
+block2:
+    return %2
+}";
+        assert_eq!(errors(source), [
+            "fn1 block1 instruction 0: expected %2 to be i32, found i64",
+            "fn1 block1 instruction 1: arithmetic on non numeric type ref",
+            "fn1 block1 instruction 2: cannot compare reference %3 against zero",
+            "fn1 block2 instruction 0: expected %2 to be i32, found i64",
+        ]);
+    }
+
+    #[test]
+    fn definition_errors(){
+        let source = "
+function fn1() -> (i32) {
+block1:
+    %2 = add.i32 %1, %1
+    %1 = const.i32 1
+    %1 = const.i32 2
+    %3 = add.i32 %9, %1
+    %4 = load_local $1
+    return %2
+}";
+        assert_eq!(errors(source), [
+            "fn1 block1: %1 is defined more than once",
+            "fn1 block1 instruction 0: %1 is used before it is defined",
+            "fn1 block1 instruction 3: %9 is not defined",
+            "fn1 block1 instruction 4: expected 0 outputs, found 1",
+            "fn1 block1 instruction 4: $1 is not a local of the function",
+        ]);
+    }
+
+    #[test]
+    fn structure_errors(){
+        let source = "
+function fn1(i32) {
+block1:
+    return
+    br block2(%1)
+block2(%2: i32, %3: i32):
+    %4 = invoke fn2()
+    br block3
+}";
+        assert_eq!(errors(source), [
+            "fn1 block1: entry inputs [] does not match the function inputs [I32]",
+            "fn1 block1 instruction 0: terminator in the middle of a block",
+            "fn1 block1 instruction 1: block2 expects 2 arguments, found 1",
+            "fn1 block2 instruction 0: expected 0 outputs, found 1",
+            "fn1 block2 instruction 0: fn2 is not a function of the module",
+            "fn1 block2 instruction 1: block3 is not a block of the function",
+        ]);
+    }
+
+    #[test]
+    fn call_and_return_errors(){
+        let source = "
+function fn1(i32) -> (i32) {
+block1(%1: i32):
+    %2 = invoke fn1()
+    br block2(%1, %1)
+block2(%3: i32):
+    return
+}";
+        assert_eq!(errors(source), [
+            "fn1 block1 instruction 0: fn1 expects 1 arguments, found 0",
+            "fn1 block1 instruction 1: block2 expects 1 arguments, found 2",
+            "fn1 block2 instruction 0: expected 1 return values, found 0",
+        ]);
+
+        let source = "
+function fn1() {
+block1:
+    %1 = const.i32 1
+}";
+        assert_eq!(errors(source), ["fn1 block1: block does not end with a branch or return"]);
+    }
+}
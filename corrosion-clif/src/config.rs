@@ -0,0 +1,171 @@
+use std::{fmt::Display, str::FromStr};
+
+use cranelift::prelude::{settings, Configurable, isa::{self, OwnedTargetIsa}};
+use cranelift::codegen::CodegenError;
+use target_lexicon::Triple;
+
+///
+/// Optimization level passed to cranelift
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OptLevel{
+    #[default]
+    None,
+    Speed,
+    SpeedAndSize,
+}
+
+impl OptLevel{
+    fn as_setting(self) -> &'static str{
+        match self {
+            OptLevel::None => "none",
+            OptLevel::Speed => "speed",
+            OptLevel::SpeedAndSize => "speed_and_size",
+        }
+    }
+}
+
+impl FromStr for OptLevel{
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(OptLevel::None),
+            "speed" => Ok(OptLevel::Speed),
+            "speed_and_size" => Ok(OptLevel::SpeedAndSize),
+            _ => Err(ConfigError::InvalidOptLevel(s.to_string()))
+        }
+    }
+}
+
+///
+/// Which cpu features the generated code is allowed to use
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub enum CpuFeatures{
+    ///
+    /// Detect the features of the host cpu
+    #[default]
+    Native,
+
+    ///
+    /// Only use features every cpu of the target architecture supports
+    Baseline,
+
+    ///
+    /// Enable exactly the provided isa flags, e.g. `has_avx` or `has_lse`
+    Explicit(Vec<String>),
+}
+
+#[derive(Clone, Debug)]
+pub struct GeneratorConfig{
+    pub opt_level: OptLevel,
+
+    ///
+    /// Run the cranelift verifier on every function before compiling it
+    pub verifier: bool,
+    pub cpu_features: CpuFeatures,
+
+    ///
+    /// Target triple used when compiling ahead of time, the JIT always targets the host
+    pub target: Option<String>,
+}
+
+impl Default for GeneratorConfig{
+    fn default() -> Self {
+        Self{
+            opt_level: OptLevel::None,
+            verifier: true,
+            cpu_features: CpuFeatures::Native,
+            target: None
+        }
+    }
+}
+
+impl GeneratorConfig{
+    pub fn flags(&self) -> Result<settings::Flags, ConfigError>{
+        let mut builder = settings::builder();
+        builder.set("opt_level", self.opt_level.as_setting())?;
+        builder.set("enable_verifier", if self.verifier { "true" } else { "false" })?;
+
+        // Colocated libcalls might not reach every definition and hotswapping requires PIC
+        builder.set("use_colocated_libcalls", "false")?;
+        builder.set("is_pic", "true")?;
+
+        Ok(settings::Flags::new(builder))
+    }
+
+    ///
+    /// Isa for the machine we are running on, used by the JIT
+    pub fn host_isa(&self) -> Result<OwnedTargetIsa, ConfigError>{
+        self.isa_for(Triple::host())
+    }
+
+    ///
+    /// Isa for the configured target triple, or the host if none is set
+    pub fn target_isa(&self) -> Result<OwnedTargetIsa, ConfigError>{
+        let triple = match &self.target {
+            Some(target) => Triple::from_str(target).map_err(|_| ConfigError::InvalidTriple(target.clone()))?,
+            None => Triple::host(),
+        };
+        self.isa_for(triple)
+    }
+
+    fn isa_for(&self, triple: Triple) -> Result<OwnedTargetIsa, ConfigError>{
+        let builder = match &self.cpu_features {
+            CpuFeatures::Native => {
+                if triple != Triple::host(){
+                    return Err(ConfigError::NativeFeaturesForForeignTarget(triple));
+                }
+                cranelift_native::builder().map_err(ConfigError::UnsupportedHost)?
+            },
+            CpuFeatures::Baseline => isa::lookup(triple.clone()).map_err(|_| ConfigError::UnsupportedTarget(triple))?,
+            CpuFeatures::Explicit(features) => {
+                let mut builder = isa::lookup(triple.clone()).map_err(|_| ConfigError::UnsupportedTarget(triple))?;
+                for feature in features{
+                    builder.enable(feature)?;
+                }
+                builder
+            },
+        };
+
+        Ok(builder.finish(self.flags()?)?)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError{
+    InvalidOptLevel(String),
+    InvalidTriple(String),
+    UnsupportedTarget(Triple),
+    UnsupportedHost(&'static str),
+    NativeFeaturesForForeignTarget(Triple),
+    Setting(settings::SetError),
+    Codegen(CodegenError),
+}
+
+impl Display for ConfigError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::InvalidOptLevel(level) => write!(f, "invalid opt level '{}', expected none, speed or speed_and_size", level),
+            ConfigError::InvalidTriple(triple) => write!(f, "invalid target triple '{}'", triple),
+            ConfigError::UnsupportedTarget(triple) => write!(f, "target {} is not supported by cranelift", triple),
+            ConfigError::UnsupportedHost(msg) => write!(f, "host machine is not supported: {}", msg),
+            ConfigError::NativeFeaturesForForeignTarget(triple) => write!(f, "cannot detect native cpu features for foreign target {}", triple),
+            ConfigError::Setting(err) => write!(f, "invalid setting: {}", err),
+            ConfigError::Codegen(err) => write!(f, "failed to create isa: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError{}
+
+impl From<settings::SetError> for ConfigError{
+    fn from(value: settings::SetError) -> Self {
+        ConfigError::Setting(value)
+    }
+}
+
+impl From<CodegenError> for ConfigError{
+    fn from(value: CodegenError) -> Self {
+        ConfigError::Codegen(value)
+    }
+}
@@ -1,269 +1,266 @@
-use std::collections::HashMap;
-use cranelift::prelude::{*, isa::x64::settings::builder};
-use corrosion_base::{Module as CModule, Type as CType, ModuleRef, FunctionRef, Function};
+use std::{collections::{HashMap, HashSet}, fmt::Display};
+use cranelift::prelude::*;
+use corrosion_base::{Module as CModule, ModuleRef, FunctionRef, Function, Backend, BackendError, Scalar};
 use cranelift_jit::{JITModule, JITBuilder};
-use cranelift_module::{default_libcall_names, FuncId, Module, Linkage};
+use cranelift_module::{default_libcall_names, FuncId, Module, Linkage, ModuleError};
+
+mod config;
+mod lower;
+mod object;
+
+pub use config::*;
+pub use object::*;
+
+use lower::from_base_type;
 
 pub struct Generator{
     module: JITModule,
+    config: GeneratorConfig,
     functions: HashMap<(ModuleRef, FunctionRef), FunctionDeclaration>,
-    var_counter: u32,
+    names: HashMap<(ModuleRef, String), FunctionRef>,
+    dumps: Option<HashMap<(ModuleRef, FunctionRef), FunctionDump>>,
+}
+
+impl Default for Generator{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Generator{
     pub fn new() -> Self{
-        let mut builder = JITBuilder::new(default_libcall_names()).unwrap();
+        Self::with_config(GeneratorConfig::default()).unwrap()
+    }
+
+    pub fn with_config(config: GeneratorConfig) -> Result<Self, ConfigError>{
+        let mut builder = JITBuilder::with_isa(config.host_isa()?, default_libcall_names());
         builder.hotswap(true);
-        Self{
+        builder.symbols(lower::libcalls());
+        Ok(Self{
             module: JITModule::new(builder),
+            config,
             functions: HashMap::new(),
-            var_counter: 0
+            names: HashMap::new(),
+            dumps: None
+        })
+    }
+
+    pub fn config(&self) -> &GeneratorConfig{
+        &self.config
+    }
+
+    ///
+    /// Capture the clif and disassembly of every function defined by later calls to `load_module`
+    pub fn set_capture_dumps(&mut self, enabled: bool){
+        if enabled{
+            self.dumps.get_or_insert_with(HashMap::new);
+        }
+        else{
+            self.dumps = None;
         }
     }
 
-    pub fn load_module(&mut self, module: CModule){
-        let mut ctx = self.module.make_context();
-        let mut f_ctx = FunctionBuilderContext::new();
-        
-        for function in &module.functions{
-            let declaration = self.declare_function(&module, function);
+    pub fn dump(&self, module: ModuleRef, function: FunctionRef) -> Option<&FunctionDump>{
+        self.dumps.as_ref()?.get(&(module, function))
+    }
 
-            if declaration.defined{
-                panic!("Duplicate definition of function ({:?} {:?}) {}({:?}) -> {:?}", module.id, function.id, function.name, function.inputs, function.outputs);
-            }
+    pub fn dumps(&self) -> impl Iterator<Item = (&(ModuleRef, FunctionRef), &FunctionDump)>{
+        self.dumps.iter().flat_map(|x| x.iter())
+    }
 
-            let id = declaration.id;
-            ctx.func.signature = declaration.signature.clone();
+    pub fn load_module(&mut self, module: CModule) -> Result<(), BackendError>{
+        self.define_module(&module, false)
+    }
 
-            {
-                let mut b_ctx = FunctionBuilder::new(&mut ctx.func, &mut f_ctx);
+    ///
+    /// Define functions of an already loaded module, replacing the code of those that were defined before.
+    /// Existing callers pick up the new code through hotswapping, so signatures and names can't change
+    pub fn reload_module(&mut self, module: CModule) -> Result<(), BackendError>{
+        self.define_module(&module, true)
+    }
+
+    ///
+    /// Names and declarations are only recorded when the whole module was defined.
+    /// Functions that were already compiled keep their new code if a later function fails
+    fn define_module(&mut self, module: &CModule, redefine: bool) -> Result<(), BackendError>{
+        self.check_module(module, redefine)?;
 
-                let mut block_map = HashMap::with_capacity(function.blocks.len());
-                for block in &function.blocks{
-                    let block_id = b_ctx.create_block();
-                    block_map.insert(block.label, block_id);
+        let mut declared = Vec::new();
+        let result = self.define_checked(module, &mut declared);
+        if result.is_err(){
+            // The jit keeps the declarations, which are reused when the function is declared again with the same signature
+            for function in declared{
+                if self.functions.get(&(module.id, function)).is_some_and(|x| !x.defined){
+                    self.functions.remove(&(module.id, function));
                 }
+            }
+            return result;
+        }
 
-                let mut locals = HashMap::new();
+        for function in &module.functions{
+            if !function.name.is_empty(){
+                self.names.insert((module.id, function.name.clone()), function.id);
+            }
+        }
+        Ok(())
+    }
 
-                for (var, type_) in &function.locals {
-                    let var_ = Variable::from_u32(self.var_counter);
-                    b_ctx.declare_var(var_, from_base_type(*type_));
-                    locals.insert(*var, var_);
+    ///
+    /// Reject definitions that conflict with the module or with what was loaded before, without changing anything
+    fn check_module(&self, module: &CModule, redefine: bool) -> Result<(), BackendError>{
+        let mut names = HashSet::new();
+        for function in &module.functions{
+            if !function.name.is_empty(){
+                let existing = self.names.get(&(module.id, function.name.clone()));
+                if !names.insert(&function.name) || existing.is_some_and(|x| *x != function.id){
+                    return Err(BackendError::DuplicateName(module.id, function.name.clone()));
                 }
+            }
 
-                for b in &function.blocks{
-                    let block = block_map[&b.label];
-                    let mut values = HashMap::new();
-
-                    b_ctx.switch_to_block(block);
-
-                    //Initalize parameters
-                    if b.label == function.entry{
-                        assert!(b.inputs.len() == function.inputs.len());
-                        b_ctx.append_block_params_for_function_params(block);
-                        
-                        for (a, b) in b.inputs.iter().zip(b_ctx.block_params(block).iter()){
-                            let imm = a.immediate();
-                            values.insert(imm, *b);
-                        }
-                    }
-                    else{
-                        for input in &b.inputs{
-                            let value = b_ctx.append_block_param(block, from_base_type(input.type_()));
-                            values.insert(input.immediate(), value);
-                        }
-                    }
-
-                    for instruction in &b.instructions{
-                        match instruction.operation(){
-                            corrosion_base::Operation::ConstI32(value) => {
-                                let output = instruction.assert_1_immediate();
-                                let value = b_ctx.ins().iconst(types::I32, *value as i32 as i64);
-                                values.insert(output, value);
-                            },
-                            corrosion_base::Operation::ConstI64(value) => {
-                                let output = instruction.assert_1_immediate();
-                                let value = b_ctx.ins().iconst(types::I64, *value as i64);
-                                values.insert(output, value);
-                            },
-                            corrosion_base::Operation::ConstF32(value) => {
-                                let output = instruction.assert_1_immediate();
-                                let value = b_ctx.ins().f32const(*value);
-                                values.insert(output, value);
-                            },
-                            corrosion_base::Operation::ConstF64(value) => {
-                                let output = instruction.assert_1_immediate();
-                                let value = b_ctx.ins().f64const(*value);
-                                values.insert(output, value);
-
-                            },
-                            corrosion_base::Operation::OffsetPtr1(_, _) => todo!("offset ptr with alignment 1"),
-                            corrosion_base::Operation::OffsetPtr2(_, _) => todo!("offset ptr with alignment 2"),
-                            corrosion_base::Operation::OffsetPtr4(_, _) => todo!("offset ptr with alignment 4"),
-                            corrosion_base::Operation::OffsetPtr8(_, _) => todo!("offset ptr with alignment 8"),
-                            corrosion_base::Operation::Add(a, b, type_) => {
-                                let a_v = values[a];
-                                let b_v = values[b];
-                                let value = match type_{
-                                    CType::I8 | 
-                                    CType::I16 | 
-                                    CType::I32 | 
-                                    CType::I64 => b_ctx.ins().iadd(a_v, b_v),
-                                    CType::F32 |
-                                    CType::F64 => b_ctx.ins().fadd(a_v, b_v),
-                                    _ => panic!("Cannot add types of {:?}",type_)
-                                };
-                                let output = instruction.assert_1_immediate();
-                                values.insert(output, value);
-                            },
-                            corrosion_base::Operation::Sub(a, b, type_) => {
-                                let a_v = values[a];
-                                let b_v = values[b];
-                                let value = match type_{
-                                    CType::I8 | 
-                                    CType::I16 | 
-                                    CType::I32 | 
-                                    CType::I64 => b_ctx.ins().isub(a_v, b_v),
-                                    CType::F32 |
-                                    CType::F64 => b_ctx.ins().fsub(a_v, b_v),
-                                    _ => panic!("Cannot subtract types of {:?}",type_)
-                                };
-                                let output = instruction.assert_1_immediate();
-                                values.insert(output, value);
-                            },
-
-                            corrosion_base::Operation::Mul(a, b, type_) => {
-                                let a_v = values[a];
-                                let b_v = values[b];
-                                let value = match type_{
-                                    CType::I8 | 
-                                    CType::I16 | 
-                                    CType::I32 | 
-                                    CType::I64 => b_ctx.ins().imul(a_v, b_v),
-                                    CType::F32 |
-                                    CType::F64 => b_ctx.ins().fmul(a_v, b_v),
-                                    _ => panic!("Cannot multiply types of {:?}",type_)
-                                };
-                                let output = instruction.assert_1_immediate();
-                                values.insert(output, value);
-                            }
-                            corrosion_base::Operation::Div(a, b, type_) => {
-                                let a_v = values[a];
-                                let b_v = values[b];
-                                let value = match type_{
-                                    CType::I8 | 
-                                    CType::I16 | 
-                                    CType::I32 | 
-                                    CType::I64 => b_ctx.ins().sdiv(a_v, b_v),
-                                    CType::F32 |
-                                    CType::F64 => b_ctx.ins().fdiv(a_v, b_v),
-                                    _ => panic!("Cannot divide types of {:?}",type_)
-                                };
-                                let output = instruction.assert_1_immediate();
-                                values.insert(output, value);
-                            }
-                            corrosion_base::Operation::Mod(a, b, type_) => {
-                                let a_v = values[a];
-                                let b_v = values[b];
-                                let value = match type_{
-                                    CType::I8 | 
-                                    CType::I16 | 
-                                    CType::I32 | 
-                                    CType::I64 => b_ctx.ins().srem(a_v, b_v),
-                                    CType::F32 |
-                                    CType::F64 => unimplemented!("Float remainder is not yet supported"),
-                                    _ => panic!("Cannot take remainder of {:?}",type_)
-                                };
-                                let output = instruction.assert_1_immediate();
-                                values.insert(output, value);
-                            }
-                            corrosion_base::Operation::LoadLocal(var) => {
-                                let var_ = locals.get(var).unwrap();
-                                let value = b_ctx.use_var(*var_);
-                                let output = instruction.assert_1_immediate();
-                                values.insert(output, value);
-                            },
-                            corrosion_base::Operation::StoreLocal(var, value) => {
-                                let var_ = locals.get(var).unwrap();
-                                b_ctx.def_var(*var_, values[value]);
-                                instruction.assert_no_immediates();
-                            },
-                            corrosion_base::Operation::Read(ptr, type_, aligned) => {
-                                let ptr = values[ptr];
-                                let output = instruction.assert_1_immediate();
-                                
-                                let mut flags = MemFlags::new().with_heap();
-                                if *aligned{ flags.set_aligned(); }
-                                let value = b_ctx.ins().load(from_base_type(*type_), flags, ptr, 0);
-
-                                values.insert(output, value);
-                            },
-                            corrosion_base::Operation::Write(ptr, value, aligned) => {
-                                let ptr = values[ptr];
-                                let value = values[value];
-
-                                let mut flags = MemFlags::new().with_heap();
-                                if *aligned{ flags.set_aligned();}
-                                b_ctx.ins().store(flags, ptr, value, 0);
-                            },
-                            corrosion_base::Operation::BranchIfEq(_, _, _) => todo!(),
-                            corrosion_base::Operation::BranchIfNe(_, _, _) => todo!(),
-                            corrosion_base::Operation::BranchIfLt(_, _, _) => todo!(),
-                            corrosion_base::Operation::BranchIfLe(_, _, _) => todo!(),
-                            corrosion_base::Operation::BranchIfGt(_, _, _) => todo!(),
-                            corrosion_base::Operation::BranchIfGe(_, _, _) => todo!(),
-                            corrosion_base::Operation::Branch(_) => todo!(),
-                            corrosion_base::Operation::Return(outputs) => {
-                                let outputs = outputs.iter().map(|x| values[x]).collect::<Vec<_>>();
-
-                                b_ctx.ins().return_(&outputs);
-                            },
-                            corrosion_base::Operation::Invoke(_, _) => todo!(),
-                        }
-                    }
-                }
+            let Some(declaration) = self.functions.get(&(module.id, function.id)) else {
+                continue;
+            };
+            if declaration.defined && !redefine{
+                return Err(BackendError::DuplicateDefinition(module.id, function.id));
+            }
+            if declaration.base.inputs != function.inputs || declaration.base.outputs != function.outputs{
+                return Err(BackendError::Unsupported(format!("changing the signature of {} after it was loaded", function.id)));
+            }
+            if declaration.name != function.name{
+                return Err(BackendError::Unsupported(format!("renaming {} after it was loaded", function.id)));
+            }
+        }
+        Ok(())
+    }
 
-                b_ctx.seal_all_blocks();
-                b_ctx.finalize();
+    ///
+    /// Declare, lower and compile the functions of a checked module, recording the functions declared for the first time in `declared`
+    fn define_checked(&mut self, module: &CModule, declared: &mut Vec<FunctionRef>) -> Result<(), BackendError>{
+        // Declare everything up front so that functions can invoke functions defined later in the module
+        for function in &module.functions{
+            if !self.functions.contains_key(&(module.id, function.id)){
+                self.declare_function(module, function)?;
+                declared.push(function.id);
             }
-            self.module.define_function(id, &mut ctx).unwrap();
+        }
+
+        // Lower every function before compiling any, so that a function that can't be lowered leaves the jit untouched
+        let mut f_ctx = FunctionBuilderContext::new();
+        let mut lowered = Vec::with_capacity(module.functions.len());
+        for function in &module.functions{
+            let signature = self.functions[&(module.id, function.id)].signature.clone();
+            let mut func = codegen::ir::Function::with_name_signature(Default::default(), signature);
+
+            let callees = |callee| self.functions[&(module.id, callee)].id;
+            lower::lower_function(&mut self.module, &mut func, &mut f_ctx, function, &callees)?;
+            lowered.push(func);
+        }
+
+        let mut ctx = self.module.make_context();
+        for (function, func) in module.functions.iter().zip(lowered){
+            let declaration = &self.functions[&(module.id, function.id)];
+
+            let id = declaration.id;
+            let trampoline = declaration.trampoline;
+            if declaration.defined{
+                self.module.prepare_for_function_redefine(id).map_err(compile_error)?;
+            }
+            ctx.func = func;
+
+            let clif = self.dumps.as_ref().map(|_| ctx.func.display().to_string());
+            ctx.set_disasm(clif.is_some());
+
+            self.module.define_function(id, &mut ctx).map_err(compile_error)?;
+
+            if let (Some(dumps), Some(clif)) = (self.dumps.as_mut(), clif){
+                dumps.insert((module.id, function.id), FunctionDump{
+                    clif,
+                    optimized_clif: ctx.func.display().to_string(),
+                    disassembly: ctx.compiled_code().and_then(|x| x.vcode.clone()).unwrap_or_default()
+                });
+            }
+
             self.module.clear_context(&mut ctx);
 
+            // Trampolines call through the hotswap table, so they stay valid when the function is redefined
+            let trampoline = match trampoline {
+                Some(x) => x,
+                None => self.define_trampoline(&mut ctx, &mut f_ctx, id, function)?,
+            };
+
             let decl = self.functions.get_mut(&(module.id, function.id)).unwrap();
             decl.defined = true;
+            decl.trampoline = Some(trampoline);
         }
 
-        self.module.finalize_definitions().unwrap();
+        self.module.finalize_definitions().map_err(compile_error)?;
+        Ok(())
     }
 
-    fn declare_function(&mut self,module: &CModule, function: &Function) -> &FunctionDeclaration{
-        let key = (module.id, function.id);
+    ///
+    /// Define a function taking pointers to argument and result slots of 8 bytes each, which calls `callee`.
+    /// This allows invoking compiled functions of any signature with values only known at runtime.
+    fn define_trampoline(&mut self, ctx: &mut codegen::Context, f_ctx: &mut FunctionBuilderContext, callee: FuncId, function: &Function) -> Result<FuncId, BackendError>{
+        let pointer = self.module.target_config().pointer_type();
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(pointer));
+        sig.params.push(AbiParam::new(pointer));
 
-        match self.functions.entry(key) {
-            std::collections::hash_map::Entry::Occupied(declaration) => {
-                declaration.into_mut()
-            },
-            std::collections::hash_map::Entry::Vacant(entry) => {
-                let mut sig = self.module.make_signature();
-                for input in &function.inputs{
-                    sig.params.push(AbiParam::new(from_base_type(*input)));
-                }
-                for output in &function.outputs{
-                    sig.returns.push(AbiParam::new(from_base_type(*output)));
-                }
-                
-                let id = self.module.declare_function(&function.name, Linkage::Export, &sig).unwrap();
+        let id = self.module.declare_anonymous_function(&sig).map_err(compile_error)?;
+        ctx.func.signature = sig;
 
-                let decl = entry.insert(FunctionDeclaration {
-                    defined: false,
-                    id,
-                    signature: sig
-                });
+        {
+            let mut b_ctx = FunctionBuilder::new(&mut ctx.func, f_ctx);
+            let block = b_ctx.create_block();
+            b_ctx.append_block_params_for_function_params(block);
+            b_ctx.switch_to_block(block);
 
-                decl
-            },
+            let [args, results] = *b_ctx.block_params(block) else { unreachable!("trampolines take two parameters") };
+            let callee = self.module.declare_func_in_func(callee, b_ctx.func);
+
+            let args = function.inputs.iter().enumerate()
+                .map(|(i, type_)| b_ctx.ins().load(from_base_type(*type_, pointer), MemFlags::trusted(), args, i as i32 * 8))
+                .collect::<Vec<_>>();
+            let call = b_ctx.ins().call(callee, &args);
+
+            let outputs = b_ctx.inst_results(call).to_vec();
+            for (i, output) in outputs.into_iter().enumerate(){
+                b_ctx.ins().store(MemFlags::trusted(), output, results, i as i32 * 8);
+            }
+            b_ctx.ins().return_(&[]);
+
+            b_ctx.seal_all_blocks();
+            b_ctx.finalize();
+        }
+
+        self.module.define_function(id, ctx).map_err(compile_error)?;
+        self.module.clear_context(ctx);
+        Ok(id)
+    }
+
+    fn declare_function(&mut self, module: &CModule, function: &Function) -> Result<(), BackendError>{
+        let sig = lower::signature_of(&self.module, function);
+
+        // Unnamed functions can only be reached through invocations within the module
+        let id = if function.name.is_empty(){
+            self.module.declare_anonymous_function(&sig).map_err(compile_error)?
         }
+        else{
+            self.module.declare_function(&symbol_name(module.id, &function.name), Linkage::Export, &sig).map_err(compile_error)?
+        };
+
+        self.functions.insert((module.id, function.id), FunctionDeclaration {
+            defined: false,
+            id,
+            name: function.name.clone(),
+            signature: sig,
+            base: corrosion_base::Signature{
+                inputs: function.inputs.clone(),
+                outputs: function.outputs.clone()
+            },
+            trampoline: None
+        });
+        Ok(())
     }
 
     pub fn get_function(&self, module: ModuleRef, function: FunctionRef) -> *const u8{
@@ -276,20 +273,91 @@ impl Generator{
     }
 }
 
+impl Backend for Generator{
+    fn load_module(&mut self, module: CModule) -> Result<(), BackendError> {
+        Generator::load_module(self, module)
+    }
+
+    fn resolve_function(&self, module: ModuleRef, name: &str) -> Result<FunctionRef, BackendError> {
+        self.names.get(&(module, name.to_string()))
+            .copied()
+            .ok_or_else(|| BackendError::UnknownFunction(module, name.to_string()))
+    }
+
+    fn signature(&self, module: ModuleRef, function: FunctionRef) -> Result<corrosion_base::Signature, BackendError> {
+        self.functions.get(&(module, function))
+            .map(|x| x.base.clone())
+            .ok_or(BackendError::UndefinedFunction(module, function))
+    }
+
+    ///
+    /// Traps raised by the compiled code can't be recovered from and abort the process
+    fn invoke(&mut self, module: ModuleRef, function: FunctionRef, args: &[Scalar]) -> Result<Vec<Scalar>, BackendError> {
+        let decl = self.functions.get(&(module, function)).ok_or(BackendError::UndefinedFunction(module, function))?;
+        let Some(trampoline) = decl.trampoline.filter(|_| decl.defined) else {
+            return Err(BackendError::UndefinedFunction(module, function));
+        };
+        decl.base.check_args(args)?;
+
+        // Values are passed as little endian 8 byte slots, matching the loads and stores of the trampoline
+        let args = args.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        let mut results = vec![0u64; decl.base.outputs.len()];
+
+        let trampoline = self.module.get_finalized_function(trampoline);
+        // Safety: trampolines are always compiled with this signature, and the slots match the function's signature
+        unsafe {
+            let trampoline = std::mem::transmute::<*const u8, extern "C" fn(*const u64, *mut u64)>(trampoline);
+            trampoline(args.as_ptr(), results.as_mut_ptr());
+        }
+
+        Ok(decl.base.outputs.iter().zip(results).map(|(type_, bits)| Scalar::from_bits(*type_, bits)).collect())
+    }
+}
+
+///
+/// Textual representations of a function captured while it was compiled
+#[derive(Clone, Debug, Default)]
+pub struct FunctionDump{
+    ///
+    /// Cranelift ir as produced by lowering, before any optimization
+    pub clif: String,
+
+    ///
+    /// Cranelift ir after the optimization passes ran
+    pub optimized_clif: String,
+
+    ///
+    /// Disassembly of the generated machine code
+    pub disassembly: String,
+}
+
+impl Display for FunctionDump{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "; clif")?;
+        writeln!(f, "{}", self.clif)?;
+        writeln!(f, "; optimized clif")?;
+        writeln!(f, "{}", self.optimized_clif)?;
+        writeln!(f, "; disassembly")?;
+        write!(f, "{}", self.disassembly)
+    }
+}
+
 struct FunctionDeclaration{
     defined: bool,
     id: FuncId,
+    name: String,
     signature: Signature,
+    base: corrosion_base::Signature,
+    trampoline: Option<FuncId>,
+}
+
+///
+/// Symbols of the jit share one namespace, so names are qualified by their module
+fn symbol_name(module: ModuleRef, name: &str) -> String{
+    format!("{}_{}", module, name)
+}
+
+pub(crate) fn compile_error(err: ModuleError) -> BackendError{
+    BackendError::Compile(Box::new(err))
 }
 
-fn from_base_type(type_: corrosion_base::Type) -> Type{
-        match type_ {
-            corrosion_base::Type::I8 => types::I8,
-            corrosion_base::Type::I16 => types::I16,
-            corrosion_base::Type::I32 => types::I32,
-            corrosion_base::Type::I64 => types::I64,
-            corrosion_base::Type::F32 => types::F32,
-            corrosion_base::Type::F64 => types::F64,
-            corrosion_base::Type::Ptr => types::R64,
-        }
-}
\ No newline at end of file
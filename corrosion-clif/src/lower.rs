@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use cranelift::prelude::*;
+use cranelift::codegen::ir;
+use corrosion_base::{analysis::ControlFlowGraph, BackendError, Function, FunctionRef, ImmediateRef, Type as CType};
+use cranelift_module::{FuncId, Linkage, Module};
+
+use crate::compile_error;
+
+///
+/// Signature of a base function for the target of the module
+pub(crate) fn signature_of<M: Module>(module: &M, function: &Function) -> Signature{
+    let pointer = module.target_config().pointer_type();
+    let mut sig = module.make_signature();
+    for input in &function.inputs{
+        sig.params.push(AbiParam::new(from_base_type(*input, pointer)));
+    }
+    for output in &function.outputs{
+        sig.returns.push(AbiParam::new(from_base_type(*output, pointer)));
+    }
+    sig
+}
+
+///
+/// Lower a base function into `func`, whose signature must already be set.
+/// `callees` maps the functions invoked by `function` to their declarations in `module`.
+/// Only blocks reachable from the entry are lowered, unreachable ones may use immediates that are never defined
+pub(crate) fn lower_function<M: Module>(module: &mut M, func: &mut ir::Function, f_ctx: &mut FunctionBuilderContext, function: &Function, callees: &dyn Fn(FunctionRef) -> FuncId) -> Result<(), BackendError>{
+    let pointer = module.target_config().pointer_type();
+    let mut b_ctx = FunctionBuilder::new(func, f_ctx);
+
+    // Reverse postorder starts with the entry, which cranelift takes to be the first block in the layout,
+    // and visits the definition of every immediate before its uses
+    let mut block_map = HashMap::with_capacity(function.blocks.len());
+    let blocks = ControlFlowGraph::new(function).reverse_postorder().into_iter()
+        .map(|x| function.blocks.iter().find(|b| b.label == x).ok_or_else(|| lower_error(format!("{} is not a block of {}", x, function.id))))
+        .collect::<Result<Vec<_>, _>>()?;
+    for block in &blocks{
+        let block_id = b_ctx.create_block();
+        block_map.insert(block.label, block_id);
+    }
+
+    let mut func_refs = HashMap::new();
+    let mut libcalls = HashMap::new();
+    let mut values = HashMap::new();
+
+    let mut locals = HashMap::new();
+
+    for (i, (var, type_)) in function.locals.iter().enumerate() {
+        let var_ = Variable::from_u32(i as u32);
+        b_ctx.declare_var(var_, from_base_type(*type_, pointer));
+        locals.insert(*var, var_);
+    }
+
+    for b in blocks{
+        let block = block_map[&b.label];
+
+        b_ctx.switch_to_block(block);
+
+        //Initalize parameters
+        if b.label == function.entry{
+            if b.inputs.len() != function.inputs.len(){
+                return Err(lower_error(format!("entry of {} has {} inputs, expected {}", function.id, b.inputs.len(), function.inputs.len())));
+            }
+            b_ctx.append_block_params_for_function_params(block);
+
+            for (a, b) in b.inputs.iter().zip(b_ctx.block_params(block).iter()){
+                let imm = a.immediate();
+                values.insert(imm, *b);
+            }
+        }
+        else{
+            for input in &b.inputs{
+                let value = b_ctx.append_block_param(block, from_base_type(input.type_(), pointer));
+                values.insert(input.immediate(), value);
+            }
+        }
+
+        for instruction in &b.instructions{
+            match instruction.operation(){
+                corrosion_base::Operation::ConstI32(value) => {
+                    let output = output_of(instruction)?;
+                    let value = b_ctx.ins().iconst(types::I32, *value as i32 as i64);
+                    values.insert(output, value);
+                },
+                corrosion_base::Operation::ConstI64(value) => {
+                    let output = output_of(instruction)?;
+                    let value = b_ctx.ins().iconst(types::I64, *value as i64);
+                    values.insert(output, value);
+                },
+                corrosion_base::Operation::ConstF32(value) => {
+                    let output = output_of(instruction)?;
+                    let value = b_ctx.ins().f32const(*value);
+                    values.insert(output, value);
+                },
+                corrosion_base::Operation::ConstF64(value) => {
+                    let output = output_of(instruction)?;
+                    let value = b_ctx.ins().f64const(*value);
+                    values.insert(output, value);
+
+                },
+                corrosion_base::Operation::OffsetPtr1(ptr, offset) |
+                corrosion_base::Operation::OffsetPtr2(ptr, offset) |
+                corrosion_base::Operation::OffsetPtr4(ptr, offset) |
+                corrosion_base::Operation::OffsetPtr8(ptr, offset) => {
+                    let scale = match instruction.operation(){
+                        corrosion_base::Operation::OffsetPtr1(..) => 1,
+                        corrosion_base::Operation::OffsetPtr2(..) => 2,
+                        corrosion_base::Operation::OffsetPtr4(..) => 4,
+                        _ => 8,
+                    };
+                    let ptr = value_of(&values, ptr)?;
+                    let mut offset = value_of(&values, offset)?;
+
+                    let offset_type = b_ctx.func.dfg.value_type(offset);
+                    if offset_type.bits() < pointer.bits(){
+                        offset = b_ctx.ins().sextend(pointer, offset);
+                    }
+                    else if offset_type.bits() > pointer.bits(){
+                        offset = b_ctx.ins().ireduce(pointer, offset);
+                    }
+
+                    let offset = b_ctx.ins().imul_imm(offset, scale);
+                    let value = b_ctx.ins().iadd(ptr, offset);
+                    let output = output_of(instruction)?;
+                    values.insert(output, value);
+                },
+                corrosion_base::Operation::Add(a, b, type_) => {
+                    let a_v = value_of(&values, a)?;
+                    let b_v = value_of(&values, b)?;
+                    let value = match type_{
+                        CType::I8 | 
+                        CType::I16 | 
+                        CType::I32 | 
+                        CType::I64 => b_ctx.ins().iadd(a_v, b_v),
+                        CType::F32 |
+                        CType::F64 => b_ctx.ins().fadd(a_v, b_v),
+                        _ => return Err(lower_error(format!("cannot add values of type {}", type_)))
+                    };
+                    let output = output_of(instruction)?;
+                    values.insert(output, value);
+                },
+                corrosion_base::Operation::Sub(a, b, type_) => {
+                    let a_v = value_of(&values, a)?;
+                    let b_v = value_of(&values, b)?;
+                    let value = match type_{
+                        CType::I8 | 
+                        CType::I16 | 
+                        CType::I32 | 
+                        CType::I64 => b_ctx.ins().isub(a_v, b_v),
+                        CType::F32 |
+                        CType::F64 => b_ctx.ins().fsub(a_v, b_v),
+                        _ => return Err(lower_error(format!("cannot subtract values of type {}", type_)))
+                    };
+                    let output = output_of(instruction)?;
+                    values.insert(output, value);
+                },
+
+                corrosion_base::Operation::Mul(a, b, type_) => {
+                    let a_v = value_of(&values, a)?;
+                    let b_v = value_of(&values, b)?;
+                    let value = match type_{
+                        CType::I8 | 
+                        CType::I16 | 
+                        CType::I32 | 
+                        CType::I64 => b_ctx.ins().imul(a_v, b_v),
+                        CType::F32 |
+                        CType::F64 => b_ctx.ins().fmul(a_v, b_v),
+                        _ => return Err(lower_error(format!("cannot multiply values of type {}", type_)))
+                    };
+                    let output = output_of(instruction)?;
+                    values.insert(output, value);
+                }
+                corrosion_base::Operation::Div(a, b, type_) => {
+                    let a_v = value_of(&values, a)?;
+                    let b_v = value_of(&values, b)?;
+                    let value = match type_{
+                        CType::I8 | 
+                        CType::I16 | 
+                        CType::I32 | 
+                        CType::I64 => b_ctx.ins().sdiv(a_v, b_v),
+                        CType::F32 |
+                        CType::F64 => b_ctx.ins().fdiv(a_v, b_v),
+                        _ => return Err(lower_error(format!("cannot divide values of type {}", type_)))
+                    };
+                    let output = output_of(instruction)?;
+                    values.insert(output, value);
+                }
+                corrosion_base::Operation::Mod(a, b, type_) => {
+                    let a_v = value_of(&values, a)?;
+                    let b_v = value_of(&values, b)?;
+                    let value = match type_{
+                        CType::I8 | 
+                        CType::I16 | 
+                        CType::I32 | 
+                        CType::I64 => b_ctx.ins().srem(a_v, b_v),
+                        // Cranelift has no float remainder, it's computed by a call to `fmod` or `fmodf` as in C
+                        CType::F32 |
+                        CType::F64 => {
+                            let name = if *type_ == CType::F32 { "fmodf" } else { "fmod" };
+                            let func_ref = match libcalls.get(name) {
+                                Some(x) => *x,
+                                None => {
+                                    let float = from_base_type(*type_, pointer);
+                                    let mut sig = module.make_signature();
+                                    sig.params.extend([AbiParam::new(float), AbiParam::new(float)]);
+                                    sig.returns.push(AbiParam::new(float));
+                                    let id = module.declare_function(name, Linkage::Import, &sig).map_err(compile_error)?;
+                                    *libcalls.entry(name).or_insert(module.declare_func_in_func(id, b_ctx.func))
+                                },
+                            };
+                            let call = b_ctx.ins().call(func_ref, &[a_v, b_v]);
+                            b_ctx.inst_results(call)[0]
+                        },
+                        _ => return Err(lower_error(format!("cannot take the remainder of values of type {}", type_)))
+                    };
+                    let output = output_of(instruction)?;
+                    values.insert(output, value);
+                }
+                corrosion_base::Operation::LoadLocal(var) => {
+                    let var_ = local_of(&locals, var)?;
+                    let value = b_ctx.use_var(var_);
+                    let output = output_of(instruction)?;
+                    values.insert(output, value);
+                },
+                corrosion_base::Operation::StoreLocal(var, value) => {
+                    let var_ = local_of(&locals, var)?;
+                    b_ctx.def_var(var_, value_of(&values, value)?);
+                },
+                corrosion_base::Operation::Read(ptr, type_, aligned) => {
+                    let ptr = value_of(&values, ptr)?;
+                    let output = output_of(instruction)?;
+
+                    let mut flags = MemFlags::new().with_heap();
+                    if *aligned{ flags.set_aligned(); }
+                    let value = b_ctx.ins().load(from_base_type(*type_, pointer), flags, ptr, 0);
+
+                    values.insert(output, value);
+                },
+                corrosion_base::Operation::Write(ptr, value, aligned) => {
+                    let ptr = value_of(&values, ptr)?;
+                    let value = value_of(&values, value)?;
+
+                    let mut flags = MemFlags::new().with_heap();
+                    if *aligned{ flags.set_aligned();}
+                    b_ctx.ins().store(flags, value, ptr, 0);
+                },
+                corrosion_base::Operation::BranchIfEq(value, then, else_) |
+                corrosion_base::Operation::BranchIfNe(value, then, else_) |
+                corrosion_base::Operation::BranchIfLt(value, then, else_) |
+                corrosion_base::Operation::BranchIfLe(value, then, else_) |
+                corrosion_base::Operation::BranchIfGt(value, then, else_) |
+                corrosion_base::Operation::BranchIfGe(value, then, else_) => {
+                    let (int_cc, float_cc) = match instruction.operation() {
+                        corrosion_base::Operation::BranchIfEq(..) => (IntCC::Equal, FloatCC::Equal),
+                        corrosion_base::Operation::BranchIfNe(..) => (IntCC::NotEqual, FloatCC::NotEqual),
+                        corrosion_base::Operation::BranchIfLt(..) => (IntCC::SignedLessThan, FloatCC::LessThan),
+                        corrosion_base::Operation::BranchIfLe(..) => (IntCC::SignedLessThanOrEqual, FloatCC::LessThanOrEqual),
+                        corrosion_base::Operation::BranchIfGt(..) => (IntCC::SignedGreaterThan, FloatCC::GreaterThan),
+                        _ => (IntCC::SignedGreaterThanOrEqual, FloatCC::GreaterThanOrEqual),
+                    };
+
+                    let value = value_of(&values, value)?;
+                    let condition = match b_ctx.func.dfg.value_type(value) {
+                        types::F32 => {
+                            let zero = b_ctx.ins().f32const(0.0);
+                            b_ctx.ins().fcmp(float_cc, value, zero)
+                        },
+                        types::F64 => {
+                            let zero = b_ctx.ins().f64const(0.0);
+                            b_ctx.ins().fcmp(float_cc, value, zero)
+                        },
+                        type_ if type_.is_ref() => return Err(lower_error("cannot compare references against zero".to_string())),
+                        _ => b_ctx.ins().icmp_imm(int_cc, value, 0),
+                    };
+
+                    let then_args = then.args.iter().map(|x| value_of(&values, x)).collect::<Result<Vec<_>, _>>()?;
+                    let else_args = else_.args.iter().map(|x| value_of(&values, x)).collect::<Result<Vec<_>, _>>()?;
+                    b_ctx.ins().brif(condition, block_of(&block_map, then.block)?, &then_args, block_of(&block_map, else_.block)?, &else_args);
+                },
+                corrosion_base::Operation::Branch(target) => {
+                    let args = target.args.iter().map(|x| value_of(&values, x)).collect::<Result<Vec<_>, _>>()?;
+                    b_ctx.ins().jump(block_of(&block_map, target.block)?, &args);
+                },
+                corrosion_base::Operation::Return(outputs) => {
+                    let outputs = outputs.iter().map(|x| value_of(&values, x)).collect::<Result<Vec<_>, _>>()?;
+
+                    b_ctx.ins().return_(&outputs);
+                },
+                corrosion_base::Operation::Invoke(callee, args) => {
+                    let func_ref = *func_refs.entry(*callee).or_insert_with(|| {
+                        module.declare_func_in_func(callees(*callee), b_ctx.func)
+                    });
+
+                    let args = args.iter().map(|x| value_of(&values, x)).collect::<Result<Vec<_>, _>>()?;
+                    let call = b_ctx.ins().call(func_ref, &args);
+
+                    let results = b_ctx.inst_results(call);
+                    if results.len() != instruction.immediates().len(){
+                        return Err(lower_error(format!("expected {} outputs from invoking {}, found {}", results.len(), callee, instruction.immediates().len())));
+                    }
+                    for (output, value) in instruction.immediates().iter().zip(results){
+                        values.insert(*output, *value);
+                    }
+                },
+            }
+        }
+    }
+
+    b_ctx.seal_all_blocks();
+    b_ctx.finalize();
+    Ok(())
+}
+
+fn value_of(values: &HashMap<ImmediateRef, Value>, immediate: &ImmediateRef) -> Result<Value, BackendError>{
+    values.get(immediate).copied().ok_or_else(|| lower_error(format!("{} is used before it is defined", immediate)))
+}
+
+fn local_of(locals: &HashMap<corrosion_base::VariableRef, Variable>, var: &corrosion_base::VariableRef) -> Result<Variable, BackendError>{
+    locals.get(var).copied().ok_or_else(|| lower_error(format!("{} is not a local of the function", var)))
+}
+
+fn output_of(instruction: &corrosion_base::Instruction) -> Result<ImmediateRef, BackendError>{
+    match instruction.immediates() {
+        [output] => Ok(*output),
+        x => Err(lower_error(format!("expected 1 output, found {}", x.len()))),
+    }
+}
+
+fn block_of(blocks: &HashMap<corrosion_base::BlockRef, Block>, block: corrosion_base::BlockRef) -> Result<Block, BackendError>{
+    blocks.get(&block).copied().ok_or_else(|| lower_error(format!("{} is not a block of the function", block)))
+}
+
+fn lower_error(message: String) -> BackendError{
+    BackendError::Compile(message.into())
+}
+
+///
+/// Libcalls of lowered functions that the jit provides itself, as the process may not link the C math library
+pub(crate) fn libcalls() -> [(&'static str, *const u8); 2]{
+    extern "C" fn fmodf(a: f32, b: f32) -> f32{
+        a % b
+    }
+    extern "C" fn fmod(a: f64, b: f64) -> f64{
+        a % b
+    }
+    [("fmodf", fmodf as *const u8), ("fmod", fmod as *const u8)]
+}
+
+///
+/// Lowers a base type, `pointer` is the native pointer type of the target
+pub(crate) fn from_base_type(type_: corrosion_base::Type, pointer: Type) -> Type{
+    match type_ {
+        corrosion_base::Type::I8 => types::I8,
+        corrosion_base::Type::I16 => types::I16,
+        corrosion_base::Type::I32 => types::I32,
+        corrosion_base::Type::I64 => types::I64,
+        corrosion_base::Type::F32 => types::F32,
+        corrosion_base::Type::F64 => types::F64,
+        corrosion_base::Type::Ptr => pointer,
+        corrosion_base::Type::Ref => if pointer.bits() == 32 { types::R32 } else { types::R64 },
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use corrosion_base::parse_module;
+
+    use crate::Generator;
+
+    ///
+    /// Load unverified IR into the jit, which must fail to lower it rather than panic
+    fn lowering_error(source: &str) -> String{
+        let mut generator = Generator::new();
+        match generator.load_module(parse_module(source).unwrap()) {
+            Err(err) => err.to_string(),
+            Ok(()) => panic!("expected lowering to fail"),
+        }
+    }
+
+    #[test]
+    fn arithmetic_on_references(){
+        assert_eq!(lowering_error("
+function fn1(ref, ref) -> (ref) {
+block1(%1: ref, %2: ref):
+    %3 = add.ref %1, %2
+    return %3
+}"), "compilation failed: cannot add values of type ref");
+    }
+
+    #[test]
+    fn branching_on_a_reference(){
+        assert_eq!(lowering_error("
+function fn1(ref) -> () {
+block1(%1: ref):
+    br_eq %1, block2, block2
+block2:
+    return
+}"), "compilation failed: cannot compare references against zero");
+    }
+
+    #[test]
+    fn undeclared_local(){
+        assert_eq!(lowering_error("
+function fn1() -> (i32) {
+block1:
+    %1 = load_local $4
+    return %1
+}"), "compilation failed: $4 is not a local of the function");
+    }
+
+    #[test]
+    fn invoke_output_count(){
+        assert_eq!(lowering_error("
+function fn1() -> (i32) {
+block1:
+    %1, %2 = invoke fn2()
+    return %1
+}
+
+function fn2() -> (i32) {
+block1:
+    %1 = const.i32 1
+    return %1
+}"), "compilation failed: expected 1 outputs from invoking fn2, found 2");
+    }
+
+    #[test]
+    fn constant_output_count(){
+        assert_eq!(lowering_error("
+function fn1() -> (i32) {
+block1:
+    %1, %2 = const.i32 1
+    return %1
+}"), "compilation failed: expected 1 output, found 2");
+    }
+}
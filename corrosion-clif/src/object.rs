@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use cranelift::prelude::*;
+use corrosion_base::{Module as CModule, ModuleRef, FunctionRef, BackendError};
+use cranelift_module::{default_libcall_names, FuncId, Module, Linkage};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+
+use crate::{compile_error, lower, GeneratorConfig};
+
+///
+/// Ahead of time compiler producing a relocatable object file for the configured target
+pub struct ObjectCompiler{
+    module: ObjectModule,
+    functions: HashMap<(ModuleRef, FunctionRef), FuncId>,
+}
+
+impl ObjectCompiler{
+    ///
+    /// `name` is the name of the object, recorded in its symbol table
+    pub fn new(config: &GeneratorConfig, name: &str) -> Result<Self, BackendError>{
+        let isa = config.target_isa().map_err(|x| BackendError::Compile(Box::new(x)))?;
+        let builder = ObjectBuilder::new(isa, name, default_libcall_names()).map_err(compile_error)?;
+        Ok(Self{
+            module: ObjectModule::new(builder),
+            functions: HashMap::new()
+        })
+    }
+
+    ///
+    /// Compile every function of the module, named functions are exported under their name
+    pub fn add_module(&mut self, module: &CModule) -> Result<(), BackendError>{
+        let mut ctx = self.module.make_context();
+        let mut f_ctx = FunctionBuilderContext::new();
+
+        for function in &module.functions{
+            let key = (module.id, function.id);
+            if self.functions.contains_key(&key){
+                return Err(BackendError::DuplicateDefinition(module.id, function.id));
+            }
+
+            let sig = lower::signature_of(&self.module, function);
+            let id = if function.name.is_empty(){
+                self.module.declare_anonymous_function(&sig).map_err(compile_error)?
+            }
+            else{
+                self.module.declare_function(&function.name, Linkage::Export, &sig).map_err(compile_error)?
+            };
+            self.functions.insert(key, id);
+        }
+
+        for function in &module.functions{
+            let id = self.functions[&(module.id, function.id)];
+            ctx.func.signature = lower::signature_of(&self.module, function);
+
+            let callees = |callee| self.functions[&(module.id, callee)];
+            lower::lower_function(&mut self.module, &mut ctx.func, &mut f_ctx, function, &callees)?;
+
+            self.module.define_function(id, &mut ctx).map_err(compile_error)?;
+            self.module.clear_context(&mut ctx);
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Emit the object file containing every added module
+    pub fn finish(self) -> Result<Vec<u8>, BackendError>{
+        self.module.finish().emit().map_err(|x| BackendError::Compile(Box::new(x)))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use corrosion_base::parse_module;
+
+    use crate::CpuFeatures;
+
+    use super::*;
+
+    const SOURCE: &str = "
+function fn1 \"add\"(i32, i32) -> (i32) {
+block1(%1: i32, %2: i32):
+    %3 = invoke fn2(%1, %2)
+    return %3
+}
+
+function fn2(i32, i32) -> (i32) {
+block1(%1: i32, %2: i32):
+    %3 = add.i32 %1, %2
+    return %3
+}";
+
+    fn config(target: &str) -> GeneratorConfig{
+        GeneratorConfig{
+            target: Some(target.to_string()),
+            cpu_features: CpuFeatures::Baseline,
+            ..GeneratorConfig::default()
+        }
+    }
+
+    fn compile(target: &str) -> Vec<u8>{
+        let mut compiler = ObjectCompiler::new(&config(target), "test").unwrap();
+        compiler.add_module(&parse_module(SOURCE).unwrap()).unwrap();
+        compiler.finish().unwrap()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool{
+        haystack.windows(needle.len()).any(|x| x == needle)
+    }
+
+    #[test]
+    fn emits_an_elf_object_exporting_named_functions(){
+        let object = compile("x86_64-unknown-linux-gnu");
+        assert!(object.starts_with(b"\x7fELF"));
+        // Relocatable object for x86-64
+        assert_eq!(u16::from_le_bytes([object[16], object[17]]), 1);
+        assert_eq!(u16::from_le_bytes([object[18], object[19]]), 0x3e);
+        assert!(contains(&object, b"add\0"));
+    }
+
+    #[test]
+    fn object_format_follows_the_target(){
+        let object = compile("x86_64-apple-darwin");
+        assert!(object.starts_with(&0xfeedfacf_u32.to_le_bytes()));
+        assert!(contains(&object, b"_add\0"));
+    }
+
+    #[test]
+    fn modules_can_only_be_added_once(){
+        let module = parse_module(SOURCE).unwrap();
+        let mut compiler = ObjectCompiler::new(&config("x86_64-unknown-linux-gnu"), "test").unwrap();
+        compiler.add_module(&module).unwrap();
+        assert!(matches!(compiler.add_module(&module), Err(BackendError::DuplicateDefinition(..))));
+    }
+
+    #[test]
+    fn unknown_target_is_an_error(){
+        assert!(ObjectCompiler::new(&config("not-a-target"), "test").is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use std::{fmt::Display, panic::{catch_unwind, AssertUnwindSafe}};
+
+use corrosion_base::{immediate_types, Function, FunctionRef, ImmediateRef, Instruction, Module, Operation, Type};
+use corrosion_clif::Generator;
+use corrosion_interp::{InterpretError, Interpreter, Scalar};
+
+use crate::{entry_args, generate, ProgramOptions, Rng, ENTRY_INPUTS};
+
+///
+/// A program for which the JIT and the interpreter disagree
+#[derive(Debug)]
+pub struct Mismatch{
+    pub seed: u64,
+    pub module: Module,
+    pub entry: FunctionRef,
+    pub args: Vec<Scalar>,
+
+    ///
+    /// Value returned by the JIT, or the panic message if compiling it failed
+    pub jit: Result<Scalar, String>,
+    pub interpreter: Result<Vec<Scalar>, InterpretError>,
+}
+
+impl Display for Mismatch{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "mismatch for seed {:#x}", self.seed)?;
+        writeln!(f, "args: {:?}", self.args)?;
+        writeln!(f, "jit: {:?}", self.jit)?;
+        match &self.interpreter {
+            Ok(x) => writeln!(f, "interpreter: {:?}", x)?,
+            Err(x) => writeln!(f, "interpreter: {}", x)?,
+        }
+        write!(f, "{:#?}", self.module)
+    }
+}
+
+///
+/// Generate the program for a seed and compare the JIT against the interpreter, minimizing the program on a mismatch
+pub fn run_seed(seed: u64, options: &ProgramOptions) -> Result<(), Box<Mismatch>>{
+    let mut rng = Rng::new(seed);
+    let program = generate(&mut rng, options);
+    let args = entry_args(&mut rng);
+
+    check(&program.module, program.entry, &args)
+        .map_err(|mut x| {
+            x.seed = seed;
+            Box::new(minimize(*x))
+        })
+}
+
+///
+/// Run the entry of a module with both backends
+pub fn check(module: &Module, entry: FunctionRef, args: &[Scalar]) -> Result<(), Box<Mismatch>>{
+    let interpreter = run_interpreter(module, entry, args);
+
+    // A trap in the JIT takes down the whole process, so only run programs the interpreter accepts
+    let jit = match &interpreter {
+        Ok(_) => run_jit(module, entry, args),
+        Err(_) => Err("not run, the interpreter trapped".to_string())
+    };
+
+    let matches = match (&jit, &interpreter) {
+        (Ok(a), Ok(b)) => b.len() == 1 && same(*a, b[0]),
+        _ => false
+    };
+
+    if matches{
+        Ok(())
+    }
+    else{
+        Err(Box::new(Mismatch{
+            seed: 0,
+            module: module.clone(),
+            entry,
+            args: args.to_vec(),
+            jit,
+            interpreter
+        }))
+    }
+}
+
+pub fn run_interpreter(module: &Module, entry: FunctionRef, args: &[Scalar]) -> Result<Vec<Scalar>, InterpretError>{
+    let mut interpreter = Interpreter::new();
+    interpreter.load_module(module.clone());
+    interpreter.call(module.id, entry, args)
+}
+
+///
+/// Compile the module and call the entry, which must take `ENTRY_INPUTS` and return a single value
+pub fn run_jit(module: &Module, entry: FunctionRef, args: &[Scalar]) -> Result<Scalar, String>{
+    let function = module.functions.iter().find(|x| x.id == entry).ok_or("missing entry function")?;
+    assert!(function.inputs == ENTRY_INPUTS, "entry must take the entry inputs");
+
+    let output = *function.outputs.first().ok_or("entry has no outputs")?;
+    let [Scalar::I32(a), Scalar::I32(b), Scalar::I64(c), Scalar::I64(d), Scalar::F32(e), Scalar::F32(f), Scalar::F64(g), Scalar::F64(h)] = *args else {
+        panic!("arguments {:?} does not match the entry inputs", args);
+    };
+
+    let module = module.clone();
+    let id = module.id;
+    let result = catch_unwind(AssertUnwindSafe(move || {
+        let mut generator = Generator::new();
+        if let Err(err) = generator.load_module(module){
+            panic!("{}", err);
+        }
+        let ptr = generator.get_function(id, entry);
+
+        // Safety: the entry was compiled with the signature of ENTRY_INPUTS and a single output of `output`
+        unsafe {
+            match output {
+                Type::I32 => Scalar::I32(std::mem::transmute::<*const u8, extern "C" fn(i32, i32, i64, i64, f32, f32, f64, f64) -> i32>(ptr)(a, b, c, d, e, f, g, h)),
+                Type::I64 => Scalar::I64(std::mem::transmute::<*const u8, extern "C" fn(i32, i32, i64, i64, f32, f32, f64, f64) -> i64>(ptr)(a, b, c, d, e, f, g, h)),
+                Type::F32 => Scalar::F32(std::mem::transmute::<*const u8, extern "C" fn(i32, i32, i64, i64, f32, f32, f64, f64) -> f32>(ptr)(a, b, c, d, e, f, g, h)),
+                Type::F64 => Scalar::F64(std::mem::transmute::<*const u8, extern "C" fn(i32, i32, i64, i64, f32, f32, f64, f64) -> f64>(ptr)(a, b, c, d, e, f, g, h)),
+                _ => panic!("unsupported entry output {:?}", output)
+            }
+        }
+    }));
+
+    result.map_err(|x| {
+        x.downcast_ref::<String>().cloned()
+            .or_else(|| x.downcast_ref::<&str>().map(|x| x.to_string()))
+            .unwrap_or_else(|| "jit panicked".to_string())
+    })
+}
+
+///
+/// Equal bit patterns, or both NaN since the payload of a NaN isn't specified
+fn same(a: Scalar, b: Scalar) -> bool{
+    match (a, b) {
+        (Scalar::F32(a), Scalar::F32(b)) if a.is_nan() && b.is_nan() => true,
+        (Scalar::F64(a), Scalar::F64(b)) if a.is_nan() && b.is_nan() => true,
+        (a, b) => a.bitwise_eq(b)
+    }
+}
+
+///
+/// Shrink a mismatching program while it keeps mismatching, by turning conditional branches into branches,
+/// replacing instructions with zero constants and removing instructions whose outputs are unused
+pub fn minimize(mismatch: Mismatch) -> Mismatch{
+    let mut current = mismatch;
+
+    'outer: loop {
+        for candidate in reductions(&current.module){
+            match check(&candidate, current.entry, &current.args) {
+                Err(reduced) if reduced.interpreter.is_ok() => {
+                    current = Mismatch{
+                        seed: current.seed,
+                        ..*reduced
+                    };
+                    continue 'outer;
+                },
+                _ => {}
+            }
+        }
+        return current;
+    }
+}
+
+fn reductions(module: &Module) -> Vec<Module>{
+    let mut candidates = Vec::new();
+
+    for (f, function) in module.functions.iter().enumerate(){
+        let types = immediate_types(module, function);
+        let used = used_immediates(function);
+
+        for (b, block) in function.blocks.iter().enumerate(){
+            for (i, instruction) in block.instructions.iter().enumerate(){
+                let mut replace = |instruction: Option<Instruction>|{
+                    let mut candidate = module.clone();
+                    let instructions = &mut candidate.functions[f].blocks[b].instructions;
+                    match instruction {
+                        Some(instruction) => instructions[i] = instruction,
+                        None => { instructions.remove(i); },
+                    }
+                    candidates.push(candidate);
+                };
+
+                let operation = instruction.operation();
+                if operation.is_terminator(){
+                    if let [then, else_] = operation.targets()[..]{
+                        replace(Some(Instruction::new(Operation::Branch(then.clone()), vec![])));
+                        replace(Some(Instruction::new(Operation::Branch(else_.clone()), vec![])));
+                    }
+                    continue;
+                }
+
+                if instruction.immediates().iter().all(|x| !used.contains(x)){
+                    replace(None);
+                    continue;
+                }
+
+                if let [output] = instruction.immediates(){
+                    let zero = match types.get(output) {
+                        Some(Type::I32) => Operation::ConstI32(0),
+                        Some(Type::I64) => Operation::ConstI64(0),
+                        Some(Type::F32) => Operation::ConstF32(0.0),
+                        Some(Type::F64) => Operation::ConstF64(0.0),
+                        _ => continue
+                    };
+                    let is_zero = matches!(operation, Operation::ConstI32(0) | Operation::ConstI64(0))
+                        || matches!(operation, Operation::ConstF32(x) if x.to_bits() == 0)
+                        || matches!(operation, Operation::ConstF64(x) if x.to_bits() == 0);
+                    if !is_zero{
+                        replace(Some(Instruction::new(zero, vec![*output])));
+                    }
+                }
+            }
+        }
+    }
+
+    candidates
+}
+
+fn used_immediates(function: &Function) -> Vec<ImmediateRef>{
+    let mut used = Vec::new();
+
+    for block in &function.blocks{
+        for instruction in &block.instructions{
+            match instruction.operation() {
+                Operation::OffsetPtr1(a, b) | Operation::OffsetPtr2(a, b) | Operation::OffsetPtr4(a, b) | Operation::OffsetPtr8(a, b) |
+                Operation::Add(a, b, _) | Operation::Sub(a, b, _) | Operation::Mul(a, b, _) | Operation::Div(a, b, _) | Operation::Mod(a, b, _) |
+                Operation::Write(a, b, _) => used.extend([*a, *b]),
+                Operation::StoreLocal(_, a) | Operation::Read(a, _, _) => used.push(*a),
+                Operation::BranchIfEq(a, ..) | Operation::BranchIfNe(a, ..) | Operation::BranchIfLt(a, ..) |
+                Operation::BranchIfLe(a, ..) | Operation::BranchIfGt(a, ..) | Operation::BranchIfGe(a, ..) => used.push(*a),
+                Operation::Return(x) | Operation::Invoke(_, x) => used.extend(x.iter().copied()),
+                _ => {}
+            }
+            for target in instruction.operation().targets(){
+                used.extend(target.args.iter().copied());
+            }
+        }
+    }
+    used
+}
@@ -0,0 +1,7 @@
+mod harness;
+mod program;
+mod rng;
+
+pub use harness::*;
+pub use program::*;
+pub use rng::*;
@@ -0,0 +1,232 @@
+use corrosion_base::{BlockBuilder, Condition, FunctionRef, Module, ModuleBuilder, Type, Value, VariableRef};
+
+use crate::Rng;
+
+///
+/// Inputs of the entry function of every generated program, so that the JIT compiled entry can be called through a single signature
+pub const ENTRY_INPUTS: [Type; 8] = [Type::I32, Type::I32, Type::I64, Type::I64, Type::F32, Type::F32, Type::F64, Type::F64];
+
+///
+/// Types the generator produces values of, these are the types with constant operations
+const TYPES: [Type; 4] = [Type::I32, Type::I64, Type::F32, Type::F64];
+
+const CONDITIONS: [Condition; 6] = [Condition::Eq, Condition::Ne, Condition::Lt, Condition::Le, Condition::Gt, Condition::Ge];
+
+#[derive(Clone, Debug)]
+pub struct ProgramOptions{
+    ///
+    /// Maximum amount of functions besides the entry
+    pub functions: usize,
+    pub blocks: usize,
+    pub instructions: usize,
+    pub locals: usize,
+}
+
+impl Default for ProgramOptions{
+    fn default() -> Self {
+        Self{
+            functions: 3,
+            blocks: 4,
+            instructions: 8,
+            locals: 2
+        }
+    }
+}
+
+///
+/// A generated module, the entry is the last function and takes `ENTRY_INPUTS`
+pub struct Program{
+    pub module: Module,
+    pub entry: FunctionRef,
+}
+
+struct Signature{
+    id: FunctionRef,
+    inputs: Vec<Type>,
+}
+
+///
+/// Generate a random well typed program.
+///
+/// Programs always terminate: blocks only branch forward and functions only invoke functions generated before them.
+/// Integer division only uses constant divisors other than 0 and -1, and floats are never used with `Mod`,
+/// so a correct backend never traps.
+pub fn generate(rng: &mut Rng, options: &ProgramOptions) -> Program{
+    let mut mb = ModuleBuilder::new();
+    let mut callees = Vec::new();
+
+    for _ in 0..rng.range(0, options.functions){
+        let inputs = (0..rng.range(0, 3)).map(|_| *rng.pick(&TYPES)).collect::<Vec<_>>();
+        let outputs = (0..rng.range(0, 2)).map(|_| *rng.pick(&TYPES)).collect::<Vec<_>>();
+        let id = mb.new_function();
+        generate_function(rng, &mut mb, id, &inputs, &outputs, &callees, options);
+        callees.push(Signature { id, inputs });
+    }
+
+    let entry = mb.new_function();
+    let output = *rng.pick(&TYPES);
+    generate_function(rng, &mut mb, entry, &ENTRY_INPUTS, &[output], &callees, options);
+
+    Program{
+        module: mb.build(),
+        entry
+    }
+}
+
+fn generate_function(rng: &mut Rng, mb: &mut ModuleBuilder, id: FunctionRef, inputs: &[Type], outputs: &[Type], callees: &[Signature], options: &ProgramOptions){
+    let mut fb = mb.funtion_builder(id);
+    for input in inputs{
+        fb.add_input(*input);
+    }
+    for output in outputs{
+        fb.add_output(*output);
+    }
+
+    let locals = (0..rng.range(0, options.locals))
+        .map(|_| {
+            let type_ = *rng.pick(&TYPES);
+            (fb.add_local(type_), type_)
+        })
+        .collect::<Vec<_>>();
+
+    let blocks = (0..rng.range(1, options.blocks)).map(|_| fb.create_block()).collect::<Vec<_>>();
+    fb.block_builder(blocks[0]).into_entry_block();
+
+    let mut params = vec![inputs.to_vec()];
+    for block in &blocks[1..]{
+        let mut bb = fb.block_builder(*block);
+        let types = (0..rng.range(0, 3)).map(|_| *rng.pick(&TYPES)).collect::<Vec<_>>();
+        for type_ in &types{
+            bb.add_param(*type_);
+        }
+        params.push(types);
+    }
+
+    for (index, block) in blocks.iter().enumerate(){
+        let mut bb = fb.block_builder(*block);
+        let mut pool = bb.get_params().to_vec();
+
+        for _ in 0..rng.range(0, options.instructions){
+            generate_instruction(rng, &mut bb, &mut pool, &locals, callees);
+        }
+
+        let later = index + 1..blocks.len();
+        match rng.below(3) {
+            _ if later.is_empty() => {
+                let values = outputs.iter().map(|x| value(rng, &mut bb, &pool, *x)).collect::<Vec<_>>();
+                bb.return_(&values);
+            },
+            0 => {
+                let values = outputs.iter().map(|x| value(rng, &mut bb, &pool, *x)).collect::<Vec<_>>();
+                bb.return_(&values);
+            },
+            1 => {
+                let target = rng.range(later.start, later.end - 1);
+                let args = params[target].iter().map(|x| value(rng, &mut bb, &pool, *x)).collect::<Vec<_>>();
+                bb.branch(blocks[target], &args);
+            },
+            _ => {
+                let condition = *rng.pick(&CONDITIONS);
+                let type_ = *rng.pick(&TYPES);
+                let test = value(rng, &mut bb, &pool, type_);
+
+                let then = rng.range(later.start, later.end - 1);
+                let else_ = rng.range(later.start, later.end - 1);
+                let then_args = params[then].iter().map(|x| value(rng, &mut bb, &pool, *x)).collect::<Vec<_>>();
+                let else_args = params[else_].iter().map(|x| value(rng, &mut bb, &pool, *x)).collect::<Vec<_>>();
+                bb.branch_if(condition, test, (blocks[then], &then_args), (blocks[else_], &else_args));
+            }
+        }
+    }
+}
+
+fn generate_instruction(rng: &mut Rng, bb: &mut BlockBuilder, pool: &mut Vec<Value>, locals: &[(VariableRef, Type)], callees: &[Signature]){
+    match rng.below(6) {
+        0 => {
+            let type_ = *rng.pick(&TYPES);
+            let value = constant(rng, bb, type_);
+            pool.push(value);
+        },
+        1 | 2 => {
+            let type_ = *rng.pick(&TYPES);
+            let a = value(rng, bb, pool, type_);
+            let b = value(rng, bb, pool, type_);
+            let value = match rng.below(3) {
+                0 => bb.add_values(a, b),
+                1 => bb.sub_values(a, b),
+                _ => bb.mul_values(a, b),
+            };
+            pool.push(value);
+        },
+        3 => {
+            let type_ = *rng.pick(&TYPES);
+            let a = value(rng, bb, pool, type_);
+            let value = if type_.is_integer(){
+                // Constant divisors keep division by zero and overflow out of the programs
+                let mut divisor = rng.range(2, 1000) as i64;
+                if rng.chance(1, 2){
+                    divisor = -divisor;
+                }
+                let divisor = if type_ == Type::I32 { bb.const_i32(divisor as i32) } else { bb.const_i64(divisor) };
+                if rng.chance(1, 2) { bb.div_values(a, divisor) } else { bb.modulus_values(a, divisor) }
+            }
+            else{
+                let b = value(rng, bb, pool, type_);
+                bb.div_values(a, b)
+            };
+            pool.push(value);
+        },
+        4 if !locals.is_empty() => {
+            let (var, type_) = *rng.pick(locals);
+            if rng.chance(1, 2){
+                pool.push(bb.get_local(var));
+            }
+            else{
+                let value = value(rng, bb, pool, type_);
+                bb.set_local(var, value);
+            }
+        },
+        5 if !callees.is_empty() => {
+            let callee = rng.pick(callees);
+            let args = callee.inputs.iter().map(|x| value(rng, bb, pool, *x)).collect::<Vec<_>>();
+            let outputs = bb.invoke(callee.id, &args);
+            pool.extend(outputs);
+        },
+        _ => {},
+    }
+}
+
+///
+/// Pick an existing value of the type, or create a new constant
+fn value(rng: &mut Rng, bb: &mut BlockBuilder, pool: &[Value], type_: Type) -> Value{
+    let candidates = pool.iter().filter(|x| x.type_() == type_).collect::<Vec<_>>();
+    if !candidates.is_empty() && rng.chance(3, 4){
+        **rng.pick(&candidates)
+    }
+    else{
+        constant(rng, bb, type_)
+    }
+}
+
+fn constant(rng: &mut Rng, bb: &mut BlockBuilder, type_: Type) -> Value{
+    match type_ {
+        Type::I32 => bb.const_i32(rng.interesting_i64() as i32),
+        Type::I64 => bb.const_i64(rng.interesting_i64()),
+        Type::F32 => bb.const_f32(rng.interesting_f64() as f32),
+        Type::F64 => bb.const_f64(rng.interesting_f64()),
+        _ => unreachable!("no constants of type {:?}", type_)
+    }
+}
+
+///
+/// Random arguments for the entry function
+pub fn entry_args(rng: &mut Rng) -> Vec<corrosion_interp::Scalar>{
+    use corrosion_interp::Scalar;
+
+    ENTRY_INPUTS.iter().map(|x| match x {
+        Type::I32 => Scalar::I32(rng.interesting_i64() as i32),
+        Type::I64 => Scalar::I64(rng.interesting_i64()),
+        Type::F32 => Scalar::F32(rng.interesting_f64() as f32),
+        _ => Scalar::F64(rng.interesting_f64()),
+    }).collect()
+}
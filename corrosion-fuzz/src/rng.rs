@@ -0,0 +1,62 @@
+///
+/// Small deterministic random number generator (SplitMix64), so that a seed always produces the same program
+#[derive(Clone, Debug)]
+pub struct Rng{
+    state: u64,
+}
+
+impl Rng{
+    pub fn new(seed: u64) -> Self{
+        Self{
+            state: seed
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64{
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    ///
+    /// Uniform value in `0..bound`
+    pub fn below(&mut self, bound: usize) -> usize{
+        assert!(bound > 0, "bound must be positive");
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    ///
+    /// Uniform value in `min..=max`
+    pub fn range(&mut self, min: usize, max: usize) -> usize{
+        min + self.below(max - min + 1)
+    }
+
+    pub fn chance(&mut self, numerator: u64, denominator: u64) -> bool{
+        self.next_u64() % denominator < numerator
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T{
+        &items[self.below(items.len())]
+    }
+
+    ///
+    /// Integer biased towards small values and edge cases
+    pub fn interesting_i64(&mut self) -> i64{
+        match self.below(6) {
+            0 => *self.pick(&[0, 1, -1, 2, -2]),
+            1 => *self.pick(&[i64::MIN, i64::MAX, i32::MIN as i64, i32::MAX as i64, u32::MAX as i64]),
+            2 | 3 => self.range(0, 200) as i64 - 100,
+            _ => self.next_u64() as i64
+        }
+    }
+
+    pub fn interesting_f64(&mut self) -> f64{
+        match self.below(5) {
+            0 => *self.pick(&[0.0, -0.0, 1.0, -1.0, 0.5, f64::INFINITY, f64::NEG_INFINITY, f64::NAN]),
+            1 | 2 => (self.range(0, 2000) as f64 - 1000.0) / 8.0,
+            _ => f64::from_bits(self.next_u64())
+        }
+    }
+}
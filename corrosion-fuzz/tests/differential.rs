@@ -0,0 +1,15 @@
+use corrosion_fuzz::{run_seed, ProgramOptions};
+
+const SEED: u64 = 0x5EED_C0DE;
+const PROGRAMS: u64 = 256;
+
+#[test]
+fn jit_matches_interpreter(){
+    let options = ProgramOptions::default();
+
+    for program in 0..PROGRAMS{
+        if let Err(mismatch) = run_seed(SEED.wrapping_add(program), &options){
+            panic!("{}", mismatch);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt::Display;
+
+use corrosion_base::{BlockRef, FunctionRef, ImmediateRef, ModuleRef, Type, VariableRef};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TrapKind{
+    DivisionByZero,
+
+    ///
+    /// Signed division of the smallest integer by -1
+    IntegerOverflow,
+    OutOfBounds{
+        address: u64,
+        size: u64
+    },
+    Misaligned{
+        address: u64,
+        alignment: u64
+    },
+    TypeMismatch{
+        expected: Type,
+        found: Type
+    },
+    ArgumentCount{
+        expected: usize,
+        found: usize
+    },
+    InvalidOperands(Type),
+    UndefinedModule(ModuleRef),
+    UndefinedFunction(FunctionRef),
+    UndefinedBlock(BlockRef),
+    UndefinedImmediate(ImmediateRef),
+    UndefinedVariable(VariableRef),
+    OutputCount{
+        expected: usize,
+        found: usize
+    },
+
+    ///
+    /// The block ran out of instructions without branching or returning
+    MissingTerminator,
+    StackOverflow,
+}
+
+///
+/// A trap together with the instruction that raised it
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InterpretError{
+    pub kind: TrapKind,
+    pub module: ModuleRef,
+    pub function: FunctionRef,
+    pub block: Option<BlockRef>,
+    pub instruction: Option<usize>,
+}
+
+impl Display for TrapKind{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrapKind::DivisionByZero => write!(f, "division by zero"),
+            TrapKind::IntegerOverflow => write!(f, "integer overflow"),
+            TrapKind::OutOfBounds { address, size } => write!(f, "out of bounds access of {} bytes at {:#x}", size, address),
+            TrapKind::Misaligned { address, alignment } => write!(f, "access at {:#x} is not aligned to {} bytes", address, alignment),
+            TrapKind::TypeMismatch { expected, found } => write!(f, "expected a value of type {:?}, found {:?}", expected, found),
+            TrapKind::ArgumentCount { expected, found } => write!(f, "expected {} arguments, found {}", expected, found),
+            TrapKind::InvalidOperands(type_) => write!(f, "invalid operands of type {:?}", type_),
+            TrapKind::UndefinedModule(module) => write!(f, "undefined module {:?}", module),
+            TrapKind::UndefinedFunction(function) => write!(f, "undefined function {:?}", function),
+            TrapKind::UndefinedBlock(block) => write!(f, "undefined block {:?}", block),
+            TrapKind::UndefinedImmediate(immediate) => write!(f, "undefined immediate {:?}", immediate),
+            TrapKind::UndefinedVariable(variable) => write!(f, "undefined variable {:?}", variable),
+            TrapKind::OutputCount { expected, found } => write!(f, "expected {} outputs, found {}", expected, found),
+            TrapKind::MissingTerminator => write!(f, "block does not end with a branch or return"),
+            TrapKind::StackOverflow => write!(f, "stack overflow"),
+        }
+    }
+}
+
+impl Display for InterpretError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} in {:?} {:?}", self.kind, self.module, self.function)?;
+        if let Some(block) = self.block{
+            write!(f, " {:?}", block)?;
+        }
+        if let Some(instruction) = self.instruction{
+            write!(f, " instruction {}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InterpretError{}
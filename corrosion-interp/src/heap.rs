@@ -0,0 +1,109 @@
+use corrosion_base::{Scalar, Type};
+
+use crate::TrapKind;
+
+///
+/// Sandboxed memory for the interpreter, addresses are offsets into a growable buffer.
+/// The first bytes are never handed out so that a null pointer is always out of bounds.
+pub struct Heap{
+    memory: Vec<u8>,
+    limit: u64,
+}
+
+const RESERVED: u64 = 16;
+
+impl Default for Heap{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap{
+    pub fn new() -> Self{
+        Self::with_limit(u32::MAX as u64)
+    }
+
+    ///
+    /// Create a heap which can never grow beyond `limit` bytes
+    pub fn with_limit(limit: u64) -> Self{
+        Self{
+            memory: vec![0; RESERVED as usize],
+            limit
+        }
+    }
+
+    pub fn size(&self) -> u64{
+        self.memory.len() as u64
+    }
+
+    ///
+    /// Allocate zeroed memory, returns `None` if the heap limit would be exceeded
+    pub fn alloc(&mut self, size: u64, alignment: u64) -> Option<u64>{
+        assert!(alignment.is_power_of_two(), "alignment {} is not a power of two", alignment);
+        let start = (self.size() + alignment - 1) & !(alignment - 1);
+        let end = start.checked_add(size)?;
+        if end > self.limit{
+            return None;
+        }
+        self.memory.resize(end as usize, 0);
+        Some(start)
+    }
+
+    fn range(&self, address: u64, size: u64) -> Result<std::ops::Range<usize>, TrapKind>{
+        match address.checked_add(size) {
+            Some(end) if address >= RESERVED && end <= self.size() => Ok(address as usize..end as usize),
+            _ => Err(TrapKind::OutOfBounds { address, size })
+        }
+    }
+
+    pub fn bytes(&self, address: u64, size: u64) -> Result<&[u8], TrapKind>{
+        let range = self.range(address, size)?;
+        Ok(&self.memory[range])
+    }
+
+    pub fn bytes_mut(&mut self, address: u64, size: u64) -> Result<&mut [u8], TrapKind>{
+        let range = self.range(address, size)?;
+        Ok(&mut self.memory[range])
+    }
+
+    pub fn read(&self, address: u64, type_: Type, aligned: bool) -> Result<Scalar, TrapKind>{
+        let size = size_of(type_);
+        check_alignment(address, size, aligned)?;
+        let bytes = self.bytes(address, size)?;
+
+        let mut buffer = [0; 8];
+        buffer[..bytes.len()].copy_from_slice(bytes);
+
+        Ok(Scalar::from_bits(type_, u64::from_le_bytes(buffer)))
+    }
+
+    pub fn write(&mut self, address: u64, value: Scalar, aligned: bool) -> Result<(), TrapKind>{
+        let size = size_of(value.type_());
+        check_alignment(address, size, aligned)?;
+
+        let bits = value.to_bits();
+
+        self.bytes_mut(address, size)?.copy_from_slice(&bits.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+}
+
+///
+/// Size in bytes of a value stored in the heap, pointers are always 64 bits wide
+pub fn size_of(type_: Type) -> u64{
+    match type_ {
+        Type::I8 => 1,
+        Type::I16 => 2,
+        Type::I32 | Type::F32 => 4,
+        Type::I64 | Type::F64 | Type::Ptr | Type::Ref => 8,
+    }
+}
+
+fn check_alignment(address: u64, size: u64, aligned: bool) -> Result<(), TrapKind>{
+    if aligned && !address.is_multiple_of(size){
+        Err(TrapKind::Misaligned { address, alignment: size })
+    }
+    else{
+        Ok(())
+    }
+}
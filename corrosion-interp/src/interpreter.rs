@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+
+use corrosion_base::{Backend, BackendError, Block, BlockCall, BlockRef, Function, FunctionRef, ImmediateRef, Module, ModuleRef, Operation, Scalar, Signature, Type, VariableRef};
+
+use crate::{Heap, InterpretError, TrapKind};
+
+///
+/// Reference interpreter for corrosion-base modules, evaluates the ir directly without compiling it
+pub struct Interpreter{
+    modules: HashMap<ModuleRef, Module>,
+    heap: Heap,
+    max_depth: usize,
+}
+
+impl Default for Interpreter{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter{
+    pub fn new() -> Self{
+        Self::with_heap(Heap::new())
+    }
+
+    pub fn with_heap(heap: Heap) -> Self{
+        Self{
+            modules: HashMap::new(),
+            heap,
+            max_depth: 1024
+        }
+    }
+
+    ///
+    /// Maximum amount of nested invocations before a call traps with a stack overflow
+    pub fn set_max_depth(&mut self, max_depth: usize){
+        self.max_depth = max_depth;
+    }
+
+    ///
+    /// Load a module, replacing any previously loaded module with the same id
+    pub fn load_module(&mut self, module: Module){
+        self.modules.insert(module.id, module);
+    }
+
+    pub fn module(&self, module: ModuleRef) -> Option<&Module>{
+        self.modules.get(&module)
+    }
+
+    pub fn heap(&self) -> &Heap{
+        &self.heap
+    }
+
+    pub fn heap_mut(&mut self) -> &mut Heap{
+        &mut self.heap
+    }
+
+    pub fn call(&mut self, module: ModuleRef, function: FunctionRef, args: &[Scalar]) -> Result<Vec<Scalar>, InterpretError>{
+        let Some(module_) = self.modules.get(&module) else {
+            return Err(InterpretError { kind: TrapKind::UndefinedModule(module), module, function, block: None, instruction: None });
+        };
+
+        let mut executor = Executor{
+            module: module_,
+            heap: &mut self.heap,
+            max_depth: self.max_depth
+        };
+
+        executor.call(function, args, 0)
+    }
+}
+
+impl Backend for Interpreter{
+    fn load_module(&mut self, module: Module) -> Result<(), BackendError> {
+        Interpreter::load_module(self, module);
+        Ok(())
+    }
+
+    fn resolve_function(&self, module: ModuleRef, name: &str) -> Result<FunctionRef, BackendError> {
+        let module_ = self.modules.get(&module).ok_or(BackendError::UndefinedModule(module))?;
+        module_.functions.iter()
+            .find(|x| x.name == name)
+            .map(|x| x.id)
+            .ok_or_else(|| BackendError::UnknownFunction(module, name.to_string()))
+    }
+
+    fn signature(&self, module: ModuleRef, function: FunctionRef) -> Result<Signature, BackendError> {
+        let module_ = self.modules.get(&module).ok_or(BackendError::UndefinedModule(module))?;
+        let function_ = module_.functions.iter()
+            .find(|x| x.id == function)
+            .ok_or(BackendError::UndefinedFunction(module, function))?;
+
+        Ok(Signature {
+            inputs: function_.inputs.clone(),
+            outputs: function_.outputs.clone()
+        })
+    }
+
+    fn invoke(&mut self, module: ModuleRef, function: FunctionRef, args: &[Scalar]) -> Result<Vec<Scalar>, BackendError> {
+        self.signature(module, function)?.check_args(args)?;
+        self.call(module, function, args).map_err(|x| BackendError::Trap(Box::new(x)))
+    }
+}
+
+struct Executor<'a>{
+    module: &'a Module,
+    heap: &'a mut Heap,
+    max_depth: usize,
+}
+
+enum Flow<'a>{
+    Continue,
+    Jump(&'a BlockCall),
+    Return(Vec<Scalar>),
+}
+
+impl<'a> Executor<'a>{
+    fn call(&mut self, function: FunctionRef, args: &[Scalar], depth: usize) -> Result<Vec<Scalar>, InterpretError>{
+        let error = |kind, block, instruction| InterpretError{
+            kind,
+            module: self.module.id,
+            function,
+            block,
+            instruction
+        };
+
+        if depth >= self.max_depth{
+            return Err(error(TrapKind::StackOverflow, None, None));
+        }
+
+        let Some(function_) = self.module.functions.iter().find(|x| x.id == function) else {
+            return Err(error(TrapKind::UndefinedFunction(function), None, None));
+        };
+
+        check_types(&function_.inputs, args).map_err(|x| error(x, None, None))?;
+
+        let mut locals = function_.locals.iter()
+            .map(|(var, type_)| (*var, Scalar::zero(*type_)))
+            .collect::<HashMap<_, _>>();
+        let mut values = HashMap::new();
+
+        let mut block = find_block(function_, function_.entry).map_err(|x| error(x, None, None))?;
+        let mut inputs = args.to_vec();
+
+        loop {
+            bind_inputs(block, &inputs, &mut values).map_err(|x| error(x, Some(block.label), None))?;
+
+            let mut next = None;
+            for (index, instruction) in block.instructions.iter().enumerate(){
+                let trap = |kind| error(kind, Some(block.label), Some(index));
+
+                let flow = match instruction.operation() {
+                    Operation::Invoke(callee, args) => {
+                        let args = args.iter().map(|x| get(&values, *x)).collect::<Result<Vec<_>, _>>().map_err(trap)?;
+                        let outputs = self.call(*callee, &args, depth + 1)?;
+                        let immediates = instruction.immediates();
+                        if immediates.len() != outputs.len(){
+                            return Err(trap(TrapKind::OutputCount { expected: immediates.len(), found: outputs.len() }));
+                        }
+                        for (immediate, value) in immediates.iter().zip(outputs){
+                            values.insert(*immediate, value);
+                        }
+                        Flow::Continue
+                    },
+                    operation => self.step(function_, operation, instruction.immediates(), &mut values, &mut locals).map_err(trap)?
+                };
+
+                match flow {
+                    Flow::Continue => {},
+                    Flow::Jump(target) => {
+                        next = Some(target);
+                        break;
+                    },
+                    Flow::Return(outputs) => {
+                        check_types(&function_.outputs, &outputs).map_err(trap)?;
+                        return Ok(outputs);
+                    },
+                }
+            }
+
+            let Some(target) = next else {
+                return Err(error(TrapKind::MissingTerminator, Some(block.label), None));
+            };
+
+            inputs = target.args.iter().map(|x| get(&values, *x)).collect::<Result<Vec<_>, _>>()
+                .map_err(|x| error(x, Some(block.label), None))?;
+            block = find_block(function_, target.block).map_err(|x| error(x, Some(block.label), None))?;
+        }
+    }
+
+    fn step<'f>(&mut self, function: &Function, operation: &'f Operation, immediates: &[ImmediateRef], values: &mut HashMap<ImmediateRef, Scalar>, locals: &mut HashMap<VariableRef, Scalar>) -> Result<Flow<'f>, TrapKind>{
+        let output = match operation {
+            Operation::ConstI32(value) => Scalar::I32(*value as i32),
+            Operation::ConstI64(value) => Scalar::I64(*value as i64),
+            Operation::ConstF32(value) => Scalar::F32(*value),
+            Operation::ConstF64(value) => Scalar::F64(*value),
+            Operation::OffsetPtr1(ptr, offset) => offset_ptr(get(values, *ptr)?, get(values, *offset)?, 1)?,
+            Operation::OffsetPtr2(ptr, offset) => offset_ptr(get(values, *ptr)?, get(values, *offset)?, 2)?,
+            Operation::OffsetPtr4(ptr, offset) => offset_ptr(get(values, *ptr)?, get(values, *offset)?, 4)?,
+            Operation::OffsetPtr8(ptr, offset) => offset_ptr(get(values, *ptr)?, get(values, *offset)?, 8)?,
+            Operation::Add(a, b, type_) |
+            Operation::Sub(a, b, type_) |
+            Operation::Mul(a, b, type_) |
+            Operation::Div(a, b, type_) |
+            Operation::Mod(a, b, type_) => {
+                let a = get(values, *a)?;
+                let b = get(values, *b)?;
+                check_type(*type_, a)?;
+                check_type(*type_, b)?;
+                arithmetic(operation, a, b)?
+            },
+            Operation::LoadLocal(var) => *locals.get(var).ok_or(TrapKind::UndefinedVariable(*var))?,
+            Operation::StoreLocal(var, value) => {
+                let value = get(values, *value)?;
+                let local = locals.get_mut(var).ok_or(TrapKind::UndefinedVariable(*var))?;
+                check_type(local.type_(), value)?;
+                *local = value;
+                return Ok(Flow::Continue);
+            },
+            Operation::Read(ptr, type_, aligned) => {
+                let address = pointer(get(values, *ptr)?)?;
+                self.heap.read(address, *type_, *aligned)?
+            },
+            Operation::Write(ptr, value, aligned) => {
+                let address = pointer(get(values, *ptr)?)?;
+                self.heap.write(address, get(values, *value)?, *aligned)?;
+                return Ok(Flow::Continue);
+            },
+            Operation::BranchIfEq(value, then, else_) => return branch(get(values, *value)?, then, else_, |x| x == 0, |x| x == 0.0),
+            Operation::BranchIfNe(value, then, else_) => return branch(get(values, *value)?, then, else_, |x| x != 0, |x| x != 0.0),
+            Operation::BranchIfLt(value, then, else_) => return branch(get(values, *value)?, then, else_, |x| x < 0, |x| x < 0.0),
+            Operation::BranchIfLe(value, then, else_) => return branch(get(values, *value)?, then, else_, |x| x <= 0, |x| x <= 0.0),
+            Operation::BranchIfGt(value, then, else_) => return branch(get(values, *value)?, then, else_, |x| x > 0, |x| x > 0.0),
+            Operation::BranchIfGe(value, then, else_) => return branch(get(values, *value)?, then, else_, |x| x >= 0, |x| x >= 0.0),
+            Operation::Branch(target) => return Ok(Flow::Jump(target)),
+            Operation::Return(outputs) => {
+                let outputs = outputs.iter().map(|x| get(values, *x)).collect::<Result<Vec<_>, _>>()?;
+                if outputs.len() != function.outputs.len(){
+                    return Err(TrapKind::OutputCount { expected: function.outputs.len(), found: outputs.len() });
+                }
+                return Ok(Flow::Return(outputs));
+            },
+            Operation::Invoke(_, _) => unreachable!("invocations are handled by the caller"),
+        };
+
+        let [immediate] = immediates else {
+            return Err(TrapKind::OutputCount { expected: 1, found: immediates.len() });
+        };
+        values.insert(*immediate, output);
+
+        Ok(Flow::Continue)
+    }
+}
+
+fn find_block(function: &Function, block: BlockRef) -> Result<&Block, TrapKind>{
+    function.blocks.iter().find(|x| x.label == block).ok_or(TrapKind::UndefinedBlock(block))
+}
+
+fn bind_inputs(block: &Block, inputs: &[Scalar], values: &mut HashMap<ImmediateRef, Scalar>) -> Result<(), TrapKind>{
+    check_types(&block.inputs.iter().map(|x| x.type_()).collect::<Vec<_>>(), inputs)?;
+    for (input, value) in block.inputs.iter().zip(inputs){
+        values.insert(input.immediate(), *value);
+    }
+    Ok(())
+}
+
+fn get(values: &HashMap<ImmediateRef, Scalar>, immediate: ImmediateRef) -> Result<Scalar, TrapKind>{
+    values.get(&immediate).copied().ok_or(TrapKind::UndefinedImmediate(immediate))
+}
+
+fn check_type(expected: Type, value: Scalar) -> Result<(), TrapKind>{
+    if value.type_() == expected{
+        Ok(())
+    }
+    else{
+        Err(TrapKind::TypeMismatch { expected, found: value.type_() })
+    }
+}
+
+fn check_types(expected: &[Type], values: &[Scalar]) -> Result<(), TrapKind>{
+    if expected.len() != values.len(){
+        return Err(TrapKind::ArgumentCount { expected: expected.len(), found: values.len() });
+    }
+    for (type_, value) in expected.iter().zip(values){
+        check_type(*type_, *value)?;
+    }
+    Ok(())
+}
+
+fn pointer(value: Scalar) -> Result<u64, TrapKind>{
+    match value {
+        Scalar::Ptr(address) => Ok(address),
+        x => Err(TrapKind::TypeMismatch { expected: Type::Ptr, found: x.type_() })
+    }
+}
+
+fn offset_ptr(ptr: Scalar, offset: Scalar, scale: i64) -> Result<Scalar, TrapKind>{
+    let address = pointer(ptr)?;
+    let offset = match offset {
+        Scalar::I8(_) | Scalar::I16(_) | Scalar::I32(_) | Scalar::I64(_) => offset.as_i64().unwrap(),
+        x => return Err(TrapKind::InvalidOperands(x.type_()))
+    };
+    Ok(Scalar::Ptr(address.wrapping_add(offset.wrapping_mul(scale) as u64)))
+}
+
+fn branch<'f>(value: Scalar, then: &'f BlockCall, else_: &'f BlockCall, int: impl Fn(i64) -> bool, float: impl Fn(f64) -> bool) -> Result<Flow<'f>, TrapKind>{
+    let taken = match value {
+        Scalar::F32(x) => float(x as f64),
+        Scalar::F64(x) => float(x),
+        x => int(x.as_i64().unwrap())
+    };
+    Ok(Flow::Jump(if taken { then } else { else_ }))
+}
+
+macro_rules! integer_arithmetic {
+    ($operation:expr, $a:expr, $b:expr, $min:expr) => {
+        match $operation {
+            Operation::Add(..) => $a.wrapping_add($b),
+            Operation::Sub(..) => $a.wrapping_sub($b),
+            Operation::Mul(..) => $a.wrapping_mul($b),
+            Operation::Div(..) if $b == 0 => return Err(TrapKind::DivisionByZero),
+            Operation::Div(..) if $a == $min && $b == -1 => return Err(TrapKind::IntegerOverflow),
+            Operation::Div(..) => $a / $b,
+            Operation::Mod(..) if $b == 0 => return Err(TrapKind::DivisionByZero),
+            Operation::Mod(..) => $a.wrapping_rem($b),
+            _ => unreachable!()
+        }
+    };
+}
+
+macro_rules! float_arithmetic {
+    ($operation:expr, $a:expr, $b:expr) => {
+        match $operation {
+            Operation::Add(..) => $a + $b,
+            Operation::Sub(..) => $a - $b,
+            Operation::Mul(..) => $a * $b,
+            Operation::Div(..) => $a / $b,
+            Operation::Mod(..) => $a % $b,
+            _ => unreachable!()
+        }
+    };
+}
+
+fn arithmetic(operation: &Operation, a: Scalar, b: Scalar) -> Result<Scalar, TrapKind>{
+    Ok(match (a, b) {
+        (Scalar::I8(a), Scalar::I8(b)) => Scalar::I8(integer_arithmetic!(operation, a, b, i8::MIN)),
+        (Scalar::I16(a), Scalar::I16(b)) => Scalar::I16(integer_arithmetic!(operation, a, b, i16::MIN)),
+        (Scalar::I32(a), Scalar::I32(b)) => Scalar::I32(integer_arithmetic!(operation, a, b, i32::MIN)),
+        (Scalar::I64(a), Scalar::I64(b)) => Scalar::I64(integer_arithmetic!(operation, a, b, i64::MIN)),
+        (Scalar::F32(a), Scalar::F32(b)) => Scalar::F32(float_arithmetic!(operation, a, b)),
+        (Scalar::F64(a), Scalar::F64(b)) => Scalar::F64(float_arithmetic!(operation, a, b)),
+        (a, _) => return Err(TrapKind::InvalidOperands(a.type_()))
+    })
+}
+
+#[cfg(test)]
+mod tests{
+    use corrosion_base::{BlockBuilder, Condition, FunctionBuilder, ModuleBuilder, Value};
+
+    use super::*;
+
+    struct Fixture{
+        interpreter: Interpreter,
+        module: ModuleRef,
+        function: FunctionRef,
+    }
+
+    impl Fixture{
+        fn call(&mut self, args: &[Scalar]) -> Result<Vec<Scalar>, InterpretError>{
+            self.interpreter.call(self.module, self.function, args)
+        }
+
+        fn trap(&mut self, args: &[Scalar]) -> TrapKind{
+            self.call(args).expect_err("expected a trap").kind
+        }
+    }
+
+    ///
+    /// Build a module with a single function and load it into a fresh interpreter
+    fn fixture(inputs: &[Type], outputs: &[Type], body: impl FnOnce(&mut FunctionBuilder, FunctionRef)) -> Fixture{
+        let mut mb = ModuleBuilder::new();
+        let function = mb.new_function();
+        let mut fb = mb.funtion_builder(function);
+        for type_ in inputs{
+            fb.add_input(*type_);
+        }
+        for type_ in outputs{
+            fb.add_output(*type_);
+        }
+        body(&mut fb, function);
+
+        let module = mb.build();
+        let mut interpreter = Interpreter::new();
+        let id = module.id;
+        interpreter.load_module(module);
+        Fixture{ interpreter, module: id, function }
+    }
+
+    fn binary(operation: fn(&mut BlockBuilder, Value, Value) -> Value) -> Fixture{
+        fixture(&[Type::I32, Type::I32], &[Type::I32], |fb, _| {
+            let entry = fb.create_block();
+            let mut bb = fb.block_builder(entry);
+            bb.into_entry_block();
+            let [a, b] = *bb.get_params() else { unreachable!() };
+            let x = operation(&mut bb, a, b);
+            bb.return_(&[x]);
+        })
+    }
+
+    #[test]
+    fn division_by_zero_traps(){
+        let mut fixture = binary(|bb, a, b| bb.div_values(a, b));
+        assert_eq!(fixture.call(&[Scalar::I32(7), Scalar::I32(2)]), Ok(vec![Scalar::I32(3)]));
+        assert_eq!(fixture.trap(&[Scalar::I32(7), Scalar::I32(0)]), TrapKind::DivisionByZero);
+        assert_eq!(fixture.trap(&[Scalar::I32(i32::MIN), Scalar::I32(-1)]), TrapKind::IntegerOverflow);
+
+        let mut fixture = binary(|bb, a, b| bb.modulus_values(a, b));
+        assert_eq!(fixture.trap(&[Scalar::I32(7), Scalar::I32(0)]), TrapKind::DivisionByZero);
+        assert_eq!(fixture.call(&[Scalar::I32(i32::MIN), Scalar::I32(-1)]), Ok(vec![Scalar::I32(0)]));
+    }
+
+    #[test]
+    fn trap_reports_its_location(){
+        let mut fixture = binary(|bb, a, b| {
+            let x = bb.add_values(a, b);
+            bb.div_values(x, b)
+        });
+        let error = fixture.call(&[Scalar::I32(1), Scalar::I32(0)]).unwrap_err();
+        let entry = fixture.interpreter.module(fixture.module).unwrap().functions[0].entry;
+        assert_eq!(error, InterpretError{
+            kind: TrapKind::DivisionByZero,
+            module: fixture.module,
+            function: fixture.function,
+            block: Some(entry),
+            instruction: Some(1)
+        });
+    }
+
+    fn read(aligned: bool) -> Fixture{
+        fixture(&[Type::Ptr], &[Type::I32], |fb, _| {
+            let entry = fb.create_block();
+            let mut bb = fb.block_builder(entry);
+            bb.into_entry_block();
+            let ptr = bb.get_params()[0];
+            let x = bb.read(ptr, Type::I32, aligned);
+            bb.return_(&[x]);
+        })
+    }
+
+    #[test]
+    fn out_of_bounds_access_traps(){
+        let mut fixture = read(false);
+        let address = fixture.interpreter.heap_mut().alloc(8, 8).unwrap();
+        fixture.interpreter.heap_mut().write(address + 4, Scalar::I32(42), true).unwrap();
+
+        assert_eq!(fixture.call(&[Scalar::Ptr(address + 4)]), Ok(vec![Scalar::I32(42)]));
+        assert_eq!(fixture.trap(&[Scalar::Ptr(0)]), TrapKind::OutOfBounds { address: 0, size: 4 });
+        assert_eq!(fixture.trap(&[Scalar::Ptr(address + 6)]), TrapKind::OutOfBounds { address: address + 6, size: 4 });
+        assert_eq!(fixture.trap(&[Scalar::Ptr(u64::MAX)]), TrapKind::OutOfBounds { address: u64::MAX, size: 4 });
+    }
+
+    #[test]
+    fn misaligned_access_traps(){
+        let mut fixture = read(true);
+        let address = fixture.interpreter.heap_mut().alloc(8, 8).unwrap();
+        assert_eq!(fixture.trap(&[Scalar::Ptr(address + 2)]), TrapKind::Misaligned { address: address + 2, alignment: 4 });
+
+        let mut fixture = read(false);
+        let address = fixture.interpreter.heap_mut().alloc(8, 8).unwrap();
+        assert_eq!(fixture.call(&[Scalar::Ptr(address + 2)]), Ok(vec![Scalar::I32(0)]));
+    }
+
+    #[test]
+    fn unbounded_recursion_overflows_the_stack(){
+        let mut fixture = fixture(&[Type::I32], &[Type::I32], |fb, function| {
+            let entry = fb.create_block();
+            let mut bb = fb.block_builder(entry);
+            bb.into_entry_block();
+            let x = bb.get_params()[0];
+            let outputs = bb.invoke(function, &[x]);
+            bb.return_(&outputs);
+        });
+        fixture.interpreter.set_max_depth(8);
+        let error = fixture.call(&[Scalar::I32(1)]).unwrap_err();
+        assert_eq!(error.kind, TrapKind::StackOverflow);
+        assert_eq!(error.block, None);
+    }
+
+    #[test]
+    fn branches_pass_block_arguments(){
+        // Returns (x, 0) for negative x and (1, x) otherwise, through a shared exit block
+        let mut fixture = fixture(&[Type::I32], &[Type::I32, Type::I32], |fb, _| {
+            let entry = fb.create_block();
+            let exit = fb.create_block();
+
+            let mut bb = fb.block_builder(exit);
+            let a = bb.add_param(Type::I32);
+            let b = bb.add_param(Type::I32);
+            bb.return_(&[a, b]);
+
+            let mut bb = fb.block_builder(entry);
+            bb.into_entry_block();
+            let x = bb.get_params()[0];
+            let zero = bb.const_i32(0);
+            let one = bb.const_i32(1);
+            bb.branch_if(Condition::Lt, x, (exit, &[x, zero]), (exit, &[one, x]));
+        });
+
+        assert_eq!(fixture.call(&[Scalar::I32(-5)]), Ok(vec![Scalar::I32(-5), Scalar::I32(0)]));
+        assert_eq!(fixture.call(&[Scalar::I32(5)]), Ok(vec![Scalar::I32(1), Scalar::I32(5)]));
+    }
+
+    #[test]
+    fn block_arguments_are_type_checked(){
+        let mut fixture = fixture(&[Type::I32], &[Type::I32], |fb, _| {
+            let entry = fb.create_block();
+            let mut bb = fb.block_builder(entry);
+            bb.into_entry_block();
+            let x = bb.get_params()[0];
+            bb.return_(&[x]);
+        });
+        assert_eq!(fixture.trap(&[Scalar::I64(1)]), TrapKind::TypeMismatch { expected: Type::I32, found: Type::I64 });
+        assert_eq!(fixture.trap(&[]), TrapKind::ArgumentCount { expected: 1, found: 0 });
+    }
+}
@@ -0,0 +1,9 @@
+mod error;
+mod heap;
+mod interpreter;
+
+pub use error::*;
+pub use heap::*;
+pub use interpreter::*;
+
+pub use corrosion_base::Scalar;
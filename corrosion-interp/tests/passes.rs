@@ -0,0 +1,196 @@
+use corrosion_base::{parse_module, passes::{DemoteBlockInputs, PassManager, PromoteLocals}, verify_module, Module, Scalar};
+use corrosion_interp::{Interpreter, TrapKind};
+
+///
+/// Name of the function to invoke and its arguments
+type Call = (&'static str, &'static [Scalar]);
+
+///
+/// Programs exercising the default passes, with the calls their results are compared on
+const PROGRAMS: &[(&str, &[Call])] = &[
+    ("
+function fn1 \"fold\"(i32) -> (i32, i64) {
+block1(%1: i32):
+    %2 = const.i32 6
+    %3 = const.i32 7
+    %4 = mul.i32 %2, %3
+    %5 = const.i64 -9223372036854775808
+    %6 = const.i64 -1
+    %7 = mod.i64 %5, %6
+    br_gt %4, block2, block3
+block2:
+    %8 = add.i32 %4, %1
+    %9 = const.i32 2147483647
+    %10 = add.i32 %9, %8
+    return %10, %7
+block3:
+    %11 = const.i32 0
+    %12 = div.i32 %4, %11
+    return %12, %7
+}
+", &[("fold", &[Scalar::I32(5)]), ("fold", &[Scalar::I32(-42)])]),
+    ("
+function fn1 \"loop\"(i32, i32) -> (i32) {
+    local $1: i32
+block1(%1: i32, %2: i32):
+    %3 = mul.i32 %1, %2
+    %4 = div.i32 %1, %2
+    %5 = const.i32 0
+    store_local $1, %5
+    br block2(%5, %3)
+block2(%6: i32, %7: i32):
+    %8 = add.i32 %7, %7
+    %9 = const.i32 1
+    %10 = add.i32 %6, %9
+    %11 = add.i32 %9, %6
+    %12 = load_local $1
+    %13 = add.i32 %12, %11
+    store_local $1, %13
+    %14 = sub.i32 %10, %1
+    br_lt %14, block2(%11, %8), block3
+block3:
+    %15 = load_local $1
+    return %15
+}
+", &[("loop", &[Scalar::I32(4), Scalar::I32(2)]), ("loop", &[Scalar::I32(4), Scalar::I32(0)]), ("loop", &[Scalar::I32(-3), Scalar::I32(1)])]),
+    ("
+function fn1 \"cfg\"(i32, i32) -> (i32) {
+block1(%1: i32, %2: i32):
+    %3 = add.i32 %1, %2
+    %4 = add.i32 %2, %1
+    %5 = sub.i32 %2, %1
+    br_eq %1, block2, block3
+block2:
+    %6 = const.i32 9
+    %7 = sub.i32 %1, %2
+    %8 = mul.i32 %6, %7
+    br block4(%8)
+block3:
+    %9 = const.i32 9
+    %10 = mul.i32 %9, %5
+    br block4(%10)
+block4(%11: i32):
+    %12 = add.i32 %3, %4
+    %13 = add.i32 %11, %12
+    br block5
+block5:
+    %14 = invoke fn2(%13)
+    return %14
+block6:
+    %15 = const.i32 1
+    br block6
+}
+
+function fn2 \"half\"(i32) -> (i32) {
+block1(%1: i32):
+    %2 = const.i32 2
+    %3 = div.i32 %1, %2
+    return %3
+}
+", &[("cfg", &[Scalar::I32(0), Scalar::I32(5)]), ("cfg", &[Scalar::I32(3), Scalar::I32(5)]), ("half", &[Scalar::I32(-7)])]),
+    ("
+function fn1 \"floats\"(f64, f64) -> (f64) {
+block1(%1: f64, %2: f64):
+    %3 = add.f64 %1, %2
+    %4 = add.f64 %2, %1
+    %5 = const.f64 0.0
+    %6 = const.f64 -0.0
+    %7 = mul.f64 %3, %5
+    %8 = mul.f64 %4, %6
+    %9 = sub.f64 %7, %8
+    %10 = const.f64 7.5
+    %11 = const.f64 2.0
+    %12 = mod.f64 %10, %11
+    %13 = add.f64 %9, %12
+    return %13
+}
+", &[("floats", &[Scalar::F64(1.5), Scalar::F64(2.0)]), ("floats", &[Scalar::F64(-1.5), Scalar::F64(-2.0)])]),
+    ("
+function fn1 \"main\"(i32) -> (i32) {
+block1(%1: i32):
+    %2 = invoke fn2(%1)
+    %3 = invoke fn3(%2)
+    %4 = invoke fn3(%3)
+    %5 = invoke fn4(%4)
+    return %5
+}
+
+function fn2 \"twice\"(i32) -> (i32) {
+block1(%1: i32):
+    %2 = add.i32 %1, %1
+    return %2
+}
+
+function fn3 \"accumulate\"(i32) -> (i32) inline {
+    local $1: i32
+block1(%1: i32):
+    %2 = load_local $1
+    %3 = add.i32 %2, %1
+    store_local $1, %3
+    return %3
+}
+
+function fn4 \"down\"(i32) -> (i32) {
+block1(%1: i32):
+    br_gt %1, block2, block3
+block2:
+    %2 = const.i32 3
+    %3 = sub.i32 %1, %2
+    %4 = invoke fn4(%3)
+    return %4
+block3:
+    return %1
+}
+", &[("main", &[Scalar::I32(5)]), ("main", &[Scalar::I32(-5)]), ("down", &[Scalar::I32(100)])]),
+];
+
+fn results(module: &Module, calls: &[Call]) -> Vec<Result<Vec<Scalar>, TrapKind>>{
+    let mut interpreter = Interpreter::new();
+    interpreter.load_module(module.clone());
+    calls.iter().map(|(name, args)| {
+        let function = module.functions.iter().find(|x| x.name == *name).unwrap().id;
+        interpreter.call(module.id, function, args).map_err(|x| x.kind)
+    }).collect()
+}
+
+///
+/// Run `manager` on every program and compare the interpreter's results before and after
+fn assert_equivalent(name: &str, manager: &PassManager){
+    for (source, calls) in PROGRAMS{
+        let original = parse_module(source).unwrap();
+        let mut module = original.clone();
+        manager.run(&mut module).unwrap();
+        verify_module(&module).unwrap();
+        assert_eq!(results(&module, calls), results(&original, calls), "{}\n{}", name, module);
+    }
+}
+
+#[test]
+fn each_pass_preserves_results(){
+    let names = PassManager::with_default_passes().passes().map(|(name, _)| name.to_string()).collect::<Vec<_>>();
+    for name in &names{
+        let mut manager = PassManager::with_default_passes();
+        for other in names.iter().filter(|x| *x != name){
+            manager.set_enabled(other, false);
+        }
+        assert_equivalent(name, &manager);
+    }
+}
+
+#[test]
+fn default_passes_preserve_results(){
+    let mut manager = PassManager::with_default_passes();
+    manager.set_verify(true);
+    assert_equivalent("default passes", &manager);
+}
+
+#[test]
+fn demoting_and_promoting_preserves_results(){
+    let mut manager = PassManager::new();
+    manager.add(DemoteBlockInputs);
+    assert_equivalent("reg2mem", &manager);
+
+    manager.add(PromoteLocals);
+    manager.set_max_iterations(1);
+    assert_equivalent("reg2mem and mem2reg", &manager);
+}
@@ -0,0 +1,592 @@
+use std::fmt::Display;
+
+///
+/// Type of the values an instruction operates on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind{
+    Int,
+    Long,
+    Float,
+    Double,
+    Reference,
+}
+
+///
+/// Type of the elements of an array load or store, byte arrays are also used for booleans
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArrayKind{
+    Int,
+    Long,
+    Float,
+    Double,
+    Reference,
+    Byte,
+    Char,
+    Short,
+}
+
+///
+/// Element type of `newarray`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrimitiveArray{
+    Boolean,
+    Char,
+    Float,
+    Double,
+    Byte,
+    Short,
+    Int,
+    Long,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Condition{
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Gt,
+    Le,
+}
+
+///
+/// A decoded instruction. Instructions with the operand encoded in the opcode, like `iload_1` or `iconst_2`,
+/// and `wide` instructions decode to the same variant as their general form.
+/// Branch targets are absolute offsets into the bytecode
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction{
+    Nop,
+    AconstNull,
+
+    ///
+    /// `iconst_<n>`, `bipush` and `sipush`
+    Iconst(i32),
+    Lconst(i64),
+    Fconst(f32),
+    Dconst(f64),
+
+    ///
+    /// `ldc` and `ldc_w`
+    Ldc(u16),
+    Ldc2(u16),
+
+    Load(Kind, u16),
+    Store(Kind, u16),
+    ArrayLoad(ArrayKind),
+    ArrayStore(ArrayKind),
+
+    Pop,
+    Pop2,
+    Dup,
+    DupX1,
+    DupX2,
+    Dup2,
+    Dup2X1,
+    Dup2X2,
+    Swap,
+
+    Add(Kind),
+    Sub(Kind),
+    Mul(Kind),
+    Div(Kind),
+    Rem(Kind),
+    Neg(Kind),
+    Shl(Kind),
+    Shr(Kind),
+    Ushr(Kind),
+    And(Kind),
+    Or(Kind),
+    Xor(Kind),
+    Iinc{
+        index: u16,
+        value: i16
+    },
+
+    ///
+    /// Conversion between ints, longs, floats and doubles
+    Convert(Kind, Kind),
+    IntToByte,
+    IntToChar,
+    IntToShort,
+
+    Lcmp,
+    Fcmpl,
+    Fcmpg,
+    Dcmpl,
+    Dcmpg,
+
+    ///
+    /// Compare an int against zero
+    If(Condition, u32),
+    IfIcmp(Condition, u32),
+    IfAcmpEq(u32),
+    IfAcmpNe(u32),
+    IfNull(u32),
+    IfNonNull(u32),
+
+    ///
+    /// `goto` and `goto_w`
+    Goto(u32),
+
+    ///
+    /// `jsr` and `jsr_w`
+    Jsr(u32),
+    Ret(u16),
+    TableSwitch{
+        default: u32,
+        low: i32,
+        targets: Vec<u32>
+    },
+    LookupSwitch{
+        default: u32,
+        pairs: Vec<(i32, u32)>
+    },
+
+    ///
+    /// `return` has no kind
+    Return(Option<Kind>),
+
+    GetStatic(u16),
+    PutStatic(u16),
+    GetField(u16),
+    PutField(u16),
+    InvokeVirtual(u16),
+    InvokeSpecial(u16),
+    InvokeStatic(u16),
+    InvokeInterface{
+        index: u16,
+        count: u8
+    },
+    InvokeDynamic(u16),
+
+    New(u16),
+    NewArray(PrimitiveArray),
+    AnewArray(u16),
+    ArrayLength,
+    Athrow,
+    CheckCast(u16),
+    InstanceOf(u16),
+    MonitorEnter,
+    MonitorExit,
+    MultiAnewArray{
+        index: u16,
+        dimensions: u8
+    },
+}
+
+///
+/// Offsets are those of the instruction that failed to decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BytecodeError{
+    Truncated(u32),
+    InvalidOpcode{
+        offset: u32,
+        opcode: u8
+    },
+
+    ///
+    /// `wide` followed by an instruction it can't modify
+    InvalidWide{
+        offset: u32,
+        opcode: u8
+    },
+    InvalidOperand(u32),
+    InvalidBranchTarget{
+        offset: u32,
+        target: i64
+    },
+}
+
+impl Display for BytecodeError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BytecodeError::Truncated(offset) => write!(f, "instruction at {} is truncated", offset),
+            BytecodeError::InvalidOpcode { offset, opcode } => write!(f, "invalid opcode {:#04x} at {}", opcode, offset),
+            BytecodeError::InvalidWide { offset, opcode } => write!(f, "wide at {} modifies opcode {:#04x}", offset, opcode),
+            BytecodeError::InvalidOperand(offset) => write!(f, "invalid operand for instruction at {}", offset),
+            BytecodeError::InvalidBranchTarget { offset, target } => write!(f, "instruction at {} branches to {}, outside of the method", offset, target),
+        }
+    }
+}
+
+impl std::error::Error for BytecodeError{}
+
+///
+/// Decode all instructions of a method's bytecode, paired with their offsets
+pub fn decode(bytecode: &[u8]) -> Result<Vec<(u32, Instruction)>, BytecodeError>{
+    Instructions::new(bytecode).collect()
+}
+
+///
+/// Iterator decoding one instruction at a time, it stops after the first error
+pub struct Instructions<'a>{
+    bytecode: &'a [u8],
+    position: usize,
+    start: u32,
+    failed: bool,
+}
+
+impl<'a> Instructions<'a>{
+    pub fn new(bytecode: &'a [u8]) -> Self{
+        Self{
+            bytecode,
+            position: 0,
+            start: 0,
+            failed: false
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError>{
+        let x = *self.bytecode.get(self.position).ok_or(BytecodeError::Truncated(self.start))?;
+        self.position += 1;
+        Ok(x)
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError>{
+        Ok(u16::from_be_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn i32(&mut self) -> Result<i32, BytecodeError>{
+        Ok(i32::from_be_bytes([self.u8()?, self.u8()?, self.u8()?, self.u8()?]))
+    }
+
+    ///
+    /// Local variable index, which is two bytes after `wide`
+    fn index(&mut self, wide: bool) -> Result<u16, BytecodeError>{
+        if wide { self.u16() } else { self.u8().map(u16::from) }
+    }
+
+    fn target(&self, offset: i64) -> Result<u32, BytecodeError>{
+        let target = self.start as i64 + offset;
+        if target < 0 || target >= self.bytecode.len() as i64{
+            return Err(BytecodeError::InvalidBranchTarget { offset: self.start, target });
+        }
+        Ok(target as u32)
+    }
+
+    fn branch(&mut self) -> Result<u32, BytecodeError>{
+        let offset = self.u16()? as i16;
+        self.target(offset as i64)
+    }
+
+    fn branch_wide(&mut self) -> Result<u32, BytecodeError>{
+        let offset = self.i32()?;
+        self.target(offset as i64)
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, BytecodeError>{
+        let opcode = self.u8()?;
+        Ok(match opcode {
+            0x00 => Instruction::Nop,
+            0x01 => Instruction::AconstNull,
+            0x02..=0x08 => Instruction::Iconst(opcode as i32 - 0x03),
+            0x09..=0x0a => Instruction::Lconst((opcode - 0x09) as i64),
+            0x0b..=0x0d => Instruction::Fconst((opcode - 0x0b) as f32),
+            0x0e..=0x0f => Instruction::Dconst((opcode - 0x0e) as f64),
+            0x10 => Instruction::Iconst(self.u8()? as i8 as i32),
+            0x11 => Instruction::Iconst(self.u16()? as i16 as i32),
+            0x12 => Instruction::Ldc(self.u8()? as u16),
+            0x13 => Instruction::Ldc(self.u16()?),
+            0x14 => Instruction::Ldc2(self.u16()?),
+            0x15..=0x19 => Instruction::Load(KINDS[(opcode - 0x15) as usize], self.index(false)?),
+            0x1a..=0x2d => Instruction::Load(KINDS[((opcode - 0x1a) / 4) as usize], ((opcode - 0x1a) % 4) as u16),
+            0x2e..=0x35 => Instruction::ArrayLoad(ARRAY_KINDS[(opcode - 0x2e) as usize]),
+            0x36..=0x3a => Instruction::Store(KINDS[(opcode - 0x36) as usize], self.index(false)?),
+            0x3b..=0x4e => Instruction::Store(KINDS[((opcode - 0x3b) / 4) as usize], ((opcode - 0x3b) % 4) as u16),
+            0x4f..=0x56 => Instruction::ArrayStore(ARRAY_KINDS[(opcode - 0x4f) as usize]),
+            0x57 => Instruction::Pop,
+            0x58 => Instruction::Pop2,
+            0x59 => Instruction::Dup,
+            0x5a => Instruction::DupX1,
+            0x5b => Instruction::DupX2,
+            0x5c => Instruction::Dup2,
+            0x5d => Instruction::Dup2X1,
+            0x5e => Instruction::Dup2X2,
+            0x5f => Instruction::Swap,
+            0x60..=0x77 => {
+                let kind = KINDS[((opcode - 0x60) % 4) as usize];
+                match (opcode - 0x60) / 4 {
+                    0 => Instruction::Add(kind),
+                    1 => Instruction::Sub(kind),
+                    2 => Instruction::Mul(kind),
+                    3 => Instruction::Div(kind),
+                    4 => Instruction::Rem(kind),
+                    _ => Instruction::Neg(kind),
+                }
+            },
+            0x78..=0x83 => {
+                let kind = KINDS[((opcode - 0x78) % 2) as usize];
+                match (opcode - 0x78) / 2 {
+                    0 => Instruction::Shl(kind),
+                    1 => Instruction::Shr(kind),
+                    2 => Instruction::Ushr(kind),
+                    3 => Instruction::And(kind),
+                    4 => Instruction::Or(kind),
+                    _ => Instruction::Xor(kind),
+                }
+            },
+            0x84 => Instruction::Iinc { index: self.index(false)?, value: self.u8()? as i8 as i16 },
+            0x85..=0x90 => {
+                // Each kind converts to the three others, in order
+                let from = (opcode - 0x85) / 3;
+                let mut to = (opcode - 0x85) % 3;
+                if to >= from{
+                    to += 1;
+                }
+                Instruction::Convert(KINDS[from as usize], KINDS[to as usize])
+            },
+            0x91 => Instruction::IntToByte,
+            0x92 => Instruction::IntToChar,
+            0x93 => Instruction::IntToShort,
+            0x94 => Instruction::Lcmp,
+            0x95 => Instruction::Fcmpl,
+            0x96 => Instruction::Fcmpg,
+            0x97 => Instruction::Dcmpl,
+            0x98 => Instruction::Dcmpg,
+            0x99..=0x9e => Instruction::If(CONDITIONS[(opcode - 0x99) as usize], self.branch()?),
+            0x9f..=0xa4 => Instruction::IfIcmp(CONDITIONS[(opcode - 0x9f) as usize], self.branch()?),
+            0xa5 => Instruction::IfAcmpEq(self.branch()?),
+            0xa6 => Instruction::IfAcmpNe(self.branch()?),
+            0xa7 => Instruction::Goto(self.branch()?),
+            0xa8 => Instruction::Jsr(self.branch()?),
+            0xa9 => Instruction::Ret(self.index(false)?),
+            0xaa => {
+                self.skip_padding()?;
+                let default = self.branch_wide()?;
+                let low = self.i32()?;
+                let high = self.i32()?;
+                if high < low{
+                    return Err(BytecodeError::InvalidOperand(self.start));
+                }
+                let targets = (low..=high).map(|_| self.branch_wide()).collect::<Result<_, _>>()?;
+                Instruction::TableSwitch { default, low, targets }
+            },
+            0xab => {
+                self.skip_padding()?;
+                let default = self.branch_wide()?;
+                let count = self.i32()?;
+                if count < 0{
+                    return Err(BytecodeError::InvalidOperand(self.start));
+                }
+                let pairs = (0..count).map(|_| Ok((self.i32()?, self.branch_wide()?))).collect::<Result<_, _>>()?;
+                Instruction::LookupSwitch { default, pairs }
+            },
+            0xac..=0xb0 => Instruction::Return(Some(KINDS[(opcode - 0xac) as usize])),
+            0xb1 => Instruction::Return(None),
+            0xb2 => Instruction::GetStatic(self.u16()?),
+            0xb3 => Instruction::PutStatic(self.u16()?),
+            0xb4 => Instruction::GetField(self.u16()?),
+            0xb5 => Instruction::PutField(self.u16()?),
+            0xb6 => Instruction::InvokeVirtual(self.u16()?),
+            0xb7 => Instruction::InvokeSpecial(self.u16()?),
+            0xb8 => Instruction::InvokeStatic(self.u16()?),
+            0xb9 => {
+                let index = self.u16()?;
+                let count = self.u8()?;
+                if count == 0 || self.u8()? != 0{
+                    return Err(BytecodeError::InvalidOperand(self.start));
+                }
+                Instruction::InvokeInterface { index, count }
+            },
+            0xba => {
+                let index = self.u16()?;
+                if self.u16()? != 0{
+                    return Err(BytecodeError::InvalidOperand(self.start));
+                }
+                Instruction::InvokeDynamic(index)
+            },
+            0xbb => Instruction::New(self.u16()?),
+            0xbc => Instruction::NewArray(match self.u8()? {
+                4 => PrimitiveArray::Boolean,
+                5 => PrimitiveArray::Char,
+                6 => PrimitiveArray::Float,
+                7 => PrimitiveArray::Double,
+                8 => PrimitiveArray::Byte,
+                9 => PrimitiveArray::Short,
+                10 => PrimitiveArray::Int,
+                11 => PrimitiveArray::Long,
+                _ => return Err(BytecodeError::InvalidOperand(self.start)),
+            }),
+            0xbd => Instruction::AnewArray(self.u16()?),
+            0xbe => Instruction::ArrayLength,
+            0xbf => Instruction::Athrow,
+            0xc0 => Instruction::CheckCast(self.u16()?),
+            0xc1 => Instruction::InstanceOf(self.u16()?),
+            0xc2 => Instruction::MonitorEnter,
+            0xc3 => Instruction::MonitorExit,
+            0xc4 => {
+                let opcode = self.u8()?;
+                match opcode {
+                    0x15..=0x19 => Instruction::Load(KINDS[(opcode - 0x15) as usize], self.index(true)?),
+                    0x36..=0x3a => Instruction::Store(KINDS[(opcode - 0x36) as usize], self.index(true)?),
+                    0xa9 => Instruction::Ret(self.index(true)?),
+                    0x84 => Instruction::Iinc { index: self.index(true)?, value: self.u16()? as i16 },
+                    _ => return Err(BytecodeError::InvalidWide { offset: self.start, opcode }),
+                }
+            },
+            0xc5 => {
+                let index = self.u16()?;
+                let dimensions = self.u8()?;
+                if dimensions == 0{
+                    return Err(BytecodeError::InvalidOperand(self.start));
+                }
+                Instruction::MultiAnewArray { index, dimensions }
+            },
+            0xc6 => Instruction::IfNull(self.branch()?),
+            0xc7 => Instruction::IfNonNull(self.branch()?),
+            0xc8 => Instruction::Goto(self.branch_wide()?),
+            0xc9 => Instruction::Jsr(self.branch_wide()?),
+            _ => return Err(BytecodeError::InvalidOpcode { offset: self.start, opcode }),
+        })
+    }
+
+    ///
+    /// Switch operands start at an offset that is a multiple of four
+    fn skip_padding(&mut self) -> Result<(), BytecodeError>{
+        while !self.position.is_multiple_of(4){
+            self.u8()?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Instructions<'a>{
+    type Item = Result<(u32, Instruction), BytecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.position >= self.bytecode.len(){
+            return None;
+        }
+        self.start = self.position as u32;
+        let instruction = self.instruction().map(|x| (self.start, x));
+        self.failed = instruction.is_err();
+        Some(instruction)
+    }
+}
+
+const KINDS: [Kind; 5] = [Kind::Int, Kind::Long, Kind::Float, Kind::Double, Kind::Reference];
+
+const ARRAY_KINDS: [ArrayKind; 8] = [ArrayKind::Int, ArrayKind::Long, ArrayKind::Float, ArrayKind::Double, ArrayKind::Reference, ArrayKind::Byte, ArrayKind::Char, ArrayKind::Short];
+
+const CONDITIONS: [Condition; 6] = [Condition::Eq, Condition::Ne, Condition::Lt, Condition::Ge, Condition::Gt, Condition::Le];
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    ///
+    /// `nops` nops followed by a tableswitch for 1 and 2 and a return, which is also the default target
+    fn table_switch(nops: usize) -> (Vec<u8>, u32){
+        let mut code = vec![0x00; nops];
+        code.push(0xaa);
+        while !code.len().is_multiple_of(4){
+            code.push(0);
+        }
+        let end = (code.len() + 20) as i32;
+        for x in [end - nops as i32, 1, 2, 0, end - nops as i32]{
+            code.extend(x.to_be_bytes());
+        }
+        code.push(0xb1);
+        (code, end as u32)
+    }
+
+    #[test]
+    fn table_switch_padding(){
+        for nops in 0..4{
+            let (code, end) = table_switch(nops);
+            let instructions = decode(&code).unwrap();
+            assert_eq!(instructions.len(), nops + 2);
+            assert_eq!(instructions[nops], (nops as u32, Instruction::TableSwitch { default: end, low: 1, targets: vec![nops as u32, end] }));
+            assert_eq!(instructions[nops + 1], (end, Instruction::Return(None)));
+        }
+    }
+
+    #[test]
+    fn lookup_switch_padding(){
+        for nops in 0..4{
+            let mut code = vec![0x00; nops];
+            code.push(0xab);
+            while !code.len().is_multiple_of(4){
+                code.push(0);
+            }
+            let end = (code.len() + 24) as i32;
+            for x in [end - nops as i32, 2, -5, 0, 7, end - nops as i32]{
+                code.extend(x.to_be_bytes());
+            }
+            code.push(0xb1);
+
+            let instructions = decode(&code).unwrap();
+            assert_eq!(instructions[nops], (nops as u32, Instruction::LookupSwitch { default: end as u32, pairs: vec![(-5, nops as u32), (7, end as u32)] }));
+            assert_eq!(instructions[nops + 1], (end as u32, Instruction::Return(None)));
+        }
+    }
+
+    #[test]
+    fn invalid_switches(){
+        // high < low
+        let mut code = vec![0xaa, 0, 0, 0];
+        for x in [0i32, 2, 1]{
+            code.extend(x.to_be_bytes());
+        }
+        assert_eq!(decode(&code), Err(BytecodeError::InvalidOperand(0)));
+
+        // Negative pair count
+        let mut code = vec![0xab, 0, 0, 0];
+        for x in [0i32, -1]{
+            code.extend(x.to_be_bytes());
+        }
+        assert_eq!(decode(&code), Err(BytecodeError::InvalidOperand(0)));
+    }
+
+    #[test]
+    fn wide(){
+        let code = [
+            0xc4, 0x15, 0x01, 0x00,
+            0xc4, 0x39, 0x00, 0x02,
+            0xc4, 0x84, 0x01, 0x01, 0xff, 0xfe,
+            0xc4, 0xa9, 0x00, 0x03,
+            0x15, 0x04,
+            0x84, 0x05, 0xff,
+        ];
+        assert_eq!(decode(&code).unwrap(), vec![
+            (0, Instruction::Load(Kind::Int, 256)),
+            (4, Instruction::Store(Kind::Double, 2)),
+            (8, Instruction::Iinc { index: 257, value: -2 }),
+            (14, Instruction::Ret(3)),
+            (18, Instruction::Load(Kind::Int, 4)),
+            (20, Instruction::Iinc { index: 5, value: -1 }),
+        ]);
+        assert_eq!(decode(&[0x00, 0xc4, 0x60]), Err(BytecodeError::InvalidWide { offset: 1, opcode: 0x60 }));
+    }
+
+    #[test]
+    fn truncated(){
+        assert_eq!(decode(&[0x11, 0x00]), Err(BytecodeError::Truncated(0)));
+        assert_eq!(decode(&[0x00, 0xa7, 0x00]), Err(BytecodeError::Truncated(1)));
+        assert_eq!(decode(&[0x00, 0xc4]), Err(BytecodeError::Truncated(1)));
+        assert_eq!(decode(&[0x00, 0xc4, 0x84, 0x00, 0x01]), Err(BytecodeError::Truncated(1)));
+        assert_eq!(decode(&[0x00, 0xaa, 0x00]), Err(BytecodeError::Truncated(1)));
+
+        // Only one of the two targets is there
+        let mut code = vec![0x00, 0xaa, 0x00, 0x00];
+        for x in [-1i32, 0, 1, -1]{
+            code.extend(x.to_be_bytes());
+        }
+        assert_eq!(decode(&code), Err(BytecodeError::Truncated(1)));
+    }
+
+    #[test]
+    fn stops_after_error(){
+        let mut instructions = Instructions::new(&[0x00, 0xff, 0x00]);
+        assert_eq!(instructions.next(), Some(Ok((0, Instruction::Nop))));
+        assert_eq!(instructions.next(), Some(Err(BytecodeError::InvalidOpcode { offset: 1, opcode: 0xff })));
+        assert_eq!(instructions.next(), None);
+    }
+
+    #[test]
+    fn branch_targets(){
+        assert_eq!(decode(&[0x00, 0xa7, 0xff, 0xff]).unwrap()[1], (1, Instruction::Goto(0)));
+        assert_eq!(decode(&[0xa7, 0xff, 0xff]), Err(BytecodeError::InvalidBranchTarget { offset: 0, target: -1 }));
+        assert_eq!(decode(&[0xc8, 0x00, 0x00, 0x00, 0x05]), Err(BytecodeError::InvalidBranchTarget { offset: 0, target: 5 }));
+    }
+}
@@ -0,0 +1,451 @@
+use std::{cell::RefCell, collections::{BTreeSet, HashMap}, fmt::Display, fs::File, io::Read, path::{Path, PathBuf}, rc::Rc};
+
+use zip::{result::ZipError, ZipArchive};
+
+use crate::frontend::{class_parser, ClassFile, ClassFileError};
+
+///
+/// Directories and jars searched in order for classes by their internal name, like `java/lang/Object`.
+/// Classes are read and parsed when they are first loaded, and kept for later loads
+pub struct ClassPath{
+    entries: Vec<Entry>,
+    release: Option<u16>,
+    cache: RefCell<HashMap<String, Option<Rc<LoadedClass>>>>,
+}
+
+enum Entry{
+    Directory(PathBuf),
+    Jar{
+        path: PathBuf,
+        archive: RefCell<ZipArchive<File>>,
+
+        ///
+        /// Versions under `META-INF/versions` in descending order, empty unless the jar is multi-release
+        versions: Vec<u16>,
+    },
+}
+
+impl Default for ClassPath{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClassPath{
+    ///
+    /// Multi-release jars resolve to the classes of the highest version they have
+    pub fn new() -> Self{
+        Self{
+            entries: Vec::new(),
+            release: None,
+            cache: RefCell::new(HashMap::new())
+        }
+    }
+
+    ///
+    /// Multi-release jars resolve to the classes for the java release, like 17
+    pub fn with_release(release: u16) -> Self{
+        Self{
+            release: Some(release),
+            ..Self::new()
+        }
+    }
+
+    ///
+    /// Add a directory or jar depending on what the path is
+    pub fn add(&mut self, path: impl Into<PathBuf>) -> Result<(), ClassPathError>{
+        let path = path.into();
+        if path.is_dir(){
+            self.add_directory(path);
+            Ok(())
+        }
+        else{
+            self.add_jar(path)
+        }
+    }
+
+    pub fn add_directory(&mut self, path: impl Into<PathBuf>){
+        self.push(Entry::Directory(path.into()));
+    }
+
+    pub fn add_jar(&mut self, path: impl Into<PathBuf>) -> Result<(), ClassPathError>{
+        let path = path.into();
+        let file = File::open(&path).map_err(|error| ClassPathError::Io { path: path.clone(), error })?;
+        let mut archive = ZipArchive::new(file).map_err(|error| ClassPathError::Zip { path: path.clone(), error })?;
+
+        let versions = if is_multi_release(&mut archive).map_err(|error| ClassPathError::Zip { path: path.clone(), error })?{
+            let mut versions = archive.file_names()
+                .filter_map(|x| x.strip_prefix("META-INF/versions/")?.split('/').next()?.parse::<u16>().ok())
+                .filter(|x| self.release.is_none_or(|release| *x <= release))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            versions.reverse();
+            versions
+        }
+        else{
+            Vec::new()
+        };
+
+        self.push(Entry::Jar { path, archive: RefCell::new(archive), versions });
+        Ok(())
+    }
+
+    ///
+    /// Classes that weren't found before may be in the new entry, loaded classes stay as earlier entries take precedence
+    fn push(&mut self, entry: Entry){
+        self.entries.push(entry);
+        self.cache.borrow_mut().retain(|_, x| x.is_some());
+    }
+
+    ///
+    /// Load a class by its internal name, returns `None` if no entry has it
+    pub fn load(&self, name: &str) -> Result<Option<Rc<LoadedClass>>, ClassPathError>{
+        if let Some(class) = self.cache.borrow().get(name){
+            return Ok(class.clone());
+        }
+
+        let mut class = None;
+        for entry in &self.entries{
+            if let Some((source, bytes)) = entry.read(name)?{
+                class = Some(Rc::new(LoadedClass::new(source, bytes).map_err(|error| ClassPathError::Class { name: name.to_string(), error })?));
+                break;
+            }
+        }
+
+        self.cache.borrow_mut().insert(name.to_string(), class.clone());
+        Ok(class)
+    }
+
+    ///
+    /// Internal names of every class on the class path, without loading them
+    pub fn class_names(&self) -> Result<BTreeSet<String>, ClassPathError>{
+        let mut names = BTreeSet::new();
+        for entry in &self.entries{
+            match entry {
+                Entry::Directory(path) => list_directory(path, path, &mut names)?,
+                Entry::Jar { archive, versions, .. } => {
+                    for name in archive.borrow().file_names(){
+                        let name = match name.strip_prefix("META-INF/versions/") {
+                            Some(x) => match x.split_once('/') {
+                                Some((version, name)) if version.parse().is_ok_and(|x: u16| versions.contains(&x)) => name,
+                                _ => continue,
+                            },
+                            None => name,
+                        };
+                        if let Some(name) = name.strip_suffix(".class"){
+                            names.insert(name.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(names)
+    }
+}
+
+impl Entry{
+    ///
+    /// The bytes of a class and a description of where they were found
+    fn read(&self, name: &str) -> Result<Option<(String, Vec<u8>)>, ClassPathError>{
+        let file = format!("{}.class", name);
+        match self {
+            Entry::Directory(path) => {
+                let path = path.join(&file);
+                match std::fs::read(&path) {
+                    Ok(bytes) => Ok(Some((path.display().to_string(), bytes))),
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                    Err(error) => Err(ClassPathError::Io { path, error }),
+                }
+            },
+            Entry::Jar { path, archive, versions } => {
+                let mut archive = archive.borrow_mut();
+                let candidates = versions.iter().map(|x| format!("META-INF/versions/{}/{}", x, file)).chain(std::iter::once(file.clone()));
+                for candidate in candidates{
+                    let mut entry = match archive.by_name(&candidate) {
+                        Ok(entry) => entry,
+                        Err(ZipError::FileNotFound) => continue,
+                        Err(error) => return Err(ClassPathError::Zip { path: path.clone(), error }),
+                    };
+                    let mut bytes = Vec::with_capacity(entry.size() as usize);
+                    entry.read_to_end(&mut bytes).map_err(|error| ClassPathError::Io { path: path.clone(), error })?;
+                    return Ok(Some((format!("{}!{}", path.display(), candidate), bytes)));
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn is_multi_release(archive: &mut ZipArchive<File>) -> Result<bool, ZipError>{
+    let mut manifest = match archive.by_name("META-INF/MANIFEST.MF") {
+        Ok(x) => x,
+        Err(ZipError::FileNotFound) => return Ok(false),
+        Err(err) => return Err(err),
+    };
+    let mut text = String::new();
+    manifest.read_to_string(&mut text)?;
+    Ok(text.lines().any(|x| match x.split_once(':') {
+        Some((key, value)) => key.trim().eq_ignore_ascii_case("Multi-Release") && value.trim().eq_ignore_ascii_case("true"),
+        None => false,
+    }))
+}
+
+fn list_directory(root: &Path, path: &Path, names: &mut BTreeSet<String>) -> Result<(), ClassPathError>{
+    let io = |error| ClassPathError::Io { path: path.to_path_buf(), error };
+    for entry in std::fs::read_dir(path).map_err(io)?{
+        let path = entry.map_err(io)?.path();
+        if path.is_dir(){
+            list_directory(root, &path, names)?;
+        }
+        else if path.extension().is_some_and(|x| x == "class"){
+            let name = path.strip_prefix(root).unwrap().with_extension("");
+            names.insert(name.components().map(|x| x.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"));
+        }
+    }
+    Ok(())
+}
+
+self_cell::self_cell!(
+    struct ParsedClass{
+        owner: Box<[u8]>,
+
+        #[covariant]
+        dependent: ClassFile,
+    }
+);
+
+///
+/// A parsed class together with the bytes it borrows from
+pub struct LoadedClass{
+    parsed: ParsedClass,
+    source: String,
+}
+
+impl LoadedClass{
+    fn new(source: String, bytes: Vec<u8>) -> Result<Self, ClassFileError>{
+        let parsed = ParsedClass::try_new(bytes.into_boxed_slice(), |bytes| class_parser::class_file(bytes))?;
+        Ok(Self{ parsed, source })
+    }
+
+    pub fn class(&self) -> &ClassFile<'_>{
+        self.parsed.borrow_dependent()
+    }
+
+    pub fn bytes(&self) -> &[u8]{
+        self.parsed.borrow_owner()
+    }
+
+    ///
+    /// Path of the class file, or of the jar and the entry in it
+    pub fn source(&self) -> &str{
+        &self.source
+    }
+}
+
+#[derive(Debug)]
+pub enum ClassPathError{
+    Io{
+        path: PathBuf,
+        error: std::io::Error
+    },
+    Zip{
+        path: PathBuf,
+        error: ZipError
+    },
+    Class{
+        name: String,
+        error: ClassFileError
+    },
+}
+
+impl Display for ClassPathError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClassPathError::Io { path, error } => write!(f, "failed to read {}: {}", path.display(), error),
+            ClassPathError::Zip { path, error } => write!(f, "failed to read jar {}: {}", path.display(), error),
+            ClassPathError::Class { name, error } => write!(f, "invalid class {}: {}", name, error),
+        }
+    }
+}
+
+impl std::error::Error for ClassPathError{}
+
+#[cfg(test)]
+mod tests{
+    use std::io::{Cursor, Write};
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    ///
+    /// Directory removed again when the test ends
+    struct TempDir(PathBuf);
+
+    impl TempDir{
+        fn new(name: &str) -> Self{
+            let path = std::env::temp_dir().join(format!("java-corrosion-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn write(&self, name: &str, bytes: &[u8]) -> PathBuf{
+            let path = self.0.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, bytes).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir{
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    ///
+    /// Class file of an empty class with the internal name
+    fn class(name: &str) -> Vec<u8>{
+        let mut bytes = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52, 0, 3, 1];
+        bytes.extend((name.len() as u16).to_be_bytes());
+        bytes.extend(name.as_bytes());
+        bytes.extend([7, 0, 1, 0x00, 0x21, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes
+    }
+
+    fn jar(files: &[(&str, &[u8])]) -> Vec<u8>{
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, bytes) in files{
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(bytes).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    const MULTI_RELEASE: &[u8] = b"Manifest-Version: 1.0\r\nMulti-Release: true\r\n";
+
+    fn source(classpath: &ClassPath, name: &str) -> Option<String>{
+        classpath.load(name).unwrap().map(|x| x.source().to_string())
+    }
+
+    #[test]
+    fn entries_are_searched_in_order(){
+        let dir = TempDir::new("order");
+        let first = dir.write("first/A.class", &class("A"));
+        dir.write("second/A.class", &class("A"));
+        dir.write("second/B.class", &class("B"));
+        let jar = dir.write("lib.jar", &jar(&[("B.class", &class("B")), ("C.class", &class("C"))]));
+
+        let mut classpath = ClassPath::new();
+        classpath.add(dir.0.join("first")).unwrap();
+        classpath.add(dir.0.join("second")).unwrap();
+        classpath.add(&jar).unwrap();
+
+        let a = classpath.load("A").unwrap().unwrap();
+        assert_eq!(a.source(), first.display().to_string());
+        assert_eq!(a.class().this_class_name().unwrap(), "A");
+        assert_eq!(a.bytes(), class("A"));
+        assert_eq!(source(&classpath, "B"), Some(dir.0.join("second/B.class").display().to_string()));
+        assert_eq!(source(&classpath, "C"), Some(format!("{}!C.class", jar.display())));
+        assert_eq!(source(&classpath, "D"), None);
+    }
+
+    #[test]
+    fn loaded_classes_are_cached(){
+        let dir = TempDir::new("cache");
+        let path = dir.write("A.class", &class("A"));
+        let mut classpath = ClassPath::new();
+        classpath.add_directory(&dir.0);
+
+        let a = classpath.load("A").unwrap().unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(Rc::ptr_eq(&a, &classpath.load("A").unwrap().unwrap()));
+
+        // Adding an entry keeps the class, even if the new entry has one of the same name
+        let other = TempDir::new("cache-other");
+        other.write("A.class", &class("A"));
+        classpath.add_directory(&other.0);
+        assert!(Rc::ptr_eq(&a, &classpath.load("A").unwrap().unwrap()));
+    }
+
+    #[test]
+    fn adding_an_entry_forgets_misses(){
+        let dir = TempDir::new("misses");
+        let mut classpath = ClassPath::new();
+        classpath.add_directory(dir.0.join("classes"));
+        assert_eq!(source(&classpath, "B"), None);
+
+        // Written after the miss was cached
+        dir.write("classes/B.class", &class("B"));
+        assert_eq!(source(&classpath, "B"), None);
+
+        let jar = dir.write("lib.jar", &jar(&[]));
+        classpath.add_jar(jar).unwrap();
+        assert!(source(&classpath, "B").is_some());
+    }
+
+    #[test]
+    fn multi_release_jars_pick_a_version(){
+        let dir = TempDir::new("versions");
+        let files: [(&str, &[u8]); 4] = [
+            ("META-INF/MANIFEST.MF", MULTI_RELEASE),
+            ("C.class", &class("C")),
+            ("META-INF/versions/9/C.class", &class("C")),
+            ("META-INF/versions/11/C.class", &class("C")),
+        ];
+        let path = dir.write("lib.jar", &jar(&files));
+        let version = |classpath: &mut ClassPath| {
+            classpath.add_jar(&path).unwrap();
+            source(classpath, "C").unwrap().strip_prefix(&format!("{}!", path.display())).unwrap().to_string()
+        };
+
+        assert_eq!(version(&mut ClassPath::new()), "META-INF/versions/11/C.class");
+        assert_eq!(version(&mut ClassPath::with_release(17)), "META-INF/versions/11/C.class");
+        assert_eq!(version(&mut ClassPath::with_release(10)), "META-INF/versions/9/C.class");
+        assert_eq!(version(&mut ClassPath::with_release(8)), "C.class");
+
+        // Versions are ignored unless the manifest says the jar is multi-release
+        let path = dir.write("plain.jar", &jar(&files[1..]));
+        let mut classpath = ClassPath::new();
+        classpath.add_jar(&path).unwrap();
+        assert_eq!(source(&classpath, "C"), Some(format!("{}!C.class", path.display())));
+    }
+
+    #[test]
+    fn class_names(){
+        let dir = TempDir::new("names");
+        dir.write("classes/A.class", &class("A"));
+        dir.write("classes/pkg/B.class", &class("pkg/B"));
+        dir.write("classes/pkg/notes.txt", b"");
+        let path = dir.write("lib.jar", &jar(&[
+            ("META-INF/MANIFEST.MF", MULTI_RELEASE),
+            ("C.class", &class("C")),
+            ("META-INF/versions/11/D.class", &class("D")),
+            ("META-INF/versions/bad/E.class", &class("E")),
+        ]));
+
+        let names = |mut classpath: ClassPath| {
+            classpath.add(dir.0.join("classes")).unwrap();
+            classpath.add(&path).unwrap();
+            classpath.class_names().unwrap().into_iter().collect::<Vec<_>>()
+        };
+        assert_eq!(names(ClassPath::new()), ["A", "C", "D", "pkg/B"]);
+        assert_eq!(names(ClassPath::with_release(8)), ["A", "C", "pkg/B"]);
+    }
+
+    #[test]
+    fn errors(){
+        let dir = TempDir::new("errors");
+        dir.write("A.class", b"not a class");
+        let path = dir.write("broken.jar", b"PK\x03\x04");
+
+        let mut classpath = ClassPath::new();
+        assert!(matches!(classpath.add(&path), Err(ClassPathError::Zip { .. })));
+        assert!(matches!(classpath.add(dir.0.join("missing.jar")), Err(ClassPathError::Io { .. })));
+
+        classpath.add(&dir.0).unwrap();
+        assert!(matches!(classpath.load("A"), Err(ClassPathError::Class { ref name, error: ClassFileError::BadMagic(_) }) if name == "A"));
+    }
+}
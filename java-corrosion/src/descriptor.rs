@@ -0,0 +1,234 @@
+use std::{fmt::Display, str::FromStr};
+
+use corrosion_base::Type;
+
+peg::parser!(
+    pub grammar descriptor_parser() for str{
+
+        rule base_type() -> BaseType
+            = "B" {BaseType::Byte}
+            / "C" {BaseType::Char}
+            / "D" {BaseType::Double}
+            / "F" {BaseType::Float}
+            / "I" {BaseType::Int}
+            / "J" {BaseType::Long}
+            / "S" {BaseType::Short}
+            / "Z" {BaseType::Boolean}
+
+        rule class_name() -> String
+            = "L" name:$([^ ';' | '.' | '[']+) ";" {name.to_string()}
+
+        rule element() -> FieldType
+            = x:base_type() {FieldType::Base(x)}
+            / x:class_name() {FieldType::Object(x)}
+
+        rule field() -> FieldType
+            = dimensions:$("["+) element:element()
+            {?
+                let dimensions = u8::try_from(dimensions.len()).or(Err("at most 255 array dimensions"))?;
+                Ok(FieldType::Array { dimensions, element: Box::new(element) })
+            }
+            / element()
+
+        pub rule field_type() -> FieldType
+            = field()
+
+        pub rule method_descriptor() -> MethodDescriptor
+            = "(" params:field()* ")" ret:(x:field() {Some(x)} / "V" {None})
+            {
+                MethodDescriptor{
+                    params,
+                    ret
+                }
+            }
+    }
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BaseType{
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+}
+
+impl BaseType{
+    ///
+    /// Type of the value in corrosion, booleans are bytes and chars are unsigned 16 bit integers
+    pub fn to_type(self) -> Type{
+        match self {
+            BaseType::Byte | BaseType::Boolean => Type::I8,
+            BaseType::Char | BaseType::Short => Type::I16,
+            BaseType::Int => Type::I32,
+            BaseType::Long => Type::I64,
+            BaseType::Float => Type::F32,
+            BaseType::Double => Type::F64,
+        }
+    }
+}
+
+///
+/// Type of a field, parameter or return value
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FieldType{
+    Base(BaseType),
+
+    ///
+    /// Internal name of the class, like `java/lang/String`
+    Object(String),
+
+    ///
+    /// The element is never an array itself
+    Array{
+        dimensions: u8,
+        element: Box<FieldType>
+    },
+}
+
+impl FieldType{
+    ///
+    /// Objects and arrays are managed references
+    pub fn to_type(&self) -> Type{
+        match self {
+            FieldType::Base(x) => x.to_type(),
+            FieldType::Object(_) | FieldType::Array { .. } => Type::Ref,
+        }
+    }
+
+    ///
+    /// Longs and doubles take up two local variables and operand stack entries
+    pub fn is_wide(&self) -> bool{
+        matches!(self, FieldType::Base(BaseType::Long | BaseType::Double))
+    }
+}
+
+impl FromStr for FieldType{
+    type Err = peg::error::ParseError<peg::str::LineCol>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        descriptor_parser::field_type(s)
+    }
+}
+
+impl Display for FieldType{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldType::Base(x) => write!(f, "{}", match x {
+                BaseType::Byte => 'B',
+                BaseType::Char => 'C',
+                BaseType::Double => 'D',
+                BaseType::Float => 'F',
+                BaseType::Int => 'I',
+                BaseType::Long => 'J',
+                BaseType::Short => 'S',
+                BaseType::Boolean => 'Z',
+            }),
+            FieldType::Object(name) => write!(f, "L{};", name),
+            FieldType::Array { dimensions, element } => write!(f, "{}{}", "[".repeat(*dimensions as usize), element),
+        }
+    }
+}
+
+///
+/// Parameters and return type of a method, `ret` is `None` for void methods
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MethodDescriptor{
+    pub params: Vec<FieldType>,
+    pub ret: Option<FieldType>,
+}
+
+impl MethodDescriptor{
+    ///
+    /// Inputs of the corresponding function, not including the receiver of instance methods
+    pub fn inputs(&self) -> Vec<Type>{
+        self.params.iter().map(FieldType::to_type).collect()
+    }
+
+    pub fn outputs(&self) -> Vec<Type>{
+        self.ret.iter().map(FieldType::to_type).collect()
+    }
+}
+
+impl FromStr for MethodDescriptor{
+    type Err = peg::error::ParseError<peg::str::LineCol>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        descriptor_parser::method_descriptor(s)
+    }
+}
+
+impl Display for MethodDescriptor{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(")?;
+        for param in &self.params{
+            write!(f, "{}", param)?;
+        }
+        match &self.ret {
+            Some(x) => write!(f, "){}", x),
+            None => write!(f, ")V"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn arrays(){
+        assert_eq!("[[[I".parse::<FieldType>().unwrap(), FieldType::Array { dimensions: 3, element: Box::new(FieldType::Base(BaseType::Int)) });
+        assert_eq!("[Ljava/lang/String;".parse::<FieldType>().unwrap(), FieldType::Array { dimensions: 1, element: Box::new(FieldType::Object("java/lang/String".to_string())) });
+        assert_eq!("[[J".parse::<FieldType>().unwrap().to_type(), Type::Ref);
+        assert!(!"[J".parse::<FieldType>().unwrap().is_wide());
+        assert!("[".parse::<FieldType>().is_err());
+    }
+
+    #[test]
+    fn dimensions(){
+        let max = format!("{}D", "[".repeat(255));
+        assert_eq!(max.parse::<FieldType>().unwrap(), FieldType::Array { dimensions: 255, element: Box::new(FieldType::Base(BaseType::Double)) });
+        assert!(format!("{}D", "[".repeat(256)).parse::<FieldType>().is_err());
+        assert!(format!("({}D)V", "[".repeat(256)).parse::<MethodDescriptor>().is_err());
+    }
+
+    #[test]
+    fn void_only_as_return(){
+        let descriptor = "(IJ)V".parse::<MethodDescriptor>().unwrap();
+        assert_eq!(descriptor.params, vec![FieldType::Base(BaseType::Int), FieldType::Base(BaseType::Long)]);
+        assert_eq!(descriptor.ret, None);
+        assert!(descriptor.outputs().is_empty());
+
+        assert!("V".parse::<FieldType>().is_err());
+        assert!("[V".parse::<FieldType>().is_err());
+        assert!("(V)V".parse::<MethodDescriptor>().is_err());
+        assert!("()[V".parse::<MethodDescriptor>().is_err());
+    }
+
+    #[test]
+    fn invalid_class_names(){
+        assert!("L;".parse::<FieldType>().is_err());
+        assert!("Ljava/lang/String".parse::<FieldType>().is_err());
+        assert!("Ljava.lang.String;".parse::<FieldType>().is_err());
+    }
+
+    #[test]
+    fn display_round_trip(){
+        for x in ["B", "C", "D", "F", "I", "J", "S", "Z", "Ljava/lang/Object;", "[[Z", "[Ljava/util/List;"]{
+            assert_eq!(x.parse::<FieldType>().unwrap().to_string(), x);
+        }
+        for x in ["()V", "(IDLjava/lang/Thread;)Ljava/lang/Object;", "([[I[Ljava/lang/String;J)[B"]{
+            assert_eq!(x.parse::<MethodDescriptor>().unwrap().to_string(), x);
+        }
+    }
+
+    #[test]
+    fn types(){
+        let descriptor = "(ZCSBIJFD[I)J".parse::<MethodDescriptor>().unwrap();
+        assert_eq!(descriptor.inputs(), vec![Type::I8, Type::I16, Type::I16, Type::I8, Type::I32, Type::I64, Type::F32, Type::F64, Type::Ref]);
+        assert_eq!(descriptor.outputs(), vec![Type::I64]);
+    }
+}
@@ -1,7 +1,9 @@
-use std::{cell::{RefCell, RefMut}, fmt::Display};
+use std::{borrow::Cow, cell::{RefCell, RefMut}, fmt::Display};
+
+use crate::mutf8::{self, Mutf8Error};
 
 peg::parser!(
-    pub grammar class_parser<'a>() for [u8]{
+    grammar class_grammar<'a>() for [u8]{
 
         rule u8() -> u8
             = x:[_] {x}
@@ -71,8 +73,12 @@ peg::parser!(
         rule interface_ref_info() -> ConstantPoolEntry<'input>
             = class:u16() name_and_type:u16() {ConstantPoolEntry::InterfaceMethodRef { class, name_and_type }}
         
+        rule reference_kind() -> u8
+            = quiet!{x:[1..=9] {x}}
+            / expected!("reference kind")
+
         rule method_handle_info() -> ConstantPoolEntry<'input>
-            = kind:u8() index:u16() {ConstantPoolEntry::MethodHandle{reference_kind: kind, reference_index: index}}
+            = kind:reference_kind() index:u16() {ConstantPoolEntry::MethodHandle{reference_kind: kind, reference_index: index}}
         
         rule method_type_info() -> ConstantPoolEntry<'input>
             = index:u16() {ConstantPoolEntry::MethodType { descriptor_index: index }}
@@ -93,8 +99,8 @@ peg::parser!(
             = [1]  value:utf_info()            {value}
             / [3]  value:integer_info()        {value}
             / [4]  value:float_info()          {value}
-            / [5]  value:long_info()           {**count -= 1; value}
-            / [6]  value:double_info()         {**count -= 1; value}
+            / [5]  value:long_info()           {**count = count.saturating_sub(1); value}
+            / [6]  value:double_info()         {**count = count.saturating_sub(1); value}
             / [7]  value:class_info()          {value}
             / [8]  value:string_info()         {value}
             / [9]  value:field_ref_info()      {value}
@@ -107,10 +113,21 @@ peg::parser!(
             / [18] value:invoke_dynamic_info() {value}
             / [19] value:module_info()         {value}
             / [20] value:package_info()        {value}
-            / x:u8()                           {panic!("No item with tag {}",x)}
 
+        // Longs and doubles take up two indices, the second of which is marked unusable
         rule constant_pool(count: &mut RefMut<u16>) -> Vec<ConstantPoolEntry<'input>>
-            = entries:constant_pool_entry(count)*<{(**count-1) as usize}> {entries}
+            = entries:constant_pool_entry(count)*<{count.saturating_sub(1) as usize}>
+            {
+                let mut pool = Vec::with_capacity(entries.len());
+                for entry in entries{
+                    let wide = matches!(entry, ConstantPoolEntry::LongInfo(_) | ConstantPoolEntry::DoubleInfo(_));
+                    pool.push(entry);
+                    if wide{
+                        pool.push(ConstantPoolEntry::Unusable);
+                    }
+                }
+                pool
+            }
 
         rule attribute() -> Attribute<'input>
         = name_index:u16()
@@ -123,6 +140,200 @@ peg::parser!(
             }
           }
         
+        rule exception_table_entry() -> ExceptionTableEntry
+        = start_pc:u16()
+          end_pc:u16()
+          handler_pc:u16()
+          catch_type:u16()
+          {
+            ExceptionTableEntry{
+                start_pc,
+                end_pc,
+                handler_pc,
+                catch_type
+            }
+          }
+
+        pub rule code() -> Code<'input>
+        = max_stack:u16()
+          max_locals:u16()
+          length:u32()
+          bytecode:slice(length as usize)
+          exception_table:list(<exception_table_entry()>)
+          attributes:list(<attribute()>)
+          {
+            Code{
+                max_stack,
+                max_locals,
+                bytecode,
+                exception_table,
+                attributes
+            }
+          }
+
+        pub rule constant_index() -> u16
+            = u16()
+
+        pub rule constant_indices() -> Vec<u16>
+            = list(<u16()>)
+
+        rule line_number() -> LineNumber
+            = start_pc:u16() line_number:u16() {LineNumber{ start_pc, line_number }}
+
+        pub rule line_number_table() -> Vec<LineNumber>
+            = list(<line_number()>)
+
+        rule local_variable() -> LocalVariable
+        = start_pc:u16()
+          length:u16()
+          name_index:u16()
+          descriptor_index:u16()
+          index:u16()
+          {
+            LocalVariable{
+                start_pc,
+                length,
+                name_index,
+                descriptor_index,
+                index
+            }
+          }
+
+        pub rule local_variable_table() -> Vec<LocalVariable>
+            = list(<local_variable()>)
+
+        rule verification_type() -> VerificationType
+            = [0] {VerificationType::Top}
+            / [1] {VerificationType::Integer}
+            / [2] {VerificationType::Float}
+            / [3] {VerificationType::Double}
+            / [4] {VerificationType::Long}
+            / [5] {VerificationType::Null}
+            / [6] {VerificationType::UninitializedThis}
+            / [7] class:u16() {VerificationType::Object(class)}
+            / [8] offset:u16() {VerificationType::Uninitialized(offset)}
+
+        rule stack_map_frame() -> StackMapFrame
+            = tag:[0..=63] {StackMapFrame::Same{ offset_delta: tag as u16 }}
+            / tag:[64..=127] stack:verification_type() {StackMapFrame::SameLocals1StackItem { offset_delta: (tag - 64) as u16, stack }}
+            / [247] offset_delta:u16() stack:verification_type() {StackMapFrame::SameLocals1StackItem { offset_delta, stack }}
+            / tag:[248..=250] offset_delta:u16() {StackMapFrame::Chop { offset_delta, count: 251 - tag }}
+            / [251] offset_delta:u16() {StackMapFrame::Same { offset_delta }}
+            / tag:[252..=254] offset_delta:u16() locals:verification_type()*<{(tag - 251) as usize}> {StackMapFrame::Append { offset_delta, locals }}
+            / [255] offset_delta:u16() locals:list(<verification_type()>) stack:list(<verification_type()>) {StackMapFrame::Full { offset_delta, locals, stack }}
+
+        pub rule stack_map_table() -> Vec<StackMapFrame>
+            = list(<stack_map_frame()>)
+
+        rule inner_class() -> InnerClass
+        = inner_class:u16()
+          outer_class:u16()
+          inner_name:u16()
+          access_flags:u16()
+          {
+            InnerClass{
+                inner_class,
+                outer_class,
+                inner_name,
+                access_flags
+            }
+          }
+
+        pub rule inner_classes() -> Vec<InnerClass>
+            = list(<inner_class()>)
+
+        pub rule enclosing_method() -> (u16, u16)
+            = class:u16() method:u16() {(class, method)}
+
+        rule bootstrap_method() -> BootstrapMethod
+            = method_ref:u16() arguments:list(<u16()>) {BootstrapMethod{ method_ref, arguments }}
+
+        pub rule bootstrap_methods() -> Vec<BootstrapMethod>
+            = list(<bootstrap_method()>)
+
+        rule record_component() -> RecordComponent<'input>
+        = name_index:u16()
+          descriptor_index:u16()
+          attributes:list(<attribute()>)
+          {
+            RecordComponent{
+                name_index,
+                descriptor_index,
+                attributes
+            }
+          }
+
+        pub rule record() -> Vec<RecordComponent<'input>>
+            = list(<record_component()>)
+
+        rule element_value() -> ElementValue
+            = [b'B'] index:u16() {ElementValue::Byte(index)}
+            / [b'C'] index:u16() {ElementValue::Char(index)}
+            / [b'D'] index:u16() {ElementValue::Double(index)}
+            / [b'F'] index:u16() {ElementValue::Float(index)}
+            / [b'I'] index:u16() {ElementValue::Int(index)}
+            / [b'J'] index:u16() {ElementValue::Long(index)}
+            / [b'S'] index:u16() {ElementValue::Short(index)}
+            / [b'Z'] index:u16() {ElementValue::Boolean(index)}
+            / [b's'] index:u16() {ElementValue::String(index)}
+            / [b'e'] type_name:u16() const_name:u16() {ElementValue::Enum { type_name, const_name }}
+            / [b'c'] index:u16() {ElementValue::Class(index)}
+            / [b'@'] value:annotation() {ElementValue::Annotation(value)}
+            / [b'['] values:list(<element_value()>) {ElementValue::Array(values)}
+
+        rule annotation() -> Annotation
+            = type_index:u16() elements:list(<name:u16() value:element_value() {(name, value)}>)
+            {
+                Annotation{
+                    type_index,
+                    elements
+                }
+            }
+
+        pub rule annotations() -> Vec<Annotation>
+            = list(<annotation()>)
+
+        pub rule parameter_annotations() -> Vec<Vec<Annotation>>
+            = count:u8() parameters:annotations()*<{count as usize}> {parameters}
+
+        pub rule annotation_default() -> ElementValue
+            = element_value()
+
+        rule local_variable_target() -> LocalVariableTarget
+            = start_pc:u16() length:u16() index:u16() {LocalVariableTarget{ start_pc, length, index }}
+
+        rule type_annotation_target() -> (u8, TypeAnnotationTarget)
+            = t:[0x00 | 0x01] index:u8() {(t, TypeAnnotationTarget::TypeParameter(index))}
+            / t:[0x10] index:u16() {(t, TypeAnnotationTarget::Supertype(index))}
+            / t:[0x11 | 0x12] type_parameter:u8() bound:u8() {(t, TypeAnnotationTarget::TypeParameterBound { type_parameter, bound })}
+            / t:[0x13..=0x15] {(t, TypeAnnotationTarget::Empty)}
+            / t:[0x16] index:u8() {(t, TypeAnnotationTarget::FormalParameter(index))}
+            / t:[0x17] index:u16() {(t, TypeAnnotationTarget::Throws(index))}
+            / t:[0x40 | 0x41] table:list(<local_variable_target()>) {(t, TypeAnnotationTarget::LocalVariable(table))}
+            / t:[0x42] index:u16() {(t, TypeAnnotationTarget::Catch(index))}
+            / t:[0x43..=0x46] offset:u16() {(t, TypeAnnotationTarget::Offset(offset))}
+            / t:[0x47..=0x4b] offset:u16() type_argument:u8() {(t, TypeAnnotationTarget::TypeArgument { offset, type_argument })}
+
+        rule type_path_entry() -> TypePathEntry
+            = kind:u8() type_argument:u8() {TypePathEntry{ kind, type_argument }}
+
+        rule type_annotation() -> TypeAnnotation
+        = target:type_annotation_target()
+          path_length:u8()
+          path:type_path_entry()*<{path_length as usize}>
+          annotation:annotation()
+          {
+            TypeAnnotation{
+                target_type: target.0,
+                target: target.1,
+                path,
+                annotation
+            }
+          }
+
+        pub rule type_annotations() -> Vec<TypeAnnotation>
+            = list(<type_annotation()>)
+
         rule field() -> Field<'input>
         = access_flags:u16()
           name_index:u16()
@@ -153,7 +364,6 @@ peg::parser!(
 
 
 
-        #[no_eof]
         pub rule class_file() -> ClassFile<'input>
             = magic() 
               version:version()
@@ -182,6 +392,128 @@ peg::parser!(
     }
 );
 
+pub mod class_parser{
+    use super::{class_grammar, ClassFile, ClassFileError};
+
+    const MAGIC: [u8; 4] = [0xca, 0xfe, 0xba, 0xbe];
+
+    ///
+    /// Parse a class file, which has to span the entire input
+    pub fn class_file(bytes: &[u8]) -> Result<ClassFile<'_>, ClassFileError>{
+        match bytes.get(..4) {
+            None => return Err(ClassFileError::Truncated { offset: bytes.len(), constant: None }),
+            Some(x) if x != MAGIC => return Err(ClassFileError::BadMagic([x[0], x[1], x[2], x[3]])),
+            Some(_) => {}
+        }
+
+        class_grammar::class_file(bytes).map_err(|err| {
+            let offset = err.location;
+            let entry = constant_at(bytes, offset);
+            let constant = entry.map(|x| x.0);
+            match (bytes.get(offset), entry) {
+                (None, _) => ClassFileError::Truncated { offset, constant },
+                (Some(_), _) if err.expected.tokens().any(|x| x == "EOF") => ClassFileError::TrailingBytes { offset, count: bytes.len() - offset },
+                (Some(tag), Some((index, start))) if start == offset && entry_size(*tag).is_none() => ClassFileError::UnknownConstantTag { offset, index, tag: *tag },
+                (Some(found), _) => ClassFileError::Malformed { offset, constant, expected: err.expected.to_string(), found: *found },
+            }
+        })
+    }
+
+    ///
+    /// Size of a constant pool entry following its tag, utf8 entries are followed by their length.
+    /// Returns `None` for unknown tags
+    fn entry_size(tag: u8) -> Option<usize>{
+        match tag {
+            1 => Some(2),
+            7 | 8 | 16 | 19 | 20 => Some(2),
+            15 => Some(3),
+            3 | 4 | 9 | 10 | 11 | 12 | 17 | 18 => Some(4),
+            5 | 6 => Some(8),
+            _ => None
+        }
+    }
+
+    ///
+    /// Index and offset of the constant pool entry containing an offset, used to locate errors.
+    /// Walking the pool stops at the first unknown tag, which is where parsing failed
+    fn constant_at(bytes: &[u8], offset: usize) -> Option<(u16, usize)>{
+        let count = u16::from_be_bytes([*bytes.get(8)?, *bytes.get(9)?]);
+        let mut position = 10;
+        let mut index = 1;
+        while index < count && position <= offset{
+            let tag = *bytes.get(position)?;
+            let Some(size) = entry_size(tag) else {
+                return (position == offset).then_some((index, position));
+            };
+            let mut end = position + 1 + size;
+            if tag == 1{
+                end += u16::from_be_bytes([*bytes.get(position + 1)?, *bytes.get(position + 2)?]) as usize;
+            }
+            if offset < end{
+                return Some((index, position));
+            }
+            position = end;
+            index += if matches!(tag, 5 | 6) { 2 } else { 1 };
+        }
+        None
+    }
+}
+
+///
+/// Offsets are into the class file, and `constant` is the constant pool entry being parsed if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassFileError{
+    BadMagic([u8; 4]),
+    Truncated{
+        offset: usize,
+        constant: Option<u16>
+    },
+    UnknownConstantTag{
+        offset: usize,
+        index: u16,
+        tag: u8
+    },
+
+    ///
+    /// Bytes after the end of the class
+    TrailingBytes{
+        offset: usize,
+        count: usize
+    },
+    Malformed{
+        offset: usize,
+        constant: Option<u16>,
+        expected: String,
+        found: u8
+    },
+}
+
+impl Display for ClassFileError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClassFileError::BadMagic(magic) => write!(f, "not a class file, found magic {:x?}", magic),
+            ClassFileError::Truncated { offset, constant } => {
+                write!(f, "unexpected end of class at {}", offset)?;
+                if let Some(x) = constant{
+                    write!(f, " in constant pool entry {}", x)?;
+                }
+                Ok(())
+            },
+            ClassFileError::UnknownConstantTag { offset, index, tag } => write!(f, "unknown tag {} for constant pool entry {} at {}", tag, index, offset),
+            ClassFileError::TrailingBytes { offset, count } => write!(f, "{} trailing bytes after the class at {}", count, offset),
+            ClassFileError::Malformed { offset, constant, expected, found } => {
+                write!(f, "malformed class at {}", offset)?;
+                if let Some(x) = constant{
+                    write!(f, " in constant pool entry {}", x)?;
+                }
+                write!(f, ", expected {}, found {:#04x}", expected, found)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ClassFileError{}
+
 #[derive(Debug)]
 pub enum ConstantPoolEntry<'a>{
     Utf8Info(&'a [u8]),
@@ -227,15 +559,442 @@ pub enum ConstantPoolEntry<'a>{
     },
     PackageInfo{
         name_index: u16
+    },
+
+    ///
+    /// The index following a long or double
+    Unusable
+}
+
+impl<'a> ConstantPoolEntry<'a>{
+    ///
+    /// Name of the entry's kind, as used in errors
+    pub fn kind(&self) -> &'static str{
+        match self {
+            ConstantPoolEntry::Utf8Info(_) => "utf8",
+            ConstantPoolEntry::ClassInfo(_) => "class",
+            ConstantPoolEntry::FieldRef{..} => "field reference",
+            ConstantPoolEntry::MethodRef{..} => "method reference",
+            ConstantPoolEntry::InterfaceMethodRef{..} => "interface method reference",
+            ConstantPoolEntry::StringInfo(_) => "string",
+            ConstantPoolEntry::IntegerInfo(_) => "integer",
+            ConstantPoolEntry::FloatInfo(_) => "float",
+            ConstantPoolEntry::LongInfo(_) => "long",
+            ConstantPoolEntry::DoubleInfo(_) => "double",
+            ConstantPoolEntry::NameAndTypeInfo{..} => "name and type",
+            ConstantPoolEntry::MethodHandle{..} => "method handle",
+            ConstantPoolEntry::MethodType{..} => "method type",
+            ConstantPoolEntry::DynamicInfo{..} => "dynamic",
+            ConstantPoolEntry::InvokeDynamicInfo{..} => "invoke dynamic",
+            ConstantPoolEntry::ModuleInfo{..} => "module",
+            ConstantPoolEntry::PackageInfo{..} => "package",
+            ConstantPoolEntry::Unusable => "unusable",
+        }
+    }
+}
+
+///
+/// A constant pool index that doesn't resolve to the expected kind of entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstantPoolError{
+    OutOfRange(u16),
+    WrongKind{
+        index: u16,
+        expected: &'static str,
+        found: &'static str
+    },
+    InvalidUtf8(u16, Mutf8Error),
+}
+
+impl Display for ConstantPoolError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstantPoolError::OutOfRange(index) => write!(f, "constant pool index {} is out of range", index),
+            ConstantPoolError::WrongKind { index, expected, found } => write!(f, "expected constant pool entry {} to be {}, found {}", index, expected, found),
+            ConstantPoolError::InvalidUtf8(index, err) => write!(f, "constant pool entry {} is not valid modified utf8: {}", index, err),
+        }
     }
 }
 
+impl std::error::Error for ConstantPoolError{}
+
+///
+/// Class, name and descriptor of a field or method
+pub type MemberRef<'a> = (Cow<'a, str>, Cow<'a, str>, Cow<'a, str>);
+
 #[derive(Debug)]
 pub struct Attribute<'a>{
     pub name_index: u16,
     pub bytes: &'a [u8]
 }
 
+impl<'a> Attribute<'a>{
+    pub fn name(&self, class: &ClassFile<'a>) -> Result<Cow<'a, str>, ConstantPoolError>{
+        class.utf8(self.name_index)
+    }
+
+    ///
+    /// Decode the attribute according to its name
+    pub fn decode(&self, class: &ClassFile<'a>) -> Result<AttributeInfo<'a>, AttributeError>{
+        let name = self.name(class)?;
+        let bytes = self.bytes;
+        Ok(match &*name {
+            "Code" => AttributeInfo::Code(parse("Code", bytes, class_grammar::code)?),
+            "SourceFile" => AttributeInfo::SourceFile(parse("SourceFile", bytes, class_grammar::constant_index)?),
+            "LineNumberTable" => AttributeInfo::LineNumberTable(parse("LineNumberTable", bytes, class_grammar::line_number_table)?),
+            "LocalVariableTable" => AttributeInfo::LocalVariableTable(parse("LocalVariableTable", bytes, class_grammar::local_variable_table)?),
+            "LocalVariableTypeTable" => AttributeInfo::LocalVariableTypeTable(parse("LocalVariableTypeTable", bytes, class_grammar::local_variable_table)?),
+            "StackMapTable" => AttributeInfo::StackMapTable(parse("StackMapTable", bytes, class_grammar::stack_map_table)?),
+            "Exceptions" => AttributeInfo::Exceptions(parse("Exceptions", bytes, class_grammar::constant_indices)?),
+            "InnerClasses" => AttributeInfo::InnerClasses(parse("InnerClasses", bytes, class_grammar::inner_classes)?),
+            "EnclosingMethod" => {
+                let (class, method) = parse("EnclosingMethod", bytes, class_grammar::enclosing_method)?;
+                AttributeInfo::EnclosingMethod { class, method }
+            },
+            "Signature" => AttributeInfo::Signature(parse("Signature", bytes, class_grammar::constant_index)?),
+            "BootstrapMethods" => AttributeInfo::BootstrapMethods(parse("BootstrapMethods", bytes, class_grammar::bootstrap_methods)?),
+            "NestHost" => AttributeInfo::NestHost(parse("NestHost", bytes, class_grammar::constant_index)?),
+            "NestMembers" => AttributeInfo::NestMembers(parse("NestMembers", bytes, class_grammar::constant_indices)?),
+            "Record" => AttributeInfo::Record(parse("Record", bytes, class_grammar::record)?),
+            "PermittedSubclasses" => AttributeInfo::PermittedSubclasses(parse("PermittedSubclasses", bytes, class_grammar::constant_indices)?),
+            "ConstantValue" => AttributeInfo::ConstantValue(parse("ConstantValue", bytes, class_grammar::constant_index)?),
+            "RuntimeVisibleAnnotations" => AttributeInfo::RuntimeVisibleAnnotations(parse("RuntimeVisibleAnnotations", bytes, class_grammar::annotations)?),
+            "RuntimeInvisibleAnnotations" => AttributeInfo::RuntimeInvisibleAnnotations(parse("RuntimeInvisibleAnnotations", bytes, class_grammar::annotations)?),
+            "RuntimeVisibleParameterAnnotations" => AttributeInfo::RuntimeVisibleParameterAnnotations(parse("RuntimeVisibleParameterAnnotations", bytes, class_grammar::parameter_annotations)?),
+            "RuntimeInvisibleParameterAnnotations" => AttributeInfo::RuntimeInvisibleParameterAnnotations(parse("RuntimeInvisibleParameterAnnotations", bytes, class_grammar::parameter_annotations)?),
+            "RuntimeVisibleTypeAnnotations" => AttributeInfo::RuntimeVisibleTypeAnnotations(parse("RuntimeVisibleTypeAnnotations", bytes, class_grammar::type_annotations)?),
+            "RuntimeInvisibleTypeAnnotations" => AttributeInfo::RuntimeInvisibleTypeAnnotations(parse("RuntimeInvisibleTypeAnnotations", bytes, class_grammar::type_annotations)?),
+            "AnnotationDefault" => AttributeInfo::AnnotationDefault(parse("AnnotationDefault", bytes, class_grammar::annotation_default)?),
+            _ => AttributeInfo::Unknown { name, bytes },
+        })
+    }
+}
+
+fn parse<'a, T>(attribute: &'static str, bytes: &'a [u8], rule: impl FnOnce(&'a [u8]) -> Result<T, peg::error::ParseError<usize>>) -> Result<T, AttributeError>{
+    rule(bytes).map_err(|error| AttributeError::Malformed { attribute, error })
+}
+
+///
+/// An attribute that couldn't be decoded
+#[derive(Debug, Clone)]
+pub enum AttributeError{
+    ConstantPool(ConstantPoolError),
+    Malformed{
+        attribute: &'static str,
+        error: peg::error::ParseError<usize>
+    },
+}
+
+impl Display for AttributeError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeError::ConstantPool(err) => write!(f, "{}", err),
+            AttributeError::Malformed { attribute, error } => write!(f, "malformed {} attribute: {}", attribute, error),
+        }
+    }
+}
+
+impl std::error::Error for AttributeError{}
+
+impl From<ConstantPoolError> for AttributeError{
+    fn from(value: ConstantPoolError) -> Self {
+        AttributeError::ConstantPool(value)
+    }
+}
+
+///
+/// Find the attribute with a name and decode it, attributes with names that can't be resolved are errors
+fn find_attribute<'a, T>(class: &ClassFile<'a>, attributes: &[Attribute<'a>], name: &'static str, rule: impl FnOnce(&'a [u8]) -> Result<T, peg::error::ParseError<usize>>) -> Option<Result<T, AttributeError>>{
+    for attribute in attributes{
+        match attribute.name(class) {
+            Ok(x) if x == name => return Some(parse(name, attribute.bytes, rule)),
+            Ok(_) => continue,
+            Err(err) => return Some(Err(err.into())),
+        }
+    }
+    None
+}
+
+///
+/// A handler covering the bytecode in `start_pc..end_pc`
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionTableEntry{
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+
+    ///
+    /// Class of the caught exceptions, or 0 to catch everything
+    pub catch_type: u16,
+}
+
+///
+/// Body of a method that is neither abstract nor native
+#[derive(Debug)]
+pub struct Code<'a>{
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub bytecode: &'a [u8],
+    pub exception_table: Vec<ExceptionTableEntry>,
+    pub attributes: Vec<Attribute<'a>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LineNumber{
+    pub start_pc: u16,
+    pub line_number: u16,
+}
+
+///
+/// A local variable that is live in `start_pc..start_pc + length`, also used for the `LocalVariableTypeTable`
+#[derive(Debug, Clone, Copy)]
+pub struct LocalVariable{
+    pub start_pc: u16,
+    pub length: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub index: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationType{
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+
+    ///
+    /// Instance of the class at the index
+    Object(u16),
+
+    ///
+    /// Result of the `new` instruction at the offset, before its constructor is called
+    Uninitialized(u16),
+}
+
+///
+/// Types of the locals and operand stack at `offset_delta + 1` bytes after the previous frame, or at
+/// `offset_delta` for the first frame. Frames are given relative to the locals of the previous frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackMapFrame{
+    Same{
+        offset_delta: u16
+    },
+    SameLocals1StackItem{
+        offset_delta: u16,
+        stack: VerificationType
+    },
+
+    ///
+    /// The last `count` locals are gone
+    Chop{
+        offset_delta: u16,
+        count: u8
+    },
+    Append{
+        offset_delta: u16,
+        locals: Vec<VerificationType>
+    },
+    Full{
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+        stack: Vec<VerificationType>
+    },
+}
+
+///
+/// Indices are 0 when the class is anonymous or not a member of another class
+#[derive(Debug, Clone, Copy)]
+pub struct InnerClass{
+    pub inner_class: u16,
+    pub outer_class: u16,
+    pub inner_name: u16,
+    pub access_flags: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct BootstrapMethod{
+    pub method_ref: u16,
+    pub arguments: Vec<u16>,
+}
+
+#[derive(Debug)]
+pub struct RecordComponent<'a>{
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<Attribute<'a>>,
+}
+
+///
+/// Value of an annotation element, constants are indices of constant pool entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElementValue{
+    Byte(u16),
+    Char(u16),
+    Double(u16),
+    Float(u16),
+    Int(u16),
+    Long(u16),
+    Short(u16),
+    Boolean(u16),
+
+    ///
+    /// Index of a utf8 entry rather than a string entry
+    String(u16),
+
+    ///
+    /// Descriptor of the enum type and the name of the constant
+    Enum{
+        type_name: u16,
+        const_name: u16
+    },
+
+    ///
+    /// Return descriptor of the class, like `Ljava/lang/Object;` or `V`
+    Class(u16),
+    Annotation(Annotation),
+    Array(Vec<ElementValue>),
+}
+
+///
+/// An annotation with its type given by the index of its field descriptor, and elements as pairs of
+/// element names and values. Elements with default values are omitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation{
+    pub type_index: u16,
+    pub elements: Vec<(u16, ElementValue)>,
+}
+
+impl Annotation{
+    ///
+    /// Descriptor of the annotation interface, like `Lorg/junit/Test;`
+    pub fn type_descriptor<'a>(&self, class: &ClassFile<'a>) -> Result<Cow<'a, str>, ConstantPoolError>{
+        class.utf8(self.type_index)
+    }
+
+    pub fn element(&self, class: &ClassFile, name: &str) -> Result<Option<&ElementValue>, ConstantPoolError>{
+        for (element, value) in &self.elements{
+            if class.utf8(*element)? == name{
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalVariableTarget{
+    pub start_pc: u16,
+    pub length: u16,
+    pub index: u16,
+}
+
+///
+/// The type in a declaration or expression that a type annotation applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeAnnotationTarget{
+    TypeParameter(u8),
+
+    ///
+    /// Index into the interfaces of the class, or 65535 for the super class
+    Supertype(u16),
+    TypeParameterBound{
+        type_parameter: u8,
+        bound: u8
+    },
+
+    ///
+    /// Field type, return type or receiver type
+    Empty,
+    FormalParameter(u8),
+
+    ///
+    /// Index into the `Exceptions` attribute
+    Throws(u16),
+    LocalVariable(Vec<LocalVariableTarget>),
+
+    ///
+    /// Index into the exception table
+    Catch(u16),
+
+    ///
+    /// Bytecode offset of an `instanceof`, `new` or method reference expression
+    Offset(u16),
+    TypeArgument{
+        offset: u16,
+        type_argument: u8
+    },
+}
+
+///
+/// Step into a nested type, like an array element or a type argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypePathEntry{
+    pub kind: u8,
+    pub type_argument: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeAnnotation{
+    ///
+    /// Kind of target as found in the class file, distinguishing e.g. class and method type parameters
+    pub target_type: u8,
+    pub target: TypeAnnotationTarget,
+    pub path: Vec<TypePathEntry>,
+    pub annotation: Annotation,
+}
+
+///
+/// A decoded attribute, values are constant pool indices unless noted otherwise
+#[derive(Debug)]
+pub enum AttributeInfo<'a>{
+    Code(Code<'a>),
+    SourceFile(u16),
+    LineNumberTable(Vec<LineNumber>),
+    LocalVariableTable(Vec<LocalVariable>),
+    LocalVariableTypeTable(Vec<LocalVariable>),
+    StackMapTable(Vec<StackMapFrame>),
+    Exceptions(Vec<u16>),
+    InnerClasses(Vec<InnerClass>),
+
+    ///
+    /// `method` is 0 when the class is not enclosed by a method
+    EnclosingMethod{
+        class: u16,
+        method: u16
+    },
+    Signature(u16),
+    BootstrapMethods(Vec<BootstrapMethod>),
+    NestHost(u16),
+    NestMembers(Vec<u16>),
+    Record(Vec<RecordComponent<'a>>),
+    PermittedSubclasses(Vec<u16>),
+    ConstantValue(u16),
+    RuntimeVisibleAnnotations(Vec<Annotation>),
+    RuntimeInvisibleAnnotations(Vec<Annotation>),
+
+    ///
+    /// Annotations of each parameter
+    RuntimeVisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    RuntimeInvisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    RuntimeVisibleTypeAnnotations(Vec<TypeAnnotation>),
+    RuntimeInvisibleTypeAnnotations(Vec<TypeAnnotation>),
+
+    ///
+    /// Default value of an annotation interface element
+    AnnotationDefault(ElementValue),
+
+    ///
+    /// Attributes without a decoder are kept as they are
+    Unknown{
+        name: Cow<'a, str>,
+        bytes: &'a [u8]
+    },
+}
+
 #[derive(Debug)]
 pub struct Field<'a>{
     pub access_flags: u16,
@@ -252,6 +1011,14 @@ pub struct Method<'a>{
     pub attributes: Vec<Attribute<'a>>
 }
 
+impl<'a> Method<'a>{
+    ///
+    /// The decoded `Code` attribute, abstract and native methods have none
+    pub fn code(&self, class: &ClassFile<'a>) -> Option<Result<Code<'a>, AttributeError>>{
+        find_attribute(class, &self.attributes, "Code", class_grammar::code)
+    }
+}
+
 #[derive(Debug)]
 pub struct ClassFile<'a>{
     pub version: (u16, u16),
@@ -265,6 +1032,71 @@ pub struct ClassFile<'a>{
     pub attributes: Vec<Attribute<'a>>
 }
 
+impl<'a> ClassFile<'a>{
+    ///
+    /// Look up an entry by its index in the class file, which starts at 1
+    pub fn constant(&self, index: u16) -> Result<&ConstantPoolEntry<'a>, ConstantPoolError>{
+        index.checked_sub(1)
+            .and_then(|x| self.constant_pool.get(x as usize))
+            .ok_or(ConstantPoolError::OutOfRange(index))
+    }
+
+    pub fn utf8(&self, index: u16) -> Result<Cow<'a, str>, ConstantPoolError>{
+        match self.constant(index)? {
+            ConstantPoolEntry::Utf8Info(bytes) => mutf8::decode(bytes).map_err(|err| ConstantPoolError::InvalidUtf8(index, err)),
+            x => Err(wrong_kind(index, "utf8", x)),
+        }
+    }
+
+    ///
+    /// Internal name of a class entry, like `java/lang/Object`
+    pub fn class_name(&self, index: u16) -> Result<Cow<'a, str>, ConstantPoolError>{
+        match self.constant(index)? {
+            ConstantPoolEntry::ClassInfo(name) => self.utf8(*name),
+            x => Err(wrong_kind(index, "class", x)),
+        }
+    }
+
+    ///
+    /// Name and descriptor of a name and type entry
+    pub fn name_and_type(&self, index: u16) -> Result<(Cow<'a, str>, Cow<'a, str>), ConstantPoolError>{
+        match self.constant(index)? {
+            ConstantPoolEntry::NameAndTypeInfo { name, descriptor } => Ok((self.utf8(*name)?, self.utf8(*descriptor)?)),
+            x => Err(wrong_kind(index, "name and type", x)),
+        }
+    }
+
+    ///
+    /// Class, name and descriptor of a method or interface method reference
+    pub fn resolve_method_ref(&self, index: u16) -> Result<MemberRef<'a>, ConstantPoolError>{
+        match self.constant(index)? {
+            ConstantPoolEntry::MethodRef { class, name_and_type } |
+            ConstantPoolEntry::InterfaceMethodRef { class, name_and_type } => {
+                let (name, descriptor) = self.name_and_type(*name_and_type)?;
+                Ok((self.class_name(*class)?, name, descriptor))
+            },
+            x => Err(wrong_kind(index, "method reference", x)),
+        }
+    }
+
+    pub fn this_class_name(&self) -> Result<Cow<'a, str>, ConstantPoolError>{
+        self.class_name(self.this_class)
+    }
+
+    ///
+    /// Only `java/lang/Object` has no super class
+    pub fn super_class_name(&self) -> Result<Option<Cow<'a, str>>, ConstantPoolError>{
+        match self.super_class {
+            0 => Ok(None),
+            x => self.class_name(x).map(Some),
+        }
+    }
+}
+
+fn wrong_kind(index: u16, expected: &'static str, found: &ConstantPoolEntry) -> ConstantPoolError{
+    ConstantPoolError::WrongKind { index, expected, found: found.kind() }
+}
+
 impl<'a> Display for ClassFile<'a>{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("({} {})", self.version.0, self.version.1))?;
@@ -275,4 +1107,421 @@ impl<'a> Display for ClassFile<'a>{
         f.write_fmt(format_args!(" class attributes: {}",self.attributes.len()))?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    ///
+    /// Class `Main` extending `java/lang/Object`, with a method and an interface method reference to `run()V`
+    fn resolvable_class() -> ClassFile<'static>{
+        ClassFile{
+            version: (0, 52),
+            access_flags: 0x21,
+            this_class: 4,
+            super_class: 2,
+            constant_pool: vec![
+                ConstantPoolEntry::Utf8Info(b"java/lang/Object"),
+                ConstantPoolEntry::ClassInfo(1),
+                ConstantPoolEntry::Utf8Info(b"Main"),
+                ConstantPoolEntry::ClassInfo(3),
+                ConstantPoolEntry::Utf8Info(b"run"),
+                ConstantPoolEntry::Utf8Info(b"()V"),
+                ConstantPoolEntry::NameAndTypeInfo { name: 5, descriptor: 6 },
+                ConstantPoolEntry::MethodRef { class: 4, name_and_type: 7 },
+                ConstantPoolEntry::InterfaceMethodRef { class: 2, name_and_type: 7 },
+                ConstantPoolEntry::IntegerInfo(3),
+                ConstantPoolEntry::Utf8Info(&[0xff]),
+                ConstantPoolEntry::ClassInfo(11),
+                ConstantPoolEntry::ClassInfo(10),
+                ConstantPoolEntry::LongInfo(7),
+                ConstantPoolEntry::Unusable,
+                ConstantPoolEntry::ClassInfo(15),
+                ConstantPoolEntry::Utf8Info(b"Code"),
+                ConstantPoolEntry::Utf8Info(b"LineNumberTable"),
+                ConstantPoolEntry::Utf8Info(b"StackMapTable"),
+                ConstantPoolEntry::Utf8Info(b"InnerClasses"),
+                ConstantPoolEntry::Utf8Info(b"Record"),
+                ConstantPoolEntry::Utf8Info(b"SourceFile"),
+                ConstantPoolEntry::Utf8Info(b"Custom"),
+                ConstantPoolEntry::Utf8Info(b"Lorg/junit/Test;"),
+                ConstantPoolEntry::Utf8Info(b"value"),
+                ConstantPoolEntry::Utf8Info(b"RuntimeVisibleAnnotations"),
+                ConstantPoolEntry::Utf8Info(b"RuntimeVisibleParameterAnnotations"),
+                ConstantPoolEntry::Utf8Info(b"RuntimeInvisibleTypeAnnotations"),
+            ],
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![],
+            attributes: vec![]
+        }
+    }
+
+    #[test]
+    fn constant_indices_start_at_one(){
+        let class = resolvable_class();
+        assert!(matches!(class.constant(1), Ok(ConstantPoolEntry::Utf8Info(b"java/lang/Object"))));
+        assert!(matches!(class.constant(13), Ok(ConstantPoolEntry::ClassInfo(10))));
+        assert_eq!(class.constant(0).err(), Some(ConstantPoolError::OutOfRange(0)));
+        assert_eq!(class.constant(29).err(), Some(ConstantPoolError::OutOfRange(29)));
+        assert_eq!(class.constant(u16::MAX).err(), Some(ConstantPoolError::OutOfRange(u16::MAX)));
+    }
+
+    #[test]
+    fn class_names(){
+        let class = resolvable_class();
+        assert_eq!(class.this_class_name().unwrap(), "Main");
+        assert_eq!(class.super_class_name().unwrap().as_deref(), Some("java/lang/Object"));
+        assert_eq!(class.class_name(2).unwrap(), "java/lang/Object");
+
+        assert_eq!(class.class_name(0).unwrap_err(), ConstantPoolError::OutOfRange(0));
+        assert_eq!(class.class_name(1).unwrap_err(), ConstantPoolError::WrongKind { index: 1, expected: "class", found: "utf8" });
+        assert_eq!(class.class_name(12).unwrap_err(), ConstantPoolError::InvalidUtf8(11, Mutf8Error::InvalidByte(0)));
+        // The error names the entry the class points to
+        assert_eq!(class.class_name(13).unwrap_err(), ConstantPoolError::WrongKind { index: 10, expected: "utf8", found: "integer" });
+    }
+
+    #[test]
+    fn super_class(){
+        let mut class = resolvable_class();
+        class.super_class = 0;
+        assert_eq!(class.super_class_name().unwrap(), None);
+        class.super_class = 5;
+        assert_eq!(class.super_class_name().unwrap_err(), ConstantPoolError::WrongKind { index: 5, expected: "class", found: "utf8" });
+        class.super_class = 30;
+        assert_eq!(class.super_class_name().unwrap_err(), ConstantPoolError::OutOfRange(30));
+    }
+
+    #[test]
+    fn method_refs(){
+        let class = resolvable_class();
+        assert_eq!(class.resolve_method_ref(8).unwrap(), ("Main".into(), "run".into(), "()V".into()));
+        assert_eq!(class.resolve_method_ref(9).unwrap(), ("java/lang/Object".into(), "run".into(), "()V".into()));
+
+        assert_eq!(class.resolve_method_ref(0).unwrap_err(), ConstantPoolError::OutOfRange(0));
+        assert_eq!(class.resolve_method_ref(7).unwrap_err(), ConstantPoolError::WrongKind { index: 7, expected: "method reference", found: "name and type" });
+        assert_eq!(class.resolve_method_ref(10).unwrap_err().to_string(), "expected constant pool entry 10 to be method reference, found integer");
+    }
+
+    #[test]
+    fn unusable_slot(){
+        let class = resolvable_class();
+        assert!(matches!(class.constant(14), Ok(ConstantPoolEntry::LongInfo(7))));
+        assert!(matches!(class.constant(15), Ok(ConstantPoolEntry::Unusable)));
+        assert_eq!(class.class_name(15).unwrap_err(), ConstantPoolError::WrongKind { index: 15, expected: "class", found: "unusable" });
+        assert_eq!(class.resolve_method_ref(15).unwrap_err(), ConstantPoolError::WrongKind { index: 15, expected: "method reference", found: "unusable" });
+        assert_eq!(class.class_name(16).unwrap_err(), ConstantPoolError::WrongKind { index: 15, expected: "utf8", found: "unusable" });
+    }
+
+    // max_stack 2, max_locals 1, iload_0 iconst_1 iadd ireturn, one handler catching `Main` and a LineNumberTable
+    const CODE: [u8; 36] = [
+        0, 2, 0, 1, 0, 0, 0, 4, 0x1a, 0x04, 0x60, 0xac,
+        0, 1, 0, 0, 0, 3, 0, 3, 0, 4,
+        0, 1, 0, 18, 0, 0, 0, 6, 0, 1, 0, 0, 0, 7,
+    ];
+
+    fn method(attributes: Vec<Attribute<'static>>) -> Method<'static>{
+        Method{ access_flags: 0x9, name_index: 5, descriptor_index: 6, attributes }
+    }
+
+    #[test]
+    fn code_attribute(){
+        let code = class_grammar::code(&CODE).unwrap();
+        assert_eq!((code.max_stack, code.max_locals), (2, 1));
+        assert_eq!(code.bytecode, [0x1a, 0x04, 0x60, 0xac]);
+        assert!(matches!(code.exception_table[..], [ExceptionTableEntry{ start_pc: 0, end_pc: 3, handler_pc: 3, catch_type: 4 }]));
+        assert!(matches!(code.attributes[..], [Attribute{ name_index: 18, bytes: [0, 1, 0, 0, 0, 7] }]));
+
+        // The bytecode length runs past the end of the attribute
+        let mut bytes = CODE;
+        bytes[7] = 40;
+        assert!(class_grammar::code(&bytes).is_err());
+        assert!(class_grammar::code(&CODE[..CODE.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn method_code(){
+        let class = resolvable_class();
+        let code = method(vec![Attribute{ name_index: 18, bytes: &[0, 0] }, Attribute{ name_index: 17, bytes: &CODE }]).code(&class);
+        assert_eq!(code.unwrap().unwrap().bytecode, [0x1a, 0x04, 0x60, 0xac]);
+
+        // Abstract and native methods
+        assert!(method(vec![Attribute{ name_index: 18, bytes: &[0, 0] }]).code(&class).is_none());
+
+        let code = method(vec![Attribute{ name_index: 17, bytes: &CODE[..10] }]).code(&class);
+        assert!(matches!(code, Some(Err(AttributeError::Malformed { attribute: "Code", .. }))));
+
+        let code = method(vec![Attribute{ name_index: 10, bytes: &CODE }]).code(&class);
+        assert!(matches!(code, Some(Err(AttributeError::ConstantPool(ConstantPoolError::WrongKind { index: 10, .. })))));
+    }
+
+    #[test]
+    fn stack_map_frames(){
+        use VerificationType::*;
+        let bytes = [
+            0, 11,
+            5,
+            63,
+            64, 1,
+            127, 7, 0, 4,
+            247, 0, 100, 8, 0, 3,
+            248, 0, 1,
+            250, 0, 2,
+            251, 1, 0,
+            252, 0, 4, 4,
+            254, 0, 5, 0, 2, 3,
+            255, 0, 6, 0, 2, 6, 5, 0, 1, 0,
+        ];
+        assert_eq!(class_grammar::stack_map_table(&bytes).unwrap(), [
+            StackMapFrame::Same { offset_delta: 5 },
+            StackMapFrame::Same { offset_delta: 63 },
+            StackMapFrame::SameLocals1StackItem { offset_delta: 0, stack: Integer },
+            StackMapFrame::SameLocals1StackItem { offset_delta: 63, stack: Object(4) },
+            StackMapFrame::SameLocals1StackItem { offset_delta: 100, stack: Uninitialized(3) },
+            StackMapFrame::Chop { offset_delta: 1, count: 3 },
+            StackMapFrame::Chop { offset_delta: 2, count: 1 },
+            StackMapFrame::Same { offset_delta: 256 },
+            StackMapFrame::Append { offset_delta: 4, locals: vec![Long] },
+            StackMapFrame::Append { offset_delta: 5, locals: vec![Top, Float, Double] },
+            StackMapFrame::Full { offset_delta: 6, locals: vec![UninitializedThis, Null], stack: vec![Top] },
+        ]);
+
+        // Tags 128 to 246 are reserved, and verification types end at 8
+        assert!(class_grammar::stack_map_table(&[0, 1, 128]).is_err());
+        assert!(class_grammar::stack_map_table(&[0, 1, 246, 0, 0, 1]).is_err());
+        assert!(class_grammar::stack_map_table(&[0, 1, 64, 9]).is_err());
+        // An append frame with fewer locals than its tag says
+        assert!(class_grammar::stack_map_table(&[0, 1, 253, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn decode_dispatches_on_the_name(){
+        let class = resolvable_class();
+
+        let info = Attribute{ name_index: 17, bytes: &CODE }.decode(&class).unwrap();
+        assert!(matches!(info, AttributeInfo::Code(Code{ max_stack: 2, max_locals: 1, .. })));
+        let info = Attribute{ name_index: 18, bytes: &[0, 1, 0, 0, 0, 7] }.decode(&class).unwrap();
+        assert!(matches!(info, AttributeInfo::LineNumberTable(x) if matches!(x[..], [LineNumber{ start_pc: 0, line_number: 7 }])));
+        let info = Attribute{ name_index: 19, bytes: &[0, 1, 7] }.decode(&class).unwrap();
+        assert!(matches!(info, AttributeInfo::StackMapTable(x) if x == [StackMapFrame::Same { offset_delta: 7 }]));
+        let info = Attribute{ name_index: 22, bytes: &[0, 3] }.decode(&class).unwrap();
+        assert!(matches!(info, AttributeInfo::SourceFile(3)));
+
+        // Attributes must be consumed completely
+        let info = Attribute{ name_index: 22, bytes: &[0, 3, 0] }.decode(&class);
+        assert!(matches!(info, Err(AttributeError::Malformed { attribute: "SourceFile", .. })));
+        let info = Attribute{ name_index: 10, bytes: &[0, 3] }.decode(&class);
+        assert!(matches!(info, Err(AttributeError::ConstantPool(ConstantPoolError::WrongKind { index: 10, .. }))));
+    }
+
+    #[test]
+    fn unknown_attributes_are_passed_through(){
+        let class = resolvable_class();
+        let bytes = [1, 2, 3];
+        match (Attribute{ name_index: 23, bytes: &bytes }).decode(&class).unwrap() {
+            AttributeInfo::Unknown { name, bytes: x } => {
+                assert_eq!(name, "Custom");
+                assert_eq!(x.as_ptr(), bytes.as_ptr());
+                assert_eq!(x.len(), 3);
+            },
+            x => panic!("expected an unknown attribute, found {:?}", x),
+        }
+    }
+
+    #[test]
+    fn inner_classes(){
+        let class = resolvable_class();
+        let bytes = [0, 2, 0, 4, 0, 2, 0, 3, 0, 0x19, 0, 12, 0, 0, 0, 0, 0, 0];
+        let AttributeInfo::InnerClasses(inner) = (Attribute{ name_index: 20, bytes: &bytes }).decode(&class).unwrap() else { panic!() };
+        assert!(matches!(inner[..], [
+            InnerClass{ inner_class: 4, outer_class: 2, inner_name: 3, access_flags: 0x19 },
+            InnerClass{ inner_class: 12, outer_class: 0, inner_name: 0, access_flags: 0 },
+        ]));
+        assert!(class_grammar::inner_classes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn record_components(){
+        let class = resolvable_class();
+        // Two components, the second with an attribute of its own
+        let bytes = [0, 2, 0, 5, 0, 6, 0, 0, 0, 3, 0, 6, 0, 1, 0, 22, 0, 0, 0, 2, 0, 1];
+        let AttributeInfo::Record(components) = (Attribute{ name_index: 21, bytes: &bytes }).decode(&class).unwrap() else { panic!() };
+        assert_eq!(components.len(), 2);
+        assert_eq!((components[0].name_index, components[0].descriptor_index), (5, 6));
+        assert!(components[0].attributes.is_empty());
+        assert_eq!((components[1].name_index, components[1].descriptor_index), (3, 6));
+        assert!(matches!(components[1].attributes[..], [Attribute{ name_index: 22, bytes: [0, 1] }]));
+        assert!(matches!(components[1].attributes[0].decode(&class), Ok(AttributeInfo::SourceFile(1))));
+    }
+
+    #[test]
+    fn annotations(){
+        let class = resolvable_class();
+        // @Test(value = {10, E.run, @Test("Main")}, run = ()V.class)
+        let bytes = [
+            0, 1, 0, 24, 0, 2,
+            0, 25, b'[', 0, 3,
+                b'I', 0, 10,
+                b'e', 0, 24, 0, 5,
+                b'@', 0, 24, 0, 1, 0, 25, b's', 0, 3,
+            0, 5, b'c', 0, 6,
+        ];
+        let AttributeInfo::RuntimeVisibleAnnotations(annotations) = (Attribute{ name_index: 26, bytes: &bytes }).decode(&class).unwrap() else { panic!() };
+        let nested = Annotation{ type_index: 24, elements: vec![(25, ElementValue::String(3))] };
+        assert_eq!(annotations, [Annotation{
+            type_index: 24,
+            elements: vec![
+                (25, ElementValue::Array(vec![
+                    ElementValue::Int(10),
+                    ElementValue::Enum { type_name: 24, const_name: 5 },
+                    ElementValue::Annotation(nested),
+                ])),
+                (5, ElementValue::Class(6)),
+            ]
+        }]);
+
+        let annotation = &annotations[0];
+        assert_eq!(annotation.type_descriptor(&class).unwrap(), "Lorg/junit/Test;");
+        assert_eq!(annotation.element(&class, "run").unwrap(), Some(&ElementValue::Class(6)));
+        assert_eq!(annotation.element(&class, "missing").unwrap(), None);
+
+        // Unknown element value tag
+        let info = Attribute{ name_index: 26, bytes: &[0, 1, 0, 24, 0, 1, 0, 25, b'x', 0, 0] }.decode(&class);
+        assert!(matches!(info, Err(AttributeError::Malformed { attribute: "RuntimeVisibleAnnotations", .. })));
+    }
+
+    #[test]
+    fn parameter_annotations(){
+        let class = resolvable_class();
+        let info = Attribute{ name_index: 27, bytes: &[2, 0, 0, 0, 1, 0, 24, 0, 0] }.decode(&class).unwrap();
+        let AttributeInfo::RuntimeVisibleParameterAnnotations(parameters) = info else { panic!() };
+        assert_eq!(parameters, [vec![], vec![Annotation{ type_index: 24, elements: vec![] }]]);
+
+        // Fewer parameters than the count says
+        assert!(class_grammar::parameter_annotations(&[2, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn type_annotations(){
+        let class = resolvable_class();
+        let annotation = [0, 24, 0, 0];
+        let targets: [&[u8]; 10] = [
+            &[0x00, 3, 0],
+            &[0x10, 0xff, 0xff, 1, 3, 0],
+            &[0x11, 1, 2, 0],
+            &[0x14, 0],
+            &[0x16, 1, 0],
+            &[0x17, 0, 2, 0],
+            &[0x40, 0, 1, 0, 0, 0, 4, 0, 1, 0],
+            &[0x42, 0, 0, 0],
+            &[0x44, 0, 7, 0],
+            &[0x48, 0, 7, 1, 0],
+        ];
+        let mut bytes = vec![0, targets.len() as u8];
+        for target in targets{
+            bytes.extend(target);
+            bytes.extend(annotation);
+        }
+
+        let AttributeInfo::RuntimeInvisibleTypeAnnotations(annotations) = (Attribute{ name_index: 28, bytes: &bytes }).decode(&class).unwrap() else { panic!() };
+        assert!(annotations.iter().all(|x| x.annotation == Annotation{ type_index: 24, elements: vec![] }));
+        assert_eq!(annotations.iter().map(|x| (x.target_type, x.target.clone())).collect::<Vec<_>>(), [
+            (0x00, TypeAnnotationTarget::TypeParameter(3)),
+            (0x10, TypeAnnotationTarget::Supertype(65535)),
+            (0x11, TypeAnnotationTarget::TypeParameterBound { type_parameter: 1, bound: 2 }),
+            (0x14, TypeAnnotationTarget::Empty),
+            (0x16, TypeAnnotationTarget::FormalParameter(1)),
+            (0x17, TypeAnnotationTarget::Throws(2)),
+            (0x40, TypeAnnotationTarget::LocalVariable(vec![LocalVariableTarget{ start_pc: 0, length: 4, index: 1 }])),
+            (0x42, TypeAnnotationTarget::Catch(0)),
+            (0x44, TypeAnnotationTarget::Offset(7)),
+            (0x48, TypeAnnotationTarget::TypeArgument { offset: 7, type_argument: 1 }),
+        ]);
+        assert_eq!(annotations[1].path, [TypePathEntry{ kind: 3, type_argument: 0 }]);
+        assert!(annotations.iter().enumerate().all(|(i, x)| i == 1 || x.path.is_empty()));
+
+        // 0x20 is not a target type
+        assert!(class_grammar::type_annotations(&[0, 1, 0x20, 0, 0, 0, 24, 0, 0]).is_err());
+    }
+
+    ///
+    /// Class file with the given constant pool and no interfaces, fields, methods or attributes
+    fn class(count: u16, pool: &[u8]) -> Vec<u8>{
+        let mut bytes = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52];
+        bytes.extend(count.to_be_bytes());
+        bytes.extend(pool);
+        bytes.extend([0x00, 0x21, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes
+    }
+
+    // 1: Utf8 "A", 2: Class 1, 3 and 4: Long 7
+    const POOL: [u8; 16] = [1, 0, 1, b'A', 7, 0, 1, 5, 0, 0, 0, 0, 0, 0, 0, 7];
+
+    #[test]
+    fn minimal_class(){
+        let bytes = class(5, &POOL);
+        let class = class_parser::class_file(&bytes).unwrap();
+        assert_eq!(class.version, (0, 52));
+        assert_eq!(class.this_class, 2);
+        assert!(matches!(class.constant_pool[..], [
+            ConstantPoolEntry::Utf8Info(b"A"),
+            ConstantPoolEntry::ClassInfo(1),
+            ConstantPoolEntry::LongInfo(7),
+            ConstantPoolEntry::Unusable
+        ]));
+    }
+
+    #[test]
+    fn bad_magic(){
+        let mut bytes = class(5, &POOL);
+        bytes[3] = 0xbf;
+        assert_eq!(class_parser::class_file(&bytes).err(), Some(ClassFileError::BadMagic([0xca, 0xfe, 0xba, 0xbf])));
+    }
+
+    #[test]
+    fn truncated(){
+        let bytes = class(5, &POOL);
+        assert_eq!(class_parser::class_file(&bytes[..2]).err(), Some(ClassFileError::Truncated { offset: 2, constant: None }));
+        assert_eq!(class_parser::class_file(&bytes[..9]).err(), Some(ClassFileError::Truncated { offset: 9, constant: None }));
+        // In the middle of the long
+        assert_eq!(class_parser::class_file(&bytes[..20]).err(), Some(ClassFileError::Truncated { offset: 20, constant: Some(3) }));
+        // In the methods count
+        let len = bytes.len() - 3;
+        assert_eq!(class_parser::class_file(&bytes[..len]).err(), Some(ClassFileError::Truncated { offset: len, constant: None }));
+    }
+
+    #[test]
+    fn trailing_bytes(){
+        let mut bytes = class(5, &POOL);
+        let len = bytes.len();
+        bytes.extend([0xca, 0xfe, 0]);
+        assert_eq!(class_parser::class_file(&bytes).err(), Some(ClassFileError::TrailingBytes { offset: len, count: 3 }));
+    }
+
+    #[test]
+    fn unknown_constant_tag(){
+        let bytes = class(3, &[1, 0, 1, b'A', 2, 0, 1]);
+        assert_eq!(class_parser::class_file(&bytes).err(), Some(ClassFileError::UnknownConstantTag { offset: 14, index: 2, tag: 2 }));
+
+        // The long takes up two indices
+        let mut pool = POOL.to_vec();
+        pool.extend([13, 0, 1]);
+        let bytes = class(6, &pool);
+        assert_eq!(class_parser::class_file(&bytes).err(), Some(ClassFileError::UnknownConstantTag { offset: 26, index: 5, tag: 13 }));
+    }
+
+    #[test]
+    fn malformed(){
+        // Method handle with reference kind 0
+        let mut pool = POOL.to_vec();
+        pool.extend([15, 0, 0, 2]);
+        let bytes = class(6, &pool);
+        let err = class_parser::class_file(&bytes).unwrap_err();
+        assert_eq!(err, ClassFileError::Malformed { offset: 27, constant: Some(5), expected: "reference kind".to_string(), found: 0 });
+        assert_eq!(err.to_string(), "malformed class at 27 in constant pool entry 5, expected reference kind, found 0x00");
+
+        pool[16 + 1] = 9;
+        assert!(class_parser::class_file(&class(6, &pool)).is_ok());
+    }
+}
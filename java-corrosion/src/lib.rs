@@ -0,0 +1,9 @@
+pub mod frontend;
+pub mod mutf8;
+pub mod bytecode;
+pub mod descriptor;
+mod translate;
+mod classpath;
+
+pub use translate::*;
+pub use classpath::*;
@@ -1,20 +1,41 @@
-use std::{fs::File, io::Read};
+use std::process::ExitCode;
 
-use zip::ZipArchive;
+use java_corrosion::ClassPath;
 
-pub mod frontend;
+fn main() -> ExitCode {
+    let paths = std::env::args().skip(1).collect::<Vec<_>>();
+    if paths.is_empty(){
+        eprintln!("usage: java-corrosion <directory or jar>...");
+        return ExitCode::FAILURE;
+    }
+
+    let mut classpath = ClassPath::new();
+    for path in &paths{
+        if let Err(err) = classpath.add(path){
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    }
 
-fn main() {
-    let mut archive = ZipArchive::new(File::open("./jagexappletviewer.jar").unwrap()).unwrap();
+    let names = match classpath.class_names() {
+        Ok(names) => names,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    for file in 0..archive.len(){
-        let mut file = archive.by_index(file).unwrap();
-        if file.name().ends_with(".class"){
-            println!("{}", file.name());
-            let mut buffer = Vec::with_capacity(file.size() as usize);
-            file.read_to_end(&mut buffer).unwrap();
-            let class = frontend::class_parser::class_file(&buffer).unwrap();
-            println!("\t{}",class);
+    let mut failed = false;
+    for name in names{
+        match classpath.load(&name) {
+            Ok(Some(class)) => println!("{}\n\t{}", class.source(), class.class()),
+            Ok(None) => {},
+            Err(err) => {
+                eprintln!("error: {}", err);
+                failed = true;
+            }
         }
     }
+
+    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
 }
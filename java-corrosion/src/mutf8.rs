@@ -0,0 +1,169 @@
+use std::{borrow::Cow, fmt::Display};
+
+///
+/// Decoding and encoding of the modified utf8 used by class files.
+/// It differs from utf8 in encoding nul as two bytes, and characters outside the basic multilingual plane
+/// as a surrogate pair of three bytes each.
+///
+/// Strings in the jvm may contain unpaired surrogates, which `decode` rejects as a `str` can't hold them.
+/// `decode_utf16` and `encode_utf16` keep them intact
+pub fn decode(bytes: &[u8]) -> Result<Cow<'_, str>, Mutf8Error>{
+    // Without nul, surrogates and four byte sequences the two encodings are identical
+    if let Ok(x) = std::str::from_utf8(bytes){
+        if !bytes.iter().any(|x| *x == 0 || *x >= 0xf0){
+            return Ok(Cow::Borrowed(x));
+        }
+    }
+
+    let units = decode_utf16(bytes)?;
+    let mut out = String::with_capacity(bytes.len());
+    let mut position = 0;
+    for x in char::decode_utf16(units.iter().copied()){
+        match x {
+            Ok(x) => {
+                out.push(x);
+                position += encoded_len(x);
+            },
+            Err(_) => return Err(Mutf8Error::UnpairedSurrogate(position)),
+        }
+    }
+    Ok(Cow::Owned(out))
+}
+
+///
+/// Decode into utf16 code units, the representation of strings in the jvm
+pub fn decode_utf16(bytes: &[u8]) -> Result<Vec<u16>, Mutf8Error>{
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut position = 0;
+    while position < bytes.len(){
+        let continuation = |offset: usize| match bytes.get(position + offset) {
+            Some(x) if x & 0xc0 == 0x80 => Ok((x & 0x3f) as u16),
+            Some(_) => Err(Mutf8Error::InvalidByte(position + offset)),
+            None => Err(Mutf8Error::Truncated),
+        };
+
+        let first = bytes[position];
+        let (unit, len) = match first {
+            0x01..=0x7f => (first as u16, 1),
+            0xc0..=0xdf => (((first & 0x1f) as u16) << 6 | continuation(1)?, 2),
+            0xe0..=0xef => (((first & 0x0f) as u16) << 12 | continuation(1)? << 6 | continuation(2)?, 3),
+            _ => return Err(Mutf8Error::InvalidByte(position)),
+        };
+        units.push(unit);
+        position += len;
+    }
+    Ok(units)
+}
+
+///
+/// Borrows the string when its utf8 encoding is also valid modified utf8
+pub fn encode(string: &str) -> Cow<'_, [u8]>{
+    if !string.chars().any(|x| x == '\0' || x.len_utf16() == 2){
+        return Cow::Borrowed(string.as_bytes());
+    }
+    Cow::Owned(encode_utf16(&string.encode_utf16().collect::<Vec<_>>()))
+}
+
+pub fn encode_utf16(units: &[u16]) -> Vec<u8>{
+    let mut out = Vec::with_capacity(units.len());
+    for unit in units.iter().copied(){
+        match unit {
+            0x01..=0x7f => out.push(unit as u8),
+            0x00 | 0x80..=0x7ff => out.extend([0xc0 | (unit >> 6) as u8, 0x80 | (unit & 0x3f) as u8]),
+            _ => out.extend([0xe0 | (unit >> 12) as u8, 0x80 | ((unit >> 6) & 0x3f) as u8, 0x80 | (unit & 0x3f) as u8]),
+        }
+    }
+    out
+}
+
+fn encoded_len(x: char) -> usize{
+    match x {
+        '\0' => 2,
+        x if x.len_utf16() == 2 => 6,
+        x => x.len_utf8(),
+    }
+}
+
+///
+/// Positions are byte offsets into the encoded string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutf8Error{
+    InvalidByte(usize),
+    Truncated,
+    UnpairedSurrogate(usize),
+}
+
+impl Display for Mutf8Error{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mutf8Error::InvalidByte(x) => write!(f, "invalid byte at {}", x),
+            Mutf8Error::Truncated => write!(f, "unexpected end of string"),
+            Mutf8Error::UnpairedSurrogate(x) => write!(f, "unpaired surrogate at {}", x),
+        }
+    }
+}
+
+impl std::error::Error for Mutf8Error{}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // U+1F600 as the surrogate pair D83D DE00, each encoded in three bytes
+    const EMOJI: [u8; 6] = [0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80];
+
+    #[test]
+    fn ascii_is_borrowed(){
+        assert!(matches!(decode(b"abc"), Ok(Cow::Borrowed("abc"))));
+        assert!(matches!(encode("abc"), Cow::Borrowed(b"abc")));
+    }
+
+    #[test]
+    fn nul_is_two_bytes(){
+        assert_eq!(decode_utf16(&[0xc0, 0x80]), Ok(vec![0]));
+        assert_eq!(encode_utf16(&[0]), vec![0xc0, 0x80]);
+        assert_eq!(decode(&[b'a', 0xc0, 0x80]).unwrap(), "a\0");
+        assert_eq!(&*encode("a\0"), &[b'a', 0xc0, 0x80]);
+    }
+
+    #[test]
+    fn supplementary_characters_are_surrogate_pairs(){
+        assert_eq!(decode_utf16(&EMOJI), Ok(vec![0xd83d, 0xde00]));
+        assert_eq!(encode_utf16(&[0xd83d, 0xde00]), EMOJI);
+        assert_eq!(decode(&EMOJI).unwrap(), "\u{1f600}");
+        assert_eq!(&*encode("\u{1f600}"), &EMOJI);
+    }
+
+    #[test]
+    fn unpaired_surrogates_round_trip_as_utf16(){
+        let bytes = [b'a', 0xed, 0xa0, 0xbd, b'b'];
+        let units = decode_utf16(&bytes).unwrap();
+        assert_eq!(units, vec![0x61, 0xd83d, 0x62]);
+        assert_eq!(encode_utf16(&units), bytes);
+    }
+
+    #[test]
+    fn decode_rejects_unpaired_surrogates(){
+        assert_eq!(decode(&[b'a', 0xed, 0xa0, 0xbd, b'b']), Err(Mutf8Error::UnpairedSurrogate(1)));
+
+        // The offset counts the six bytes of the pair before it
+        let mut bytes = EMOJI.to_vec();
+        bytes.extend([0xc0, 0x80, 0xed, 0xb8, 0x80]);
+        assert_eq!(decode(&bytes), Err(Mutf8Error::UnpairedSurrogate(8)));
+    }
+
+    #[test]
+    fn invalid_bytes(){
+        assert_eq!(decode(&[0]), Err(Mutf8Error::InvalidByte(0)));
+        assert_eq!(decode(&[b'a', 0xff]), Err(Mutf8Error::InvalidByte(1)));
+        assert_eq!(decode(&[0xf0, 0x9f, 0x98, 0x80]), Err(Mutf8Error::InvalidByte(0)));
+        assert_eq!(decode_utf16(&[0xc3, b'a']), Err(Mutf8Error::InvalidByte(1)));
+        assert_eq!(decode_utf16(&[0x80]), Err(Mutf8Error::InvalidByte(0)));
+    }
+
+    #[test]
+    fn truncated(){
+        assert_eq!(decode(&[0xe2, 0x82]), Err(Mutf8Error::Truncated));
+        assert_eq!(decode_utf16(&[b'a', 0xc3]), Err(Mutf8Error::Truncated));
+    }
+}
@@ -0,0 +1,820 @@
+use std::{collections::{BTreeSet, HashMap, HashSet, VecDeque}, io::{Cursor, Read}, path::Path};
+
+use corrosion_base::{BlockBuilder, BlockRef, Diagnostic, Frontend, FunctionBuilder, FunctionRef, ModuleBuilder, Operation, Translation, Type, Value, VariableRef};
+use zip::ZipArchive;
+
+use crate::{bytecode::{self, Instruction, Kind}, descriptor::{BaseType, FieldType, MethodDescriptor}, frontend::{class_parser, ClassFile, Code, ConstantPoolEntry}};
+
+const CLASS_MAGIC: [u8; 4] = [0xca, 0xfe, 0xba, 0xbe];
+const ZIP_MAGIC: [u8; 4] = [b'P', b'K', 3, 4];
+
+const ACC_STATIC: u16 = 0x0008;
+
+///
+/// Frontend for java class files and jars.
+///
+/// Static methods taking and returning ints, longs, floats and doubles are translated into functions of a single module.
+/// Their bodies may use constants, locals, arithmetic, comparisons of ints against zero, `goto`, returns
+/// and `invokestatic` of other translated methods. Other static methods are skipped with a warning
+pub struct JavaFrontend;
+
+impl Frontend for JavaFrontend{
+    fn name(&self) -> &str {
+        "java"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["class", "jar"]
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        bytes.starts_with(&CLASS_MAGIC) || bytes.starts_with(&ZIP_MAGIC)
+    }
+
+    fn translate(&self, path: &Path, bytes: &[u8]) -> Translation {
+        let mut translation = Translation::default();
+
+        let mut files = Vec::new();
+        let jar = bytes.starts_with(&ZIP_MAGIC);
+        if jar{
+            let mut archive = match ZipArchive::new(Cursor::new(bytes)) {
+                Ok(archive) => archive,
+                Err(err) => {
+                    translation.diagnostics.push(Diagnostic::error(format!("failed to open jar: {}", err)).at(path.display().to_string()));
+                    return translation;
+                }
+            };
+
+            for index in 0..archive.len(){
+                let mut file = match archive.by_index(index) {
+                    Ok(file) => file,
+                    Err(err) => {
+                        translation.diagnostics.push(Diagnostic::error(format!("failed to read entry {}: {}", index, err)).at(path.display().to_string()));
+                        continue;
+                    }
+                };
+                if !file.name().ends_with(".class"){
+                    continue;
+                }
+
+                let name = format!("{}!{}", path.display(), file.name());
+                let mut buffer = Vec::with_capacity(file.size() as usize);
+                match file.read_to_end(&mut buffer) {
+                    Ok(_) => files.push((name, buffer)),
+                    Err(err) => translation.diagnostics.push(Diagnostic::error(format!("failed to read class: {}", err)).at(name)),
+                }
+            }
+        }
+        else{
+            files.push((path.display().to_string(), bytes.to_vec()));
+        }
+
+        let mut classes = Vec::with_capacity(files.len());
+        for (name, bytes) in &files{
+            match class_parser::class_file(bytes) {
+                Ok(class) => classes.push((name.as_str(), class)),
+                Err(err) => translation.diagnostics.push(Diagnostic::error(format!("invalid class file: {}", err)).at(name.as_str())),
+            }
+        }
+
+        // Methods of a jar are qualified by their class, as different classes may have methods of the same name
+        translate_classes(&classes, jar, &mut translation);
+        translation
+    }
+}
+
+///
+/// A static method with a supported signature, which is translated into `function`
+struct StaticMethod<'c, 'a>{
+    location: String,
+    class: &'c ClassFile<'a>,
+    name: String,
+    descriptor: String,
+    outputs: Vec<Type>,
+    code: Code<'a>,
+    function: FunctionRef,
+}
+
+///
+/// Function and inputs of every translated method, by class, name and descriptor
+type Functions = HashMap<(String, String, String), (FunctionRef, Vec<Type>)>;
+
+fn translate_classes(classes: &[(&str, ClassFile)], qualify: bool, translation: &mut Translation){
+    let mut mb = ModuleBuilder::new();
+    let mut functions = Functions::new();
+    let mut methods = Vec::new();
+
+    // Functions are declared before any body is translated, so that methods can invoke methods declared after them
+    for (location, class) in classes{
+        let class_name = match class.this_class_name() {
+            Ok(x) => x,
+            Err(err) => {
+                translation.diagnostics.push(Diagnostic::error(format!("invalid class file: {}", err)).at(*location));
+                continue;
+            }
+        };
+
+        for method in class.methods.iter().filter(|x| x.access_flags & ACC_STATIC != 0){
+            let (name, descriptor) = match (class.utf8(method.name_index), class.utf8(method.descriptor_index)) {
+                (Ok(name), Ok(descriptor)) => (name, descriptor),
+                (Err(err), _) | (_, Err(err)) => {
+                    translation.diagnostics.push(Diagnostic::error(format!("invalid class file: {}", err)).at(*location));
+                    continue;
+                }
+            };
+            let location = format!("{}#{}{}", location, name, descriptor);
+
+            let signature = descriptor.parse::<MethodDescriptor>()
+                .map_err(|x| format!("invalid descriptor: {}", x))
+                .and_then(|x| Ok((x.params.iter().map(value_type).collect::<Result<Vec<_>, _>>()?, x.ret.iter().map(value_type).collect::<Result<Vec<_>, _>>()?)));
+            let code = match method.code(class) {
+                Some(Ok(code)) if code.exception_table.is_empty() => Ok(code),
+                Some(Ok(_)) => Err("exception handlers are not supported".to_string()),
+                Some(Err(err)) => Err(err.to_string()),
+                None => Err("the method has no code".to_string()),
+            };
+
+            let ((inputs, outputs), code) = match (signature, code) {
+                (Ok(signature), Ok(code)) => (signature, code),
+                (Err(err), _) | (_, Err(err)) => {
+                    translation.diagnostics.push(Diagnostic::warning(format!("skipping method: {}", err)).at(location));
+                    continue;
+                }
+            };
+
+            let function = mb.new_function();
+            let mut fb = mb.funtion_builder(function);
+            for input in &inputs{
+                fb.add_input(*input);
+            }
+            for output in &outputs{
+                fb.add_output(*output);
+            }
+
+            functions.insert((class_name.to_string(), name.to_string(), descriptor.to_string()), (function, inputs));
+            methods.push(StaticMethod {
+                location,
+                class,
+                name: if qualify { format!("{}.{}", class_name, name) } else { name.to_string() },
+                descriptor: descriptor.to_string(),
+                outputs,
+                code,
+                function
+            });
+        }
+    }
+
+    let mut skipped = HashSet::new();
+    for method in &methods{
+        if let Err(err) = translate_method(&mut mb, method, &functions){
+            translation.diagnostics.push(Diagnostic::warning(format!("skipping method: {}", err)).at(method.location.as_str()));
+            skipped.insert(method.function);
+        }
+    }
+
+    // Methods invoking skipped methods are skipped as well, until no remaining method invokes one
+    let mut module = mb.build();
+    loop {
+        let callers = module.functions.iter()
+            .filter(|x| !skipped.contains(&x.id))
+            .filter_map(|x| x.blocks.iter()
+                .flat_map(|x| &x.instructions)
+                .find_map(|x| match x.operation() {
+                    Operation::Invoke(callee, _) if skipped.contains(callee) => Some(*callee),
+                    _ => None,
+                })
+                .map(|callee| (x.id, callee)))
+            .collect::<Vec<_>>();
+        if callers.is_empty(){
+            break;
+        }
+
+        for (caller, callee) in callers{
+            let caller_ = methods.iter().find(|x| x.function == caller).unwrap();
+            let callee_ = methods.iter().find(|x| x.function == callee).unwrap();
+            translation.diagnostics.push(Diagnostic::warning(format!("skipping method: it invokes {}{}, which was skipped", callee_.name, callee_.descriptor)).at(caller_.location.as_str()));
+            skipped.insert(caller);
+        }
+    }
+    module.functions.retain(|x| !skipped.contains(&x.id));
+
+    // Overloaded methods are named by their descriptor as well
+    for function in &mut module.functions{
+        let method = methods.iter().find(|x| x.function == function.id).unwrap();
+        let overloaded = methods.iter().any(|x| x.name == method.name && x.function != method.function && !skipped.contains(&x.function));
+        function.name = if overloaded { format!("{}{}", method.name, method.descriptor) } else { method.name.clone() };
+    }
+
+    if !module.functions.is_empty(){
+        translation.modules.push(module);
+    }
+}
+
+fn value_type(type_: &FieldType) -> Result<Type, String>{
+    match type_ {
+        FieldType::Base(BaseType::Int) => Ok(Type::I32),
+        FieldType::Base(BaseType::Long) => Ok(Type::I64),
+        FieldType::Base(BaseType::Float) => Ok(Type::F32),
+        FieldType::Base(BaseType::Double) => Ok(Type::F64),
+        x => Err(format!("{} parameters and return values are not supported", x)),
+    }
+}
+
+fn kind_type(kind: Kind) -> Result<Type, String>{
+    match kind {
+        Kind::Int => Ok(Type::I32),
+        Kind::Long => Ok(Type::I64),
+        Kind::Float => Ok(Type::F32),
+        Kind::Double => Ok(Type::F64),
+        Kind::Reference => Err("references are not supported".to_string()),
+    }
+}
+
+///
+/// Longs and doubles take up two local variables and count as two values for stack manipulation
+fn is_wide(type_: Type) -> bool{
+    matches!(type_, Type::I64 | Type::F64)
+}
+
+fn translate_method(mb: &mut ModuleBuilder, method: &StaticMethod, functions: &Functions) -> Result<(), String>{
+    let instructions = bytecode::decode(method.code.bytecode).map_err(|x| x.to_string())?;
+    let indices = instructions.iter().enumerate().map(|(i, (offset, _))| (*offset, i)).collect::<HashMap<_, _>>();
+
+    // Every branch target and instruction following a branch or return starts a basic block
+    let mut leaders = BTreeSet::from([0]);
+    for (i, (_, instruction)) in instructions.iter().enumerate(){
+        match instruction {
+            Instruction::If(_, target) | Instruction::Goto(target) => leaders.insert(*target),
+            Instruction::Return(_) => false,
+            _ => continue,
+        };
+        if let Some((next, _)) = instructions.get(i + 1){
+            leaders.insert(*next);
+        }
+    }
+
+    let mut fb = mb.funtion_builder(method.function);
+    let entry = fb.create_block();
+    let mut translator = MethodTranslator{
+        fb,
+        method,
+        functions,
+        instructions: &instructions,
+        indices: &indices,
+        leaders: &leaders,
+        current: entry,
+        blocks: HashMap::new(),
+        queue: VecDeque::new(),
+        locals: HashMap::new(),
+        stack_locals: HashMap::new(),
+    };
+    translator.translate()
+}
+
+///
+/// Translates the reachable basic blocks of a method. Locals and the operand stack at the start of a block
+/// are kept in local variables of each type, which `PromoteLocals` turns into block inputs
+struct MethodTranslator<'m, 'c, 'a, 'f>{
+    fb: FunctionBuilder<'m>,
+    method: &'f StaticMethod<'c, 'a>,
+    functions: &'f Functions,
+    instructions: &'f [(u32, Instruction)],
+    indices: &'f HashMap<u32, usize>,
+    leaders: &'f BTreeSet<u32>,
+    current: BlockRef,
+
+    ///
+    /// Block and operand stack types at the start of every basic block that is branched to
+    blocks: HashMap<u32, (BlockRef, Vec<Type>)>,
+    queue: VecDeque<u32>,
+    locals: HashMap<(u16, Type), VariableRef>,
+    stack_locals: HashMap<(usize, Type), VariableRef>,
+}
+
+impl<'m, 'c, 'a, 'f> MethodTranslator<'m, 'c, 'a, 'f>{
+    fn translate(&mut self) -> Result<(), String>{
+        self.bb().into_entry_block();
+
+        let params = self.bb().get_params().to_vec();
+        let mut slot = 0;
+        for param in params{
+            let local = self.local(slot, param.type_());
+            self.bb().set_local(local, param);
+            slot += if is_wide(param.type_()) { 2 } else { 1 };
+        }
+        let start = self.target(0, &[])?;
+        self.bb().branch(start, &[]);
+
+        while let Some(offset) = self.queue.pop_front(){
+            self.translate_block(offset)?;
+        }
+        Ok(())
+    }
+
+    fn bb(&mut self) -> BlockBuilder<'_, 'm>{
+        self.fb.block_builder(self.current)
+    }
+
+    fn local(&mut self, slot: u16, type_: Type) -> VariableRef{
+        *self.locals.entry((slot, type_)).or_insert_with(|| self.fb.add_local(type_))
+    }
+
+    fn stack_local(&mut self, depth: usize, type_: Type) -> VariableRef{
+        *self.stack_locals.entry((depth, type_)).or_insert_with(|| self.fb.add_local(type_))
+    }
+
+    ///
+    /// Block of the basic block at `offset`, which is entered with `stack` on the operand stack
+    fn target(&mut self, offset: u32, stack: &[Value]) -> Result<BlockRef, String>{
+        let types = stack.iter().map(|x| x.type_()).collect::<Vec<_>>();
+        match self.blocks.get(&offset) {
+            Some((_, x)) if *x != types => Err(format!("the operand stack at {} is {:?} and {:?} on different paths", offset, x, types)),
+            Some((block, _)) => Ok(*block),
+            None if !self.indices.contains_key(&offset) => Err(format!("branch to {}, which is not an instruction", offset)),
+            None => {
+                let block = self.fb.create_block();
+                self.blocks.insert(offset, (block, types));
+                self.queue.push_back(offset);
+                Ok(block)
+            }
+        }
+    }
+
+    ///
+    /// Store the operand stack before branching to another block
+    fn spill(&mut self, stack: &[Value]){
+        for (depth, value) in stack.iter().enumerate(){
+            let local = self.stack_local(depth, value.type_());
+            self.bb().set_local(local, *value);
+        }
+    }
+
+    ///
+    /// Java defines the smallest integer divided by -1 as itself, where `div` traps on the overflow,
+    /// so a divisor of -1 negates instead. Division by zero still traps rather than throwing
+    fn divide(&mut self, a: Value, b: Value) -> Value{
+        let negate = self.fb.create_block();
+        let divide = self.fb.create_block();
+        let join = self.fb.create_block();
+        let result = self.fb.block_builder(join).add_param(a.type_());
+
+        let mut bb = self.bb();
+        let one = if a.type_() == Type::I32 { bb.const_i32(1) } else { bb.const_i64(1) };
+        let plus_one = bb.add_values(b, one);
+        bb.branch_if(corrosion_base::Condition::Eq, plus_one, (negate, &[]), (divide, &[]));
+
+        let mut bb = self.fb.block_builder(negate);
+        let zero = if a.type_() == Type::I32 { bb.const_i32(0) } else { bb.const_i64(0) };
+        let negated = bb.sub_values(zero, a);
+        bb.branch(join, &[negated]);
+
+        let mut bb = self.fb.block_builder(divide);
+        let quotient = bb.div_values(a, b);
+        bb.branch(join, &[quotient]);
+
+        self.current = join;
+        result
+    }
+
+    fn translate_block(&mut self, offset: u32) -> Result<(), String>{
+        let (block, types) = self.blocks[&offset].clone();
+        self.current = block;
+
+        let mut stack = Vec::with_capacity(types.len());
+        for (depth, type_) in types.into_iter().enumerate(){
+            let local = self.stack_local(depth, type_);
+            stack.push(self.bb().get_local(local));
+        }
+
+        let mut index = self.indices[&offset];
+        loop {
+            let Some((offset, instruction)) = self.instructions.get(index) else {
+                return Err("execution falls off the end of the code".to_string());
+            };
+            let next = self.instructions.get(index + 1).map(|x| x.0);
+
+            if self.translate_instruction(*offset, instruction, next, &mut stack)?{
+                return Ok(());
+            }
+
+            index += 1;
+            if let Some(next) = next.filter(|x| self.leaders.contains(x)){
+                let target = self.target(next, &stack)?;
+                self.spill(&stack);
+                self.bb().branch(target, &[]);
+                return Ok(());
+            }
+        }
+    }
+
+    ///
+    /// Returns whether the instruction ended the block
+    fn translate_instruction(&mut self, offset: u32, instruction: &Instruction, next: Option<u32>, stack: &mut Vec<Value>) -> Result<bool, String>{
+        let pop = |stack: &mut Vec<Value>, type_: Type| -> Result<Value, String>{
+            match stack.pop() {
+                Some(x) if x.type_() == type_ => Ok(x),
+                Some(x) => Err(format!("expected {} on the operand stack at {}, found {}", type_, offset, x.type_())),
+                None => Err(format!("operand stack underflow at {}", offset)),
+            }
+        };
+        let pop_any = |stack: &mut Vec<Value>| stack.pop().ok_or_else(|| format!("operand stack underflow at {}", offset));
+        let narrow = |value: Value| if is_wide(value.type_()) { Err(format!("{} can't be split at {}", value.type_(), offset)) } else { Ok(value) };
+
+        match instruction {
+            Instruction::Nop => {},
+            Instruction::Iconst(x) => stack.push(self.bb().const_i32(*x)),
+            Instruction::Lconst(x) => stack.push(self.bb().const_i64(*x)),
+            Instruction::Fconst(x) => stack.push(self.bb().const_f32(*x)),
+            Instruction::Dconst(x) => stack.push(self.bb().const_f64(*x)),
+            Instruction::Ldc(index) | Instruction::Ldc2(index) => {
+                let value = match self.method.class.constant(*index).map_err(|x| x.to_string())? {
+                    ConstantPoolEntry::IntegerInfo(x) => self.bb().const_i32(*x),
+                    ConstantPoolEntry::FloatInfo(x) => self.bb().const_f32(*x),
+                    ConstantPoolEntry::LongInfo(x) => self.bb().const_i64(*x),
+                    ConstantPoolEntry::DoubleInfo(x) => self.bb().const_f64(*x),
+                    x => return Err(format!("loading a {} constant is not supported", x.kind())),
+                };
+                stack.push(value);
+            },
+            Instruction::Load(kind, slot) => {
+                let local = self.local(*slot, kind_type(*kind)?);
+                stack.push(self.bb().get_local(local));
+            },
+            Instruction::Store(kind, slot) => {
+                let value = pop(stack, kind_type(*kind)?)?;
+                let local = self.local(*slot, value.type_());
+                self.bb().set_local(local, value);
+            },
+            Instruction::Iinc { index, value } => {
+                let local = self.local(*index, Type::I32);
+                let mut bb = self.bb();
+                let old = bb.get_local(local);
+                let value = bb.const_i32(*value as i32);
+                let new = bb.add_values(old, value);
+                bb.set_local(local, new);
+            },
+            Instruction::Pop => {
+                narrow(pop_any(stack)?)?;
+            },
+            Instruction::Pop2 => {
+                if !is_wide(pop_any(stack)?.type_()){
+                    narrow(pop_any(stack)?)?;
+                }
+            },
+            Instruction::Dup => {
+                let value = narrow(*stack.last().ok_or_else(|| format!("operand stack underflow at {}", offset))?)?;
+                stack.push(value);
+            },
+            Instruction::Dup2 => {
+                let top = pop_any(stack)?;
+                if is_wide(top.type_()){
+                    stack.extend([top, top]);
+                }
+                else{
+                    let below = narrow(pop_any(stack)?)?;
+                    stack.extend([below, top, below, top]);
+                }
+            },
+            Instruction::Swap => {
+                let top = narrow(pop_any(stack)?)?;
+                let below = narrow(pop_any(stack)?)?;
+                stack.extend([top, below]);
+            },
+            Instruction::Add(kind) | Instruction::Sub(kind) | Instruction::Mul(kind) | Instruction::Div(kind) | Instruction::Rem(kind) => {
+                let type_ = kind_type(*kind)?;
+                let b = pop(stack, type_)?;
+                let a = pop(stack, type_)?;
+                if matches!(instruction, Instruction::Div(_)) && !type_.is_float(){
+                    stack.push(self.divide(a, b));
+                    return Ok(false);
+                }
+                let mut bb = self.bb();
+                stack.push(match instruction {
+                    Instruction::Add(_) => bb.add_values(a, b),
+                    Instruction::Sub(_) => bb.sub_values(a, b),
+                    Instruction::Mul(_) => bb.mul_values(a, b),
+                    Instruction::Div(_) => bb.div_values(a, b),
+                    _ => bb.modulus_values(a, b),
+                });
+            },
+            Instruction::Neg(kind) => {
+                let value = pop(stack, kind_type(*kind)?)?;
+                let mut bb = self.bb();
+
+                // Floats are negated by multiplying, as subtracting from zero keeps the sign of zero
+                stack.push(match value.type_() {
+                    Type::I32 => { let zero = bb.const_i32(0); bb.sub_values(zero, value) },
+                    Type::I64 => { let zero = bb.const_i64(0); bb.sub_values(zero, value) },
+                    Type::F32 => { let one = bb.const_f32(-1.0); bb.mul_values(value, one) },
+                    _ => { let one = bb.const_f64(-1.0); bb.mul_values(value, one) },
+                });
+            },
+            Instruction::If(condition, target) => {
+                let value = pop(stack, Type::I32)?;
+                let next = next.ok_or_else(|| "execution falls off the end of the code".to_string())?;
+                let then = self.target(*target, stack)?;
+                let else_ = self.target(next, stack)?;
+                let condition = match condition {
+                    bytecode::Condition::Eq => corrosion_base::Condition::Eq,
+                    bytecode::Condition::Ne => corrosion_base::Condition::Ne,
+                    bytecode::Condition::Lt => corrosion_base::Condition::Lt,
+                    bytecode::Condition::Ge => corrosion_base::Condition::Ge,
+                    bytecode::Condition::Gt => corrosion_base::Condition::Gt,
+                    bytecode::Condition::Le => corrosion_base::Condition::Le,
+                };
+                self.spill(stack);
+                self.bb().branch_if(condition, value, (then, &[]), (else_, &[]));
+                return Ok(true);
+            },
+            Instruction::Goto(target) => {
+                let target = self.target(*target, stack)?;
+                self.spill(stack);
+                self.bb().branch(target, &[]);
+                return Ok(true);
+            },
+            Instruction::Return(kind) => {
+                let values = match kind {
+                    Some(kind) => vec![pop(stack, kind_type(*kind)?)?],
+                    None => Vec::new(),
+                };
+                if values.iter().map(|x| x.type_()).ne(self.method.outputs.iter().copied()){
+                    return Err(format!("return at {} does not match the method's descriptor", offset));
+                }
+                self.bb().return_(&values);
+                return Ok(true);
+            },
+            Instruction::InvokeStatic(index) => {
+                let (class, name, descriptor) = self.method.class.resolve_method_ref(*index).map_err(|x| x.to_string())?;
+                let Some((function, inputs)) = self.functions.get(&(class.to_string(), name.to_string(), descriptor.to_string())) else {
+                    return Err(format!("invoking {}.{}{}, which is not translated", class, name, descriptor));
+                };
+
+                let mut args = Vec::with_capacity(inputs.len());
+                for input in inputs.iter().rev(){
+                    args.push(pop(stack, *input)?);
+                }
+                args.reverse();
+                let outputs = self.bb().invoke(*function, &args);
+                stack.extend(outputs);
+            },
+            x => return Err(format!("{:?} at {} is not supported", x, offset)),
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use corrosion_base::{verify_module, Module, Scalar, Severity};
+    use corrosion_interp::{Interpreter, TrapKind};
+
+    use super::*;
+
+    ///
+    /// Builds a class file named `Test` with static methods
+    #[derive(Default)]
+    struct ClassBuilder{
+        pool: Vec<Vec<u8>>,
+        methods: Vec<u8>,
+        method_count: u16,
+    }
+
+    impl ClassBuilder{
+        fn constant(&mut self, bytes: Vec<u8>) -> u16{
+            match self.pool.iter().position(|x| *x == bytes) {
+                Some(i) => i as u16 + 1,
+                None => {
+                    self.pool.push(bytes);
+                    self.pool.len() as u16
+                }
+            }
+        }
+
+        fn utf8(&mut self, text: &str) -> u16{
+            let mut bytes = vec![1];
+            bytes.extend((text.len() as u16).to_be_bytes());
+            bytes.extend(text.as_bytes());
+            self.constant(bytes)
+        }
+
+        fn pair(&mut self, tag: u8, a: u16, b: u16) -> u16{
+            let mut bytes = vec![tag];
+            bytes.extend(a.to_be_bytes());
+            bytes.extend(b.to_be_bytes());
+            self.constant(bytes)
+        }
+
+        fn method_ref(&mut self, name: &str, descriptor: &str) -> u16{
+            let class_name = self.utf8("Test");
+            let mut class = vec![7];
+            class.extend(class_name.to_be_bytes());
+            let class = self.constant(class);
+            let name = self.utf8(name);
+            let descriptor = self.utf8(descriptor);
+            let name_and_type = self.pair(12, name, descriptor);
+            self.pair(10, class, name_and_type)
+        }
+
+        fn method(&mut self, name: &str, descriptor: &str, max_locals: u16, code: &[u8]) -> &mut Self{
+            let name = self.utf8(name);
+            let descriptor = self.utf8(descriptor);
+            let attribute = self.utf8("Code");
+
+            self.methods.extend(0x0009_u16.to_be_bytes());
+            self.methods.extend(name.to_be_bytes());
+            self.methods.extend(descriptor.to_be_bytes());
+            self.methods.extend(1_u16.to_be_bytes());
+            self.methods.extend(attribute.to_be_bytes());
+            self.methods.extend((code.len() as u32 + 12).to_be_bytes());
+            self.methods.extend(8_u16.to_be_bytes());
+            self.methods.extend(max_locals.to_be_bytes());
+            self.methods.extend((code.len() as u32).to_be_bytes());
+            self.methods.extend(code);
+            self.methods.extend([0, 0, 0, 0]);
+            self.method_count += 1;
+            self
+        }
+
+        fn build(&mut self) -> Vec<u8>{
+            let name = self.utf8("Test");
+            let mut class = vec![7];
+            class.extend(name.to_be_bytes());
+            let this_class = self.constant(class);
+
+            let mut bytes = CLASS_MAGIC.to_vec();
+            bytes.extend([0, 0, 0, 52]);
+            bytes.extend((self.pool.len() as u16 + 1).to_be_bytes());
+            bytes.extend(self.pool.concat());
+            bytes.extend(0x0021_u16.to_be_bytes());
+            bytes.extend(this_class.to_be_bytes());
+            bytes.extend([0, 0, 0, 0, 0, 0]);
+            bytes.extend(self.method_count.to_be_bytes());
+            bytes.extend(&self.methods);
+            bytes.extend([0, 0]);
+            bytes
+        }
+    }
+
+    fn translate(class: &mut ClassBuilder) -> Translation{
+        let translation = JavaFrontend.translate(Path::new("Test.class"), &class.build());
+        for module in &translation.modules{
+            verify_module(module).unwrap();
+        }
+        translation
+    }
+
+    fn call(module: &Module, name: &str, args: &[Scalar]) -> Result<Vec<Scalar>, TrapKind>{
+        let mut interpreter = Interpreter::new();
+        interpreter.load_module(module.clone());
+        let function = module.functions.iter().find(|x| x.name == name).unwrap().id;
+        interpreter.call(module.id, function, args).map_err(|x| x.kind)
+    }
+
+    fn int(module: &Module, name: &str, args: &[i32]) -> i32{
+        let args = args.iter().map(|x| Scalar::I32(*x)).collect::<Vec<_>>();
+        match call(module, name, &args).unwrap()[..] {
+            [Scalar::I32(x)] => x,
+            ref x => panic!("expected an int, found {:?}", x),
+        }
+    }
+
+    fn long(module: &Module, name: &str, args: &[i64]) -> i64{
+        let args = args.iter().map(|x| Scalar::I64(*x)).collect::<Vec<_>>();
+        match call(module, name, &args).unwrap()[..] {
+            [Scalar::I64(x)] => x,
+            ref x => panic!("expected a long, found {:?}", x),
+        }
+    }
+
+    #[test]
+    fn operand_stack_is_kept_across_blocks(){
+        // 10 + (x > 0 ? x : -x), the 10 stays on the stack over both branches
+        let translation = translate(ClassBuilder::default().method("f", "(I)I", 1, &[
+            0x10, 10,       // 0: bipush 10
+            0x1a,           // 2: iload_0
+            0x9e, 0, 7,     // 3: ifle 10
+            0x1a,           // 6: iload_0
+            0xa7, 0, 5,     // 7: goto 12
+            0x1a,           // 10: iload_0
+            0x74,           // 11: ineg
+            0x60,           // 12: iadd
+            0xac,           // 13: ireturn
+        ]));
+        assert!(translation.diagnostics.is_empty());
+        let module = &translation.modules[0];
+        assert_eq!(int(module, "f", &[5]), 15);
+        assert_eq!(int(module, "f", &[-3]), 13);
+    }
+
+    #[test]
+    fn iinc(){
+        // Sums n down to 1, adding 1000 for every iteration with a wide iinc
+        let translation = translate(ClassBuilder::default().method("sum", "(I)I", 2, &[
+            0x03,                           // 0: iconst_0
+            0x3c,                           // 1: istore_1
+            0x1a,                           // 2: iload_0
+            0x9e, 0, 19,                    // 3: ifle 22
+            0x1b,                           // 6: iload_1
+            0x1a,                           // 7: iload_0
+            0x60,                           // 8: iadd
+            0x3c,                           // 9: istore_1
+            0x84, 0, 0xff,                  // 10: iinc 0, -1
+            0xc4, 0x84, 0, 1, 0x03, 0xe8,   // 13: wide iinc 1, 1000
+            0xa7, 0xff, 0xef,               // 19: goto 2
+            0x1b,                           // 22: iload_1
+            0xac,                           // 23: ireturn
+        ]));
+        let module = &translation.modules[0];
+        assert_eq!(int(module, "sum", &[4]), 4010);
+        assert_eq!(int(module, "sum", &[0]), 0);
+    }
+
+    #[test]
+    fn wide_values_count_twice_for_dup2_and_pop2(){
+        let translation = translate(ClassBuilder::default()
+            // a * a
+            .method("square", "(J)J", 2, &[0x1e, 0x5c, 0x69, 0xad])
+            // a, popping a pushed 1
+            .method("identity", "(J)J", 2, &[0x1e, 0x0a, 0x58, 0xad])
+            // (a + b) * (a - b)
+            .method("product", "(II)I", 3, &[0x1a, 0x1b, 0x5c, 0x64, 0x3d, 0x60, 0x1c, 0x68, 0xac])
+            // a - b, popping a pushed a and b
+            .method("difference", "(II)I", 2, &[0x1a, 0x1b, 0x1a, 0x1b, 0x58, 0x64, 0xac]));
+        assert!(translation.diagnostics.is_empty());
+        let module = &translation.modules[0];
+        assert_eq!(long(module, "square", &[-3]), 9);
+        assert_eq!(long(module, "identity", &[7]), 7);
+        assert_eq!(int(module, "product", &[5, 3]), 16);
+        assert_eq!(int(module, "difference", &[5, 3]), 2);
+    }
+
+    #[test]
+    fn invokestatic_passes_arguments_in_order(){
+        let mut class = ClassBuilder::default();
+        let [hi, lo] = class.method_ref("sub", "(II)I").to_be_bytes();
+        // sub(b, a), calling a method declared after the caller
+        class.method("call", "(II)I", 2, &[0x1b, 0x1a, 0xb8, hi, lo, 0xac]);
+        class.method("sub", "(II)I", 2, &[0x1a, 0x1b, 0x64, 0xac]);
+        let translation = translate(&mut class);
+        assert!(translation.diagnostics.is_empty());
+        assert_eq!(int(&translation.modules[0], "call", &[10, 3]), -7);
+    }
+
+    #[test]
+    fn callers_of_skipped_methods_are_skipped(){
+        let mut class = ClassBuilder::default();
+        let [bad_hi, bad_lo] = class.method_ref("bad", "()I").to_be_bytes();
+        let [caller_hi, caller_lo] = class.method_ref("caller", "()I").to_be_bytes();
+        class.method("caller2", "()I", 0, &[0xb8, caller_hi, caller_lo, 0xac]);
+        class.method("caller", "()I", 0, &[0xb8, bad_hi, bad_lo, 0xac]);
+        // aconst_null, pop, iconst_0, ireturn
+        class.method("bad", "()I", 0, &[0x01, 0x57, 0x03, 0xac]);
+        class.method("ok", "()I", 0, &[0x04, 0xac]);
+        let translation = translate(&mut class);
+
+        let module = &translation.modules[0];
+        assert_eq!(module.functions.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["ok"]);
+        assert_eq!(int(module, "ok", &[]), 1);
+
+        assert!(translation.diagnostics.iter().all(|x| x.severity == Severity::Warning));
+        let locations = translation.diagnostics.iter().map(|x| x.location.as_deref().unwrap()).collect::<Vec<_>>();
+        assert_eq!(locations, ["Test.class#bad()I", "Test.class#caller()I", "Test.class#caller2()I"]);
+        assert_eq!(translation.diagnostics[1].message, "skipping method: it invokes bad()I, which was skipped");
+        assert_eq!(translation.diagnostics[2].message, "skipping method: it invokes caller()I, which was skipped");
+    }
+
+    #[test]
+    fn division_follows_java_semantics(){
+        let translation = translate(ClassBuilder::default()
+            .method("idiv", "(II)I", 2, &[0x1a, 0x1b, 0x6c, 0xac])
+            .method("ldiv", "(JJ)J", 4, &[0x1e, 0x20, 0x6d, 0xad])
+            .method("irem", "(II)I", 2, &[0x1a, 0x1b, 0x70, 0xac]));
+        let module = &translation.modules[0];
+        assert_eq!(int(module, "idiv", &[i32::MIN, -1]), i32::MIN);
+        assert_eq!(int(module, "idiv", &[7, -1]), -7);
+        assert_eq!(int(module, "idiv", &[-7, 2]), -3);
+        assert_eq!(long(module, "ldiv", &[i64::MIN, -1]), i64::MIN);
+        assert_eq!(long(module, "ldiv", &[7, 2]), 3);
+        assert_eq!(int(module, "irem", &[i32::MIN, -1]), 0);
+        assert_eq!(int(module, "irem", &[-7, 2]), -1);
+        assert_eq!(call(module, "idiv", &[Scalar::I32(1), Scalar::I32(0)]), Err(TrapKind::DivisionByZero));
+    }
+
+    #[test]
+    fn overloaded_methods_are_named_by_descriptor(){
+        let translation = translate(ClassBuilder::default()
+            .method("f", "(I)I", 1, &[0x1a, 0xac])
+            .method("f", "(J)J", 2, &[0x1e, 0xad])
+            .method("g", "()I", 0, &[0x03, 0xac]));
+        let names = translation.modules[0].functions.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["f(I)I", "f(J)J", "g"]);
+    }
+
+    #[test]
+    fn detects_classes_and_jars(){
+        assert!(JavaFrontend.detect(&ClassBuilder::default().build()));
+        assert!(JavaFrontend.detect(b"PK\x03\x04rest of the archive"));
+        assert!(!JavaFrontend.detect(b"PK\x05\x06"));
+        assert!(!JavaFrontend.detect(b"module 0"));
+    }
+}
@@ -1,36 +1,337 @@
-use std::fs;
+use std::{path::{Path, PathBuf}, process::ExitCode};
 
-use corrosion_base::{ModuleBuilder, Type};
-use corrosion_clif::Generator;
+mod repl;
 
-fn main() {
-    let mut mb = ModuleBuilder::new();
-    let f_a = mb.new_function();
-    let mut fb = mb.funtion_builder(f_a);
+use corrosion_base::{select_frontend, verify_module, Backend, Frontend, Module, Scalar, TextFrontend};
+use corrosion_clif::{CpuFeatures, Generator, GeneratorConfig, ObjectCompiler};
+use corrosion_interp::Interpreter;
+use java_corrosion::JavaFrontend;
 
-    fb.add_input(Type::F32);
-    fb.add_input(Type::F32);
-    fb.add_output(Type::F32);
-    let block = fb.create_block();
+const USAGE: &str = "usage: corrosion <command> <file> [options]
+       corrosion repl [options]
 
-    let mut bb = fb.block_builder(block);
-    bb.into_entry_block();
+commands:
+    run <file>      load the file and invoke `main`, or the function given by --invoke
+    check <file>    translate, verify and compile the file without running it
+    dump <file>     print the clif and disassembly of every compiled function
+    compile <file>  compile the file ahead of time into an object file
+    repl            define and call functions interactively
 
-    let [a, b] = *bb.get_params() else {unreachable!("Entry block should have 2 values")};
-    
-    let out = bb.add_values(a, b);
-    bb.return_(&[out]);
+options:
+    --invoke <name> [args...]   function to run, arguments are parsed by the function's inputs
+    --interpret                 run with the interpreter instead of the jit
+    --clif                      dump the clif
+    --asm                       dump the disassembly
+    -o <path>                   object file to write, defaults to the input with an .o extension
+    --target <triple>           target of the object file, defaults to the host
+    --opt-level <level>         none, speed or speed_and_size";
 
-    let module = mb.build();
+fn frontends() -> Vec<Box<dyn Frontend>>{
+    vec![
+        Box::new(TextFrontend),
+        Box::new(JavaFrontend)
+    ]
+}
+
+struct Options{
+    command: String,
+    file: Option<PathBuf>,
+    invoke: Option<(String, Vec<String>)>,
+    interpret: bool,
+    clif: bool,
+    asm: bool,
+    output: Option<PathBuf>,
+    config: GeneratorConfig,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String>{
+    let mut args = args.iter();
+    let command = args.next().ok_or("missing command")?.clone();
+    let mut options = Options{
+        command,
+        file: None,
+        invoke: None,
+        interpret: false,
+        clif: false,
+        asm: false,
+        output: None,
+        config: GeneratorConfig::default()
+    };
+
+    let mut args = args.peekable();
+    while let Some(arg) = args.next(){
+        let mut value = |name: &str| args.next().cloned().ok_or(format!("missing value for {}", name));
+        match arg.as_str() {
+            "--invoke" => {
+                let name = value("--invoke")?;
+                let mut values = Vec::new();
+                while let Some(x) = args.next_if(|x| !x.starts_with("--")){
+                    values.push(x.clone());
+                }
+                options.invoke = Some((name, values));
+            },
+            "--interpret" => options.interpret = true,
+            "--clif" => options.clif = true,
+            "--asm" => options.asm = true,
+            "-o" => options.output = Some(value("-o")?.into()),
+            "--target" => {
+                // Native cpu features can only be detected for the host
+                options.config.target = Some(value("--target")?);
+                options.config.cpu_features = CpuFeatures::Baseline;
+            },
+            "--opt-level" => options.config.opt_level = value("--opt-level")?.parse().map_err(|x| format!("{}", x))?,
+            x if x.starts_with('-') => return Err(format!("unknown option '{}'", x)),
+            x if options.file.is_none() => options.file = Some(PathBuf::from(x)),
+            x => return Err(format!("unexpected argument '{}'", x)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.is_empty() || args[0] == "help" || args[0] == "--help"{
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let options = match parse_options(&args) {
+        Ok(x) => x,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match (options.command.as_str(), &options.file) {
+        ("repl", None) => repl::Repl::new(options.config.clone())
+            .and_then(|mut x| x.run(std::io::stdin().lock(), std::io::stdout()).map_err(|x| x.to_string())),
+        ("run" | "check" | "dump" | "compile", Some(file)) => {
+            let Some(modules) = translate_file(file) else {
+                return ExitCode::FAILURE;
+            };
+            match options.command.as_str() {
+                "run" => run(&options, modules),
+                "check" => check(file, &options, modules),
+                "dump" => dump(&options, modules),
+                _ => compile(file, &options, modules),
+            }
+        },
+        ("repl", Some(file)) => Err(format!("unexpected argument '{}'", file.display())),
+        ("run" | "check" | "dump" | "compile", None) => Err(format!("missing input file\n\n{}", USAGE)),
+        (x, _) => Err(format!("unknown command '{}'\n\n{}", x, USAGE)),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+///
+/// Translate a file with the matching frontend and verify the modules, printing any diagnostics
+fn translate_file(path: &Path) -> Option<Vec<Module>>{
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("error: failed to read {}: {}", path.display(), err);
+            return None;
+        }
+    };
+
+    let frontends = frontends();
+    let Some(frontend) = select_frontend(&frontends, path, &bytes) else {
+        eprintln!("error: no frontend for {}", path.display());
+        return None;
+    };
+
+    let translation = frontend.translate(path, &bytes);
+    for diagnostic in &translation.diagnostics{
+        eprintln!("{}", diagnostic);
+    }
+    if translation.has_errors(){
+        return None;
+    }
+
+    let mut valid = true;
+    for module in &translation.modules{
+        if let Err(errors) = verify_module(module){
+            for err in errors{
+                eprintln!("error: {}: {}", path.display(), err);
+            }
+            valid = false;
+        }
+    }
+
+    valid.then_some(translation.modules)
+}
+
+fn run(options: &Options, modules: Vec<Module>) -> Result<(), String>{
+    let mut interpreter;
+    let mut generator;
+    let backend: &mut dyn Backend = if options.interpret{
+        interpreter = Interpreter::new();
+        &mut interpreter
+    }
+    else{
+        generator = Generator::with_config(options.config.clone()).map_err(|x| x.to_string())?;
+        &mut generator
+    };
+
+    let (name, args) = match &options.invoke {
+        Some((name, args)) => (name.as_str(), &args[..]),
+        None => ("main", &[][..]),
+    };
+
+    let ids = modules.iter().map(|x| x.id).collect::<Vec<_>>();
+    for module in modules{
+        backend.load_module(module).map_err(|x| x.to_string())?;
+    }
+
+    let entry = ids.iter().find_map(|x| backend.resolve_function(*x, name).ok().map(|f| (*x, f)));
+    let Some((module, function)) = entry else {
+        // Running a file without a main function only loads it
+        if options.invoke.is_none(){
+            return Ok(());
+        }
+        return Err(format!("no function named '{}'", name));
+    };
+
+    let signature = backend.signature(module, function).map_err(|x| x.to_string())?;
+    if args.len() != signature.inputs.len(){
+        return Err(format!("'{}' expects {} arguments {:?}, found {}", name, signature.inputs.len(), signature.inputs, args.len()));
+    }
+    let args = signature.inputs.iter().zip(args)
+        .map(|(type_, arg)| Scalar::parse(*type_, arg))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let outputs = backend.invoke(module, function, &args).map_err(|x| x.to_string())?;
+    for output in outputs{
+        println!("{}", output);
+    }
+
+    Ok(())
+}
+
+fn check(file: &Path, options: &Options, modules: Vec<Module>) -> Result<(), String>{
+    let mut config = options.config.clone();
+    config.verifier = true;
+
+    let mut generator = Generator::with_config(config).map_err(|x| x.to_string())?;
+    for module in modules{
+        generator.load_module(module).map_err(|x| x.to_string())?;
+    }
+
+    println!("{}: ok", file.display());
+    Ok(())
+}
+
+fn dump(options: &Options, modules: Vec<Module>) -> Result<(), String>{
+    let (clif, asm) = match (options.clif, options.asm) {
+        (false, false) => (true, true),
+        x => x,
+    };
+
+    let mut generator = Generator::with_config(options.config.clone()).map_err(|x| x.to_string())?;
+    generator.set_capture_dumps(true);
+
+    let functions = modules.iter()
+        .flat_map(|m| m.functions.iter().map(move |f| (m.id, f.id, f.name.clone())))
+        .collect::<Vec<_>>();
+    for module in modules{
+        generator.load_module(module).map_err(|x| x.to_string())?;
+    }
+
+    for (module, function, name) in functions{
+        let Some(dump) = generator.dump(module, function) else { continue };
+        println!("; function {} {:?}", function, name);
+        if clif{
+            println!("{}", dump.optimized_clif);
+        }
+        if asm{
+            println!("{}", dump.disassembly);
+        }
+    }
+
+    Ok(())
+}
+
+fn compile(file: &Path, options: &Options, modules: Vec<Module>) -> Result<(), String>{
+    let output = options.output.clone().unwrap_or_else(|| file.with_extension("o"));
+    let name = file.file_stem().and_then(|x| x.to_str()).unwrap_or("corrosion");
+
+    let mut compiler = ObjectCompiler::new(&options.config, name).map_err(|x| x.to_string())?;
+    for module in &modules{
+        compiler.add_module(module).map_err(|x| x.to_string())?;
+    }
+
+    let bytes = compiler.finish().map_err(|x| x.to_string())?;
+    std::fs::write(&output, bytes).map_err(|x| format!("failed to write {}: {}", output.display(), x))
+}
+
+#[cfg(test)]
+mod tests{
+    use corrosion_clif::OptLevel;
+
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String>{
+        parse_options(&args.split_whitespace().map(|x| x.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn command_and_file(){
+        let options = parse("run examples/add.cor").unwrap();
+        assert_eq!(options.command, "run");
+        assert_eq!(options.file, Some(PathBuf::from("examples/add.cor")));
+        assert_eq!(options.invoke, None);
+        assert!(!options.interpret && !options.clif && !options.asm);
+        assert_eq!(options.output, None);
+        assert_eq!(options.config.opt_level, OptLevel::default());
+    }
+
+    #[test]
+    fn invoke_takes_arguments_until_the_next_option(){
+        let options = parse("run --invoke add 1 -2 --interpret file.cor").unwrap();
+        assert_eq!(options.invoke, Some(("add".to_string(), vec!["1".to_string(), "-2".to_string()])));
+        assert!(options.interpret);
+        assert_eq!(options.file, Some(PathBuf::from("file.cor")));
+
+        let options = parse("run file.cor --invoke main").unwrap();
+        assert_eq!(options.invoke, Some(("main".to_string(), vec![])));
+    }
 
-    let mid = module.id;
-    let fid = module.functions[0].id;
-    
-    let mut generator = Generator::new();
+    #[test]
+    fn compile_options(){
+        let options = parse("compile file.cor -o out.o --target x86_64-unknown-linux-gnu --opt-level speed_and_size").unwrap();
+        assert_eq!(options.output, Some(PathBuf::from("out.o")));
+        assert_eq!(options.config.target.as_deref(), Some("x86_64-unknown-linux-gnu"));
+        assert_eq!(options.config.cpu_features, CpuFeatures::Baseline);
+        assert_eq!(options.config.opt_level, OptLevel::SpeedAndSize);
 
-    generator.load_module(module);
+        let options = parse("dump --clif --asm file.cor").unwrap();
+        assert!(options.clif && options.asm);
+    }
 
-    let ptr_b = unsafe { std::mem::transmute::<_, extern "C" fn(f32, f32) -> f32>(generator.get_function(mid, fid)) };
+    #[test]
+    fn file_is_optional_for_the_repl(){
+        let options = parse("repl --interpret").unwrap();
+        assert_eq!(options.command, "repl");
+        assert_eq!(options.file, None);
+        assert!(options.interpret);
+    }
 
-    println!("{}",ptr_b(5.0, 8.0));
+    #[test]
+    fn invalid_options(){
+        assert_eq!(parse("").err().unwrap(), "missing command");
+        assert_eq!(parse("run a.cor b.cor").err().unwrap(), "unexpected argument 'b.cor'");
+        assert_eq!(parse("run a.cor --frob").err().unwrap(), "unknown option '--frob'");
+        assert_eq!(parse("compile a.cor -o").err().unwrap(), "missing value for -o");
+        assert_eq!(parse("run --invoke").err().unwrap(), "missing value for --invoke");
+        assert!(parse("compile a.cor --opt-level fast").is_err());
+    }
 }
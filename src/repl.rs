@@ -0,0 +1,185 @@
+use std::io::{BufRead, Write};
+
+use corrosion_base::{open_braces, parse_into, verify_module, Backend, FunctionRef, Module, ModuleBuilder, Scalar};
+use corrosion_clif::{Generator, GeneratorConfig};
+
+const HELP: &str = "enter function definitions in the textual ir, or call a function by name or id:
+
+    function fn1 \"add\"(i32, i32) -> (i32) {
+    block1(%1: i32, %2: i32):
+        %3 = add.i32 %1, %2
+        return %3
+    }
+    add(5, 8)
+    fn1(5, 8)
+
+redefining a function replaces its code for every caller, its signature and name can't change
+
+commands:
+    :list   print the functions defined so far
+    :help   print this message
+    :quit   exit the repl";
+
+///
+/// Interactive session, functions are compiled incrementally into a single module of a long lived generator
+pub struct Repl{
+    generator: Generator,
+    module: Module,
+}
+
+enum Flow{
+    Continue,
+    Quit,
+}
+
+impl Repl{
+    pub fn new(config: GeneratorConfig) -> Result<Self, String>{
+        Ok(Self{
+            generator: Generator::with_config(config).map_err(|x| x.to_string())?,
+            module: ModuleBuilder::new().build()
+        })
+    }
+
+    ///
+    /// Read inputs until the end of `input`, definitions may span multiple lines while braces are open
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> std::io::Result<()>{
+        let mut lines = input.lines();
+        let mut source = String::new();
+        loop {
+            write!(output, "{}", if source.is_empty() { ">> " } else { ".. " })?;
+            output.flush()?;
+
+            let Some(line) = lines.next() else { break };
+            source.push_str(&line?);
+            source.push('\n');
+
+            // Input that doesn't lex is complete, evaluating it reports the error
+            if open_braces(&source).unwrap_or(0) > 0{
+                continue;
+            }
+
+            let input = std::mem::take(&mut source);
+            match self.eval(input.trim(), &mut output) {
+                Ok(Flow::Continue) => {},
+                Ok(Flow::Quit) => break,
+                Err(err) => writeln!(output, "error: {}", err)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, input: &str, output: &mut impl Write) -> Result<Flow, String>{
+        let io = |x: std::io::Error| x.to_string();
+        match input {
+            "" => {},
+            ":quit" | ":q" => return Ok(Flow::Quit),
+            ":help" => writeln!(output, "{}", HELP).map_err(io)?,
+            ":list" => write!(output, "{}", self.module).map_err(io)?,
+            _ if input.starts_with(':') => return Err(format!("unknown command '{}', try :help", input)),
+            _ if input.starts_with("function") || input.starts_with("export") => {
+                for function in self.define(input)?{
+                    writeln!(output, "defined {}", function).map_err(io)?;
+                }
+            },
+            _ => {
+                let results = self.call(input)?;
+                let results = results.iter().map(|x| format!("{}: {}", x, x.type_())).collect::<Vec<_>>();
+                writeln!(output, "{}", results.join(", ")).map_err(io)?;
+            }
+        }
+        Ok(Flow::Continue)
+    }
+
+    ///
+    /// Add definitions to the module and compile the functions they define, the module is unchanged on errors
+    fn define(&mut self, input: &str) -> Result<Vec<FunctionRef>, String>{
+        let mut module = self.module.clone();
+        let defined = parse_into(&mut module, input).map_err(|x| x.to_string())?;
+
+        if let Err(errors) = verify_module(&module){
+            return Err(errors.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("\nerror: "));
+        }
+
+        let mut update = Module::new(module.id);
+        update.functions = module.functions.iter().filter(|x| defined.contains(&x.id)).cloned().collect();
+        self.generator.reload_module(update).map_err(|x| x.to_string())?;
+
+        self.module = module;
+        Ok(defined)
+    }
+
+    ///
+    /// Evaluate a call such as `add(5, 8)`, the arguments are parsed by the function's inputs
+    fn call(&mut self, input: &str) -> Result<Vec<Scalar>, String>{
+        let Some((name, args)) = input.strip_suffix(')').and_then(|x| x.split_once('(')) else {
+            return Err(format!("expected a definition or a call, found '{}'", input));
+        };
+        let name = name.trim();
+
+        let function = match self.module.functions.iter().find(|x| x.id.to_string() == name) {
+            Some(x) => x.id,
+            None => self.generator.resolve_function(self.module.id, name).map_err(|x| x.to_string())?,
+        };
+
+        let signature = self.generator.signature(self.module.id, function).map_err(|x| x.to_string())?;
+        let args = args.split(',').map(str::trim).filter(|x| !x.is_empty()).collect::<Vec<_>>();
+        if args.len() != signature.inputs.len(){
+            return Err(format!("{} expects {} arguments, found {}", name, signature.inputs.len(), args.len()));
+        }
+        let args = signature.inputs.iter().zip(args)
+            .map(|(type_, arg)| Scalar::parse(*type_, arg))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.generator.invoke(self.module.id, function, &args).map_err(|x| x.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn session(input: &str) -> String{
+        let mut repl = Repl::new(GeneratorConfig::default()).unwrap();
+        let mut output = Vec::new();
+        repl.run(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    const ADD: &str = "function fn1 \"add\"(i32, i32) -> (i32) {
+block1(%1: i32, %2: i32):
+    %3 = add.i32 %1, %2
+    return %3
+}
+";
+
+    #[test]
+    fn definitions_continue_while_braces_are_open(){
+        let output = session(&format!("{}add(5, 8)\nfn1(1, 2)\n", ADD));
+        assert_eq!(output, ">> .. .. .. .. defined fn1\n>> 13: i32\n>> 3: i32\n>> ");
+    }
+
+    #[test]
+    fn braces_in_strings_and_comments_are_ignored(){
+        let output = session("function fn1 \"{\"() -> (i32) { ; }\nblock1:\n    %1 = const.i32 7 ; {\n    return %1\n}\nfn1()\n");
+        assert_eq!(output, ">> .. .. .. .. defined fn1\n>> 7: i32\n>> ");
+    }
+
+    #[test]
+    fn errors_leave_the_session_usable(){
+        let output = session(&format!("{}add(1)\n:nope\nsub(1, 2)\nfunction fn2() -> (i32) {{\nblock1:\n    return %5\n}}\n:list\n", ADD));
+        assert_eq!(output, format!("\
+>> .. .. .. .. defined fn1
+>> error: add expects 2 arguments, found 1
+>> error: unknown command ':nope', try :help
+>> error: no function named 'sub' in ModuleRef(0)
+>> .. .. .. error: fn2 block1 instruction 0: %5 is not defined
+>> module 0
+
+{}>> ", ADD));
+    }
+
+    #[test]
+    fn quit_stops_reading(){
+        assert_eq!(session(&format!("{}:quit\nadd(1, 1)\n", ADD)), ">> .. .. .. .. defined fn1\n>> ");
+    }
+}